@@ -1,12 +1,13 @@
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock, Mutex};
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
-use std::thread;
 use std::fs::{File, OpenOptions};
 use std::io::{Write};
 use std::hash::Hash;
 use std::fmt::Debug;
 
+use crate::task_scheduler::{SchedulerError, TaskScheduler};
+
 /// Cache entry with value and expiration time
 #[derive(Debug, Clone)]
 struct CacheEntry<V> {
@@ -48,9 +49,7 @@ where
     /// Create a new concurrent cache with specified TTL and backing store path
     pub fn new(backing_store_path: String, default_ttl: Duration) -> Self {
         // Ensure backing store file exists
-        if let Ok(_) = File::create(&backing_store_path) {
-            // File created or already exists
-        }
+        let _ = File::create(&backing_store_path);
 
         Self {
             cache: Arc::new(RwLock::new(HashMap::new())),
@@ -69,10 +68,10 @@ where
         // First, try to read from cache
         {
             let cache_read = self.cache.read().map_err(|e| format!("Cache read lock error: {}", e))?;
-            if let Some(entry) = cache_read.get(key) {
-                if !entry.is_expired() {
-                    return Ok(entry.value.clone());
-                }
+            if let Some(entry) = cache_read.get(key)
+                && !entry.is_expired()
+            {
+                return Ok(entry.value.clone());
             }
         }
 
@@ -89,10 +88,10 @@ where
         // Double-check: another thread might have computed it while we were waiting
         {
             let cache_read = self.cache.read().map_err(|e| format!("Cache read lock error: {}", e))?;
-            if let Some(entry) = cache_read.get(key) {
-                if !entry.is_expired() {
-                    return Ok(entry.value.clone());
-                }
+            if let Some(entry) = cache_read.get(key)
+                && !entry.is_expired()
+            {
+                return Ok(entry.value.clone());
             }
         }
 
@@ -141,18 +140,16 @@ where
         Ok(())
     }
 
-    /// Start the garbage collector thread
-    pub fn start_garbage_collector(&self, gc_interval: Duration) -> thread::JoinHandle<()> {
+    /// Run garbage collection every `gc_interval` as a periodic task on `scheduler`
+    ///
+    /// Returns the task id; pass it to `TaskScheduler::cancel` to stop collecting.
+    pub fn schedule_garbage_collector(&self, scheduler: &TaskScheduler, gc_interval: Duration) -> Result<u64, SchedulerError> {
         let cache_clone = Arc::clone(&self.cache);
-        
-        thread::spawn(move || {
-            loop {
-                thread::sleep(gc_interval);
-                
-                if let Ok(mut cache_write) = cache_clone.write() {
-                    cache_write.retain(|_, entry| !entry.is_expired());
-                    println!("Garbage collection completed. Cache size: {}", cache_write.len());
-                }
+
+        scheduler.schedule_with_fixed_delay(gc_interval, move || {
+            if let Ok(mut cache_write) = cache_clone.write() {
+                cache_write.retain(|_, entry| !entry.is_expired());
+                println!("Garbage collection completed. Cache size: {}", cache_write.len());
             }
         })
     }
@@ -175,6 +172,7 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::thread;
     use std::sync::atomic::{AtomicUsize, Ordering};
 
     #[test]
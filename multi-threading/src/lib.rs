@@ -0,0 +1,8 @@
+// Modules shared by the experiments binary, its benchmarks and the tests
+pub mod benchmark;
+pub mod concurrent_cache;
+pub mod original_experiments;
+pub mod scheduler_benchmark;
+pub mod task_scheduler;
+pub mod timeout_test;
+pub mod work_stealing_demo;
@@ -1,42 +1,16 @@
 // Concurrent In-Memory Cache with Expiration and Write-Through
 // Clean main file focused on cache functionality
-mod lookAtThisClass;
-mod task_scheduler;
-mod timeout_test;
-mod work_stealing_demo;
-
-use std::thread;
-use std::time::Duration;
-use rand::random;
-use std::fs::File;
-use std::io::prelude::*;
-use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
-
-use task_scheduler::{TaskScheduler, SchedulerConfig};
-use timeout_test::test_timeout_detection;
-use work_stealing_demo::demonstrate_work_stealing;
-
-// Big Chungus Devs inc
-fn multi_threaded_fizz_buzz(){
-    let handle = thread::spawn(|| {
-        for i in 1..10 {
-            if i % 2 == 0 { println!("fizz");}else { println!("buzz"); }
-            thread::sleep(Duration::from_millis(1));
-        }
-    });
-
-mod concurrent_cache;
-mod benchmark;
-mod original_experiments;
-
-use concurrent_cache::ConcurrentCache;
-use benchmark::run_cache_benchmark;
-use original_experiments::run_original_experiments;
+use multi_threading::benchmark::run_cache_benchmark;
+use multi_threading::concurrent_cache::ConcurrentCache;
+use multi_threading::original_experiments::{mntd, run_original_experiments, write_vec_to_file};
+use multi_threading::scheduler_benchmark::run_scheduler_benchmark;
 use std::thread;
 use std::time::Duration;
 use std::sync::Arc;
 
-
+use multi_threading::task_scheduler::{Priority, TaskScheduler, SchedulerConfig};
+use multi_threading::timeout_test::test_timeout_detection;
+use multi_threading::work_stealing_demo::demonstrate_work_stealing;
 
 fn cache_demonstration() {
     println!("=== Concurrent Cache with Expiration and Write-Through Demo ===");
@@ -47,8 +21,15 @@ fn cache_demonstration() {
         Duration::from_secs(5)
     ));
     
-    // Start garbage collector thread
-    let _gc_handle = cache.start_garbage_collector(Duration::from_secs(3));
+    // Collect garbage as a periodic task on a one-worker scheduler
+    let mut gc_scheduler = TaskScheduler::new(SchedulerConfig {
+        num_workers: 1,
+        ..SchedulerConfig::default()
+    });
+    gc_scheduler.start();
+    cache
+        .schedule_garbage_collector(&gc_scheduler, Duration::from_secs(3))
+        .expect("scheduler is running");
     
     println!("Cache created with 5-second TTL and garbage collection every 3 seconds");
     
@@ -131,6 +112,7 @@ fn cache_demonstration() {
     }
     
     println!("📊 Final cache size: {}", cache.size());
+    gc_scheduler.shutdown();
     println!("\n✅ Cache demonstration completed!");
     println!("💾 Check 'cache_backing_store.log' for write-through persistence");
 }
@@ -150,9 +132,15 @@ fn main() {
             "benchmark" => {
                 run_cache_benchmark();
             },
+            "scheduler-benchmark" => {
+                run_scheduler_benchmark();
+            },
             "original" => {
                 run_original_experiments();
             },
+            "scheduler" => {
+                run_scheduler_demos();
+            },
             "all" => {
                 cache_demonstration();
                 println!("\n{}", "=".repeat(50));
@@ -177,9 +165,14 @@ fn print_usage() {
     println!("Options:");
     println!("  cache     - Run cache demonstration only");
     println!("  benchmark - Run cache benchmarks only");
+    println!("  scheduler-benchmark - Compare task scheduler queue designs");
     println!("  original  - Run original threading experiments");
     println!("  all       - Run everything");
+    println!("  scheduler - Run task scheduler demonstrations");
     println!("  (no args) - Run cache demo and benchmarks (default)");
+}
+
+fn run_scheduler_demos() {
     println!("=== Rust Multi-threading Experiments ===\n");
 
     // Run the original examples
@@ -210,72 +203,71 @@ fn demonstrate_task_scheduler() {
         num_workers: 4,
         timeout_seconds: 2,
         enable_work_stealing: true,
+        execution_timeout_ms: 2000,
+        ..SchedulerConfig::default()
     };
 
-    println!("   Creating scheduler with {} workers, {}s timeout, work stealing: {}",
-             config.num_workers, config.timeout_seconds, config.enable_work_stealing);
+    println!("   Creating scheduler with {} workers, {}s timeout, {}ms execution timeout, work stealing: {}",
+             config.num_workers, config.timeout_seconds, config.execution_timeout_ms, config.enable_work_stealing);
 
     let mut scheduler = TaskScheduler::new(config);
     scheduler.start();
 
-    // Shared counter to demonstrate task execution
-    let task_counter = Arc::new(AtomicUsize::new(0));
     let start_time = std::time::Instant::now();
 
     println!("   Submitting 20 computational tasks...");
 
-    // Submit multiple computational tasks
+    // Submit multiple computational tasks into a group that collects their results
+    let computations = scheduler.group::<u64>();
     for i in 0..20 {
-        let counter = Arc::clone(&task_counter);
         let task_id = i;
         
-        scheduler.submit(move || {
+        computations.submit(move || {
             // Simulate some computational work
             let mut sum = 0u64;
             for j in 0..1000000 {
                 sum += j as u64;
             }
             
-            counter.fetch_add(1, Ordering::SeqCst);
             println!("     Task {} completed (sum: {})", task_id, sum);
+            sum
         }).unwrap();
     }
 
-    // Submit some I/O-bound tasks
-    println!("   Submitting 5 I/O-bound tasks...");
+    // Submit some latency-sensitive I/O-bound tasks ahead of the bulk work
+    println!("   Submitting 5 high-priority I/O-bound tasks...");
+    let background = scheduler.group::<()>();
     for i in 0..5 {
-        let counter = Arc::clone(&task_counter);
         let task_id = 20 + i;
         
-        scheduler.submit(move || {
+        background.submit_with_priority(Priority::HIGH, move || {
             // Simulate I/O work
             thread::sleep(Duration::from_millis(100));
-            counter.fetch_add(1, Ordering::SeqCst);
             println!("     I/O Task {} completed", task_id);
         }).unwrap();
     }
 
     // Submit a task that takes longer to demonstrate timeout detection
     println!("   Submitting a slow task to test timeout detection...");
-    let counter = Arc::clone(&task_counter);
-    scheduler.submit(move || {
+    background.submit(move || {
         println!("     Slow task starting...");
         thread::sleep(Duration::from_secs(3)); // This should trigger timeout warning
-        counter.fetch_add(1, Ordering::SeqCst);
         println!("     Slow task completed");
     }).unwrap();
 
-    // Wait for most tasks to complete
+    // Wait for each group, collecting the computational results
     println!("   Waiting for tasks to complete...");
-    while task_counter.load(Ordering::SeqCst) < 25 {
-        thread::sleep(Duration::from_millis(50));
-    }
+    let total: u64 = computations.wait_all().into_result().unwrap().into_iter().sum();
+    println!("   Computational tasks produced a combined sum of {}", total);
+
+    let background_results = background.wait_all();
+    assert!(background_results.is_ok());
 
     let elapsed = start_time.elapsed();
     println!("   All 26 tasks completed in {:?}", elapsed);
 
     // Demonstrate clean shutdown
     println!("   Shutting down scheduler...");
-    scheduler.shutdown();
-    println!("   Scheduler shut down cleanly");
+    let report = scheduler.shutdown_drain();
+    println!("   Scheduler shut down cleanly ({})", report);
 }
\ No newline at end of file
@@ -7,6 +7,8 @@ use rand::random;
 use std::fs::File;
 use std::io::prelude::*;
 
+use crate::task_scheduler::{SchedulerConfig, TaskScheduler};
+
 // Big Chungus Devs inc
 pub fn multi_threaded_fizz_buzz() {
     let handle = thread::spawn(|| {
@@ -37,9 +39,9 @@ pub fn chads1() {
     });
     let mut chad = handle.join().unwrap();
     let handle2 = thread::spawn(move || {
-        for j in 0..chad.len() { 
-            chad[j as usize] = random() 
-        } 
+        for value in chad.iter_mut() {
+            *value = random();
+        }
         chad 
     });
     let chad = handle2.join().unwrap();
@@ -78,11 +80,22 @@ pub fn mntd(length: u128) -> Vec<u32> {
     result
 }
 
+/// Sum of four six-sided dice numbered 0 to 5
+fn roll_four_dice() -> u32 {
+    (0..4).map(|_| (random::<u8>() % 6) as u32).sum()
+}
+
+/// Same distribution as `mntd`, with the rolls spread over the scheduler's workers
+pub fn par_mntd(scheduler: &TaskScheduler, length: usize) -> Vec<u32> {
+    // A Vec of `()` only records its length, so nothing is allocated per roll
+    scheduler.par_map(&vec![(); length], |()| roll_four_dice() + roll_four_dice())
+}
+
 pub fn write_vec_to_file(vec: Vec<u32>) -> std::io::Result<()> {
     let mut file = File::create("Vector.txt")?;
     for i in vec {
         let temp = i.to_string() + ",";
-        file.write(temp.as_bytes())?;
+        file.write_all(temp.as_bytes())?;
     }
     Ok(())
 }
@@ -102,6 +115,14 @@ pub fn run_original_experiments() {
         Ok(()) => println!("✅ Vector written to Vector.txt successfully"),
         Err(e) => println!("❌ Error writing vector to file: {}", e),
     }
+
+    println!("\n4. Parallel MNTD on the task scheduler:");
+    let mut scheduler = TaskScheduler::new(SchedulerConfig::default());
+    scheduler.start();
+    let started = std::time::Instant::now();
+    let output = par_mntd(&scheduler, 1_000_000);
+    println!("Generated {} values in {:?}", output.len(), started.elapsed());
+    scheduler.shutdown();
     
     println!("\n✅ Original experiments completed!");
 }
\ No newline at end of file
@@ -0,0 +1,344 @@
+use crate::task_scheduler::deque::{self, Steal};
+use crate::task_scheduler::{
+    KeyAffinity, LeastLoaded, PlacementStrategy, PowerOfTwoChoices, RandomVictim, RoundRobin, SchedulerConfig,
+    StealHalf, StealOne, StealStrategy, TaskScheduler,
+};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, atomic::{AtomicBool, AtomicUsize, Ordering}};
+use std::time::{Duration, Instant};
+use std::thread;
+
+mod mutex_scheduler;
+
+use mutex_scheduler::MutexScheduler;
+
+const DEQUE_ITEMS: usize = 1_000_000;
+const DEQUE_THIEVES: usize = 3;
+const SCHEDULER_TASKS: usize = 200_000;
+const WORKER_COUNTS: [usize; 4] = [1, 2, 4, 8];
+const SKEWED_TASKS: usize = 20_000;
+const SKEWED_WORKERS: usize = 4;
+/// Iterations of busy work in a typical task of the skewed workloads
+const SHORT_SPIN: usize = 200;
+
+/// Owner end of a work-stealing queue, so both designs run the same workload
+trait QueueOwner: Send + 'static {
+    fn push(&self, item: usize);
+    fn pop(&self) -> Option<usize>;
+}
+
+/// Thief end of a work-stealing queue
+trait QueueThief: Clone + Send + 'static {
+    fn steal(&self) -> Option<usize>;
+}
+
+/// The previous design: one `Mutex<VecDeque>` per worker
+type MutexQueue = Arc<Mutex<VecDeque<usize>>>;
+
+impl QueueOwner for MutexQueue {
+    fn push(&self, item: usize) {
+        self.lock().unwrap().push_back(item);
+    }
+
+    fn pop(&self) -> Option<usize> {
+        self.lock().unwrap().pop_front()
+    }
+}
+
+impl QueueThief for MutexQueue {
+    fn steal(&self) -> Option<usize> {
+        self.lock().unwrap().pop_back()
+    }
+}
+
+impl QueueOwner for deque::Worker<usize> {
+    fn push(&self, item: usize) {
+        deque::Worker::push(self, item);
+    }
+
+    fn pop(&self) -> Option<usize> {
+        deque::Worker::pop(self)
+    }
+}
+
+impl QueueThief for deque::Stealer<usize> {
+    fn steal(&self) -> Option<usize> {
+        loop {
+            match deque::Stealer::steal(self) {
+                Steal::Success(item) => return Some(item),
+                Steal::Empty => return None,
+                Steal::Retry => {}
+            }
+        }
+    }
+}
+
+/// Owner pushes in bursts of 64 and pops half of each burst back while
+/// thieves steal the rest; returns the time until every item was taken
+fn bench_queue(owner: impl QueueOwner, thief: impl QueueThief) -> Duration {
+    let taken = Arc::new(AtomicUsize::new(0));
+    let done = Arc::new(AtomicBool::new(false));
+    let start = Instant::now();
+
+    let thieves: Vec<_> = (0..DEQUE_THIEVES)
+        .map(|_| {
+            let thief = thief.clone();
+            let taken = Arc::clone(&taken);
+            let done = Arc::clone(&done);
+            thread::spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    match thief.steal() {
+                        Some(_) => {
+                            taken.fetch_add(1, Ordering::Relaxed);
+                        }
+                        None => thread::yield_now(),
+                    }
+                }
+            })
+        })
+        .collect();
+
+    let owner_taken = Arc::clone(&taken);
+    thread::spawn(move || {
+        for burst in (0..DEQUE_ITEMS).step_by(64) {
+            for item in burst..(burst + 64).min(DEQUE_ITEMS) {
+                owner.push(item);
+            }
+            for _ in 0..32 {
+                if owner.pop().is_some() {
+                    owner_taken.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+        while owner.pop().is_some() {
+            owner_taken.fetch_add(1, Ordering::Relaxed);
+        }
+    })
+    .join()
+    .unwrap();
+
+    while taken.load(Ordering::Relaxed) < DEQUE_ITEMS {
+        thread::yield_now();
+    }
+    let elapsed = start.elapsed();
+
+    done.store(true, Ordering::Relaxed);
+    for thief in thieves {
+        thief.join().unwrap();
+    }
+    elapsed
+}
+
+/// A scheduler design the end-to-end benchmarks can run, so both get the same workload
+trait BenchScheduler: Send + Sync + Sized + 'static {
+    fn started(num_workers: usize) -> Self;
+    fn spawn(&self, task: impl FnOnce() + Send + 'static);
+    fn drain(self);
+}
+
+impl BenchScheduler for TaskScheduler {
+    fn started(num_workers: usize) -> Self {
+        let mut scheduler = TaskScheduler::new(benchmark_config(num_workers));
+        scheduler.start();
+        scheduler
+    }
+
+    fn spawn(&self, task: impl FnOnce() + Send + 'static) {
+        self.submit(task).unwrap();
+    }
+
+    fn drain(self) {
+        self.shutdown_drain();
+    }
+}
+
+impl BenchScheduler for MutexScheduler {
+    fn started(num_workers: usize) -> Self {
+        let mut scheduler = MutexScheduler::new(num_workers);
+        scheduler.start();
+        scheduler
+    }
+
+    fn spawn(&self, task: impl FnOnce() + Send + 'static) {
+        self.submit(task).unwrap();
+    }
+
+    fn drain(self) {
+        self.shutdown_drain();
+    }
+}
+
+/// Time `SCHEDULER_TASKS` trivial tasks submitted from outside the pool
+fn bench_external_submissions<S: BenchScheduler>(num_workers: usize) -> Duration {
+    let scheduler = S::started(num_workers);
+
+    let start = Instant::now();
+    for _ in 0..SCHEDULER_TASKS {
+        scheduler.spawn(|| {});
+    }
+    scheduler.drain();
+    start.elapsed()
+}
+
+/// Time a fan-out where every task after the first is spawned by a running task
+fn bench_nested_spawns<S: BenchScheduler>(num_workers: usize) -> Duration {
+    let scheduler = Arc::new(S::started(num_workers));
+    let remaining = Arc::new(AtomicUsize::new(SCHEDULER_TASKS));
+
+    fn spawn_chunk<S: BenchScheduler>(scheduler: &Arc<S>, remaining: &Arc<AtomicUsize>) {
+        // Each task spawns up to 8 children until the budget runs out
+        for _ in 0..8 {
+            if remaining
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| left.checked_sub(1))
+                .is_err()
+            {
+                return;
+            }
+            let child_scheduler = Arc::clone(scheduler);
+            let remaining = Arc::clone(remaining);
+            scheduler.spawn(move || spawn_chunk(&child_scheduler, &remaining));
+        }
+    }
+
+    let start = Instant::now();
+    spawn_chunk(&scheduler, &remaining);
+    while remaining.load(Ordering::SeqCst) > 0 || Arc::strong_count(&scheduler) > 1 {
+        thread::yield_now();
+    }
+    Arc::into_inner(scheduler).unwrap().drain();
+    start.elapsed()
+}
+
+/// Run an end-to-end workload on the mutex design and the current one at each pool size
+fn compare_schedulers(workload: fn(usize) -> Duration, mutex_workload: fn(usize) -> Duration) {
+    for num_workers in WORKER_COUNTS {
+        let mutex = mutex_workload(num_workers);
+        let current = workload(num_workers);
+        print_rate(&format!("{} workers, mutex queues", num_workers), SCHEDULER_TASKS, mutex);
+        print_rate(&format!("{} workers, injector + deques", num_workers), SCHEDULER_TASKS, current);
+        println!("  📈 Speedup: {:.2}x", mutex.as_secs_f64() / current.as_secs_f64());
+    }
+}
+
+/// How the skewed workloads are lopsided
+#[derive(Clone, Copy)]
+enum Skew {
+    /// Nine tasks in ten share one key
+    HotKey,
+    /// Every key differs, but one task in 16 runs 100 times longer
+    LongTail,
+}
+
+fn spin(iterations: usize) {
+    for i in 0..iterations {
+        std::hint::black_box(i);
+    }
+}
+
+/// Time `SKEWED_TASKS` keyed submissions under one placement and steal strategy
+fn bench_skewed(
+    skew: Skew,
+    placement: Option<Arc<dyn PlacementStrategy>>,
+    steal_strategy: Arc<dyn StealStrategy>,
+) -> Duration {
+    let mut scheduler = TaskScheduler::new(SchedulerConfig {
+        placement,
+        steal_strategy,
+        ..benchmark_config(SKEWED_WORKERS)
+    });
+    scheduler.start();
+
+    let start = Instant::now();
+    for i in 0..SKEWED_TASKS {
+        let (key, iterations) = match skew {
+            Skew::HotKey => (if i % 10 == 0 { i } else { 0 }, SHORT_SPIN),
+            Skew::LongTail => (i, if i % 16 == 0 { SHORT_SPIN * 100 } else { SHORT_SPIN }),
+        };
+        scheduler.submit_with_key(&key, move || spin(iterations)).unwrap();
+    }
+    scheduler.shutdown_drain();
+    start.elapsed()
+}
+
+/// Strategies are built afresh for every run, so state like a round-robin cursor starts over
+type PlacementFactory = fn() -> Option<Arc<dyn PlacementStrategy>>;
+type StealFactory = fn() -> Arc<dyn StealStrategy>;
+
+/// Every placement strategy against every steal strategy on one skewed workload
+fn compare_strategies(skew: Skew) {
+    let placements: [(&str, PlacementFactory); 5] = [
+        ("injector", || None),
+        ("round robin", || Some(Arc::new(RoundRobin::default()))),
+        ("least loaded", || Some(Arc::new(LeastLoaded))),
+        ("key affinity", || Some(Arc::new(KeyAffinity))),
+        ("two choices", || Some(Arc::new(PowerOfTwoChoices))),
+    ];
+    let steals: [(&str, StealFactory); 3] = [
+        ("steal one", || Arc::new(StealOne)),
+        ("steal half", || Arc::new(StealHalf)),
+        ("random victim", || Arc::new(RandomVictim)),
+    ];
+
+    for (placement_name, placement) in placements {
+        for (steal_name, steal_strategy) in steals {
+            let label = format!("{} + {}", placement_name, steal_name);
+            print_rate(&label, SKEWED_TASKS, bench_skewed(skew, placement(), steal_strategy()));
+        }
+    }
+}
+
+fn benchmark_config(num_workers: usize) -> SchedulerConfig {
+    SchedulerConfig {
+        num_workers,
+        timeout_seconds: 0,
+        aging_interval_ms: 0,
+        execution_timeout_ms: 0,
+        ..SchedulerConfig::default()
+    }
+}
+
+fn print_rate(label: &str, operations: usize, elapsed: Duration) {
+    println!(
+        "  {:<28} {:>10.2?}  {:>12.0} ops/sec",
+        label,
+        elapsed,
+        operations as f64 / elapsed.as_secs_f64()
+    );
+}
+
+pub fn run_scheduler_benchmark() {
+    println!("\n🚀 Task Scheduler Queue Benchmark");
+    println!("==================================");
+
+    println!(
+        "\n🔀 Benchmark 1: Work-stealing queue alone ({} items, owner + {} thieves)",
+        DEQUE_ITEMS, DEQUE_THIEVES
+    );
+    let mutex_queue = MutexQueue::default();
+    let mutex = bench_queue(Arc::clone(&mutex_queue), mutex_queue);
+    print_rate("Mutex<VecDeque> (previous)", DEQUE_ITEMS, mutex);
+
+    let owner = deque::Worker::new();
+    let stealer = owner.stealer();
+    let lock_free = bench_queue(owner, stealer);
+    print_rate("Chase–Lev deque", DEQUE_ITEMS, lock_free);
+    println!("  📈 Speedup: {:.2}x", mutex.as_secs_f64() / lock_free.as_secs_f64());
+
+    println!("\n📥 Benchmark 2: External submissions, end to end ({} tasks)", SCHEDULER_TASKS);
+    compare_schedulers(
+        bench_external_submissions::<TaskScheduler>,
+        bench_external_submissions::<MutexScheduler>,
+    );
+
+    println!("\n🌳 Benchmark 3: Tasks spawned from tasks, end to end ({} tasks)", SCHEDULER_TASKS);
+    compare_schedulers(bench_nested_spawns::<TaskScheduler>, bench_nested_spawns::<MutexScheduler>);
+
+    println!(
+        "\n⚖️  Benchmark 4: Placement and stealing on skewed work ({} tasks, {} workers)",
+        SKEWED_TASKS, SKEWED_WORKERS
+    );
+    println!("  Hot key: nine tasks in ten share a key");
+    compare_strategies(Skew::HotKey);
+    println!("  Long tail: one task in 16 runs 100x longer");
+    compare_strategies(Skew::LongTail);
+}
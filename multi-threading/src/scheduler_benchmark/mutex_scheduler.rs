@@ -0,0 +1,313 @@
+//! The scheduler as it was before the deques and the injector, cut down to
+//! what a submitted task goes through, so the benchmark can run the same
+//! workload on both designs
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::task_scheduler::{CancellationToken, Priority};
+
+type Task = Box<dyn FnOnce() + Send + 'static>;
+
+/// Rounds of own-queue polling and stealing an idle worker makes before parking
+const IDLE_STEAL_ROUNDS: usize = 4;
+
+/// Upper bound on how long a parked worker sleeps between checks
+const PARK_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Only the supervisor, left out here, read the timestamps, but every
+/// task still paid for them
+#[allow(dead_code)]
+struct ScheduledTask {
+    task: Task,
+    id: u64,
+    priority: Priority,
+    submitted_at: Instant,
+    enqueued_at: Instant,
+    cancel_token: CancellationToken,
+}
+
+/// The task a worker is running, as published for cancellation and execution timeouts
+#[allow(dead_code)]
+struct RunningTask {
+    id: u64,
+    started_at: Instant,
+    cancel_token: CancellationToken,
+}
+
+/// One FIFO lane per priority level behind the worker's mutex
+struct WorkerQueue {
+    levels: Vec<VecDeque<ScheduledTask>>,
+}
+
+impl WorkerQueue {
+    fn new() -> Self {
+        Self {
+            levels: (0..Priority::LEVELS).map(|_| VecDeque::new()).collect(),
+        }
+    }
+
+    fn push(&mut self, task: ScheduledTask) {
+        self.levels[task.priority.index()].push_back(task);
+    }
+
+    fn pop(&mut self) -> Option<ScheduledTask> {
+        self.levels.iter_mut().rev().find_map(|lane| lane.pop_front())
+    }
+
+    /// Highest priority work, from the back of its lane
+    fn steal(&mut self) -> Option<ScheduledTask> {
+        self.levels.iter_mut().rev().find_map(|lane| lane.pop_back())
+    }
+
+    fn len(&self) -> usize {
+        self.levels.iter().map(|lane| lane.len()).sum()
+    }
+
+    fn top_priority(&self) -> Option<Priority> {
+        self.levels
+            .iter()
+            .rposition(|lane| !lane.is_empty())
+            .map(|level| Priority::new(level as u8))
+    }
+}
+
+struct SchedulerState {
+    worker_queues: Vec<Mutex<WorkerQueue>>,
+    running_tasks: Vec<Mutex<Option<RunningTask>>>,
+    idle_lock: Mutex<()>,
+    idle_condvar: Condvar,
+    parked_workers: AtomicUsize,
+    shutdown: Mutex<bool>,
+    task_counter: Mutex<u64>,
+    outstanding_tasks: AtomicUsize,
+    outstanding_lock: Mutex<()>,
+    outstanding_condvar: Condvar,
+    completed_tasks: AtomicUsize,
+    panicked_tasks: AtomicUsize,
+}
+
+impl SchedulerState {
+    fn notify_work(&self) {
+        if self.parked_workers.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+        let _guard = self.idle_lock.lock();
+        self.idle_condvar.notify_one();
+    }
+
+    fn task_finished(&self) {
+        if self.outstanding_tasks.fetch_sub(1, Ordering::SeqCst) == 1 {
+            let _guard = self.outstanding_lock.lock();
+            self.outstanding_condvar.notify_all();
+        }
+    }
+
+    fn is_shutting_down(&self) -> bool {
+        *self.shutdown.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Mutex-per-worker scheduler with a `try_lock` sweep on every submission
+pub(super) struct MutexScheduler {
+    state: Arc<SchedulerState>,
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+impl MutexScheduler {
+    pub(super) fn new(num_workers: usize) -> Self {
+        let state = Arc::new(SchedulerState {
+            worker_queues: (0..num_workers).map(|_| Mutex::new(WorkerQueue::new())).collect(),
+            running_tasks: (0..num_workers).map(|_| Mutex::new(None)).collect(),
+            idle_lock: Mutex::new(()),
+            idle_condvar: Condvar::new(),
+            parked_workers: AtomicUsize::new(0),
+            shutdown: Mutex::new(false),
+            task_counter: Mutex::new(0),
+            outstanding_tasks: AtomicUsize::new(0),
+            outstanding_lock: Mutex::new(()),
+            outstanding_condvar: Condvar::new(),
+            completed_tasks: AtomicUsize::new(0),
+            panicked_tasks: AtomicUsize::new(0),
+        });
+        Self {
+            state,
+            workers: Vec::new(),
+        }
+    }
+
+    pub(super) fn start(&mut self) {
+        for worker_id in 0..self.state.worker_queues.len() {
+            let state = Arc::clone(&self.state);
+            self.workers.push(thread::spawn(move || Self::worker_loop(worker_id, &state)));
+        }
+    }
+
+    /// Queue a task on the least loaded worker queue that can be locked without waiting
+    pub(super) fn submit<F>(&self, task: F) -> Result<u64, &'static str>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let state = &self.state;
+        state.outstanding_tasks.fetch_add(1, Ordering::SeqCst);
+        if state.is_shutting_down() {
+            state.task_finished();
+            return Err("Scheduler is shutting down");
+        }
+
+        let id = {
+            let mut counter = state.task_counter.lock().unwrap_or_else(|e| e.into_inner());
+            *counter = counter.wrapping_add(1);
+            *counter
+        };
+        let now = Instant::now();
+        let scheduled_task = ScheduledTask {
+            task: Box::new(task),
+            id,
+            priority: Priority::NORMAL,
+            submitted_at: now,
+            enqueued_at: now,
+            cancel_token: CancellationToken::new(),
+        };
+
+        const MAX_ATTEMPTS: usize = 3;
+        for attempt in 0..MAX_ATTEMPTS {
+            let mut min_queue_size = usize::MAX;
+            let mut best_queue_guard = None;
+            for queue_mutex in &state.worker_queues {
+                if let Ok(queue) = queue_mutex.try_lock() {
+                    let size = queue.len();
+                    if size < min_queue_size {
+                        min_queue_size = size;
+                        best_queue_guard = Some(queue);
+                        if size == 0 {
+                            break;
+                        }
+                    }
+                }
+            }
+
+            let queue = match best_queue_guard {
+                Some(queue) => Some(queue),
+                // Fall back to blocking on the first worker
+                None if attempt == MAX_ATTEMPTS - 1 => Some(state.worker_queues[0].lock().unwrap_or_else(|e| e.into_inner())),
+                None => None,
+            };
+            if let Some(mut queue) = queue {
+                queue.push(scheduled_task);
+                drop(queue);
+                state.notify_work();
+                return Ok(id);
+            }
+            thread::yield_now();
+        }
+        unreachable!("the last attempt always queues the task")
+    }
+
+    /// Wait for every submitted task to finish, then stop the workers
+    pub(super) fn shutdown_drain(mut self) {
+        let state = &self.state;
+        let mut guard = state.outstanding_lock.lock().unwrap_or_else(|e| e.into_inner());
+        while state.outstanding_tasks.load(Ordering::SeqCst) > 0 {
+            guard = state.outstanding_condvar.wait(guard).unwrap_or_else(|e| e.into_inner());
+        }
+        drop(guard);
+
+        *state.shutdown.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        {
+            let _guard = state.idle_lock.lock();
+            state.idle_condvar.notify_all();
+        }
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+
+    fn worker_loop(worker_id: usize, state: &SchedulerState) {
+        while !state.is_shutting_down() {
+            match Self::find_work(worker_id, state) {
+                Some(scheduled_task) => Self::run_task(worker_id, state, scheduled_task),
+                None => Self::park(worker_id, state),
+            }
+        }
+    }
+
+    /// Run a task with panic protection, publishing it as this worker's running task
+    fn run_task(worker_id: usize, state: &SchedulerState, scheduled_task: ScheduledTask) {
+        let ScheduledTask { task, id, cancel_token, .. } = scheduled_task;
+        if cancel_token.is_cancelled() {
+            state.task_finished();
+            return;
+        }
+
+        *state.running_tasks[worker_id].lock().unwrap_or_else(|e| e.into_inner()) = Some(RunningTask {
+            id,
+            started_at: Instant::now(),
+            cancel_token,
+        });
+        let counter = match std::panic::catch_unwind(std::panic::AssertUnwindSafe(task)) {
+            Ok(()) => &state.completed_tasks,
+            Err(_) => &state.panicked_tasks,
+        };
+        counter.fetch_add(1, Ordering::SeqCst);
+        state.task_finished();
+
+        let mut running = state.running_tasks[worker_id].lock().unwrap_or_else(|e| e.into_inner());
+        if running.as_ref().is_some_and(|task| task.id == id) {
+            *running = None;
+        }
+    }
+
+    /// Our own queue first, then a few rounds of stealing
+    fn find_work(worker_id: usize, state: &SchedulerState) -> Option<ScheduledTask> {
+        for _ in 0..IDLE_STEAL_ROUNDS {
+            if let Some(task) = state.worker_queues[worker_id].lock().unwrap_or_else(|e| e.into_inner()).pop() {
+                return Some(task);
+            }
+            if let Some(task) = Self::try_steal_work(worker_id, state) {
+                return Some(task);
+            }
+            thread::yield_now();
+        }
+        None
+    }
+
+    /// Steal from the worker with the most urgent work, keeping its queue locked while looking
+    fn try_steal_work(worker_id: usize, state: &SchedulerState) -> Option<ScheduledTask> {
+        let num_workers = state.worker_queues.len();
+        let mut best_priority = None;
+        let mut best_queue_guard = None;
+        for i in 1..num_workers {
+            let Ok(queue) = state.worker_queues[(worker_id + i) % num_workers].try_lock() else {
+                continue;
+            };
+            let top = queue.top_priority();
+            if top.is_some() && top > best_priority {
+                best_priority = top;
+                best_queue_guard = Some(queue);
+                if top == Some(Priority::MAX) {
+                    break;
+                }
+            }
+        }
+        best_queue_guard?.steal()
+    }
+
+    fn park(worker_id: usize, state: &SchedulerState) {
+        let idle_guard = state.idle_lock.lock().unwrap_or_else(|e| e.into_inner());
+        state.parked_workers.fetch_add(1, Ordering::SeqCst);
+
+        let has_work = state.worker_queues.iter().enumerate().any(|(i, queue_mutex)| {
+            let queue = queue_mutex.lock().unwrap_or_else(|e| e.into_inner());
+            if i == worker_id { queue.len() > 0 } else { queue.top_priority().is_some() }
+        });
+        if !state.is_shutting_down() && !has_work {
+            let _ = state.idle_condvar.wait_timeout(idle_guard, PARK_TIMEOUT);
+        }
+
+        state.parked_workers.fetch_sub(1, Ordering::SeqCst);
+    }
+}
@@ -1,33 +1,281 @@
-use std::collections::VecDeque;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering, fence};
 use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
 
+mod affinity;
+mod backpressure;
+mod cancel;
+pub(crate) mod deque;
+mod elastic;
+mod error;
+mod events;
+mod executor;
+mod graph;
+mod group;
+mod handle;
+mod hooks;
+mod journal;
+mod limits;
+mod parallel;
+mod placement;
+mod priority;
+mod queue;
+mod retry;
+mod schedule;
+mod scope;
+mod shutdown;
+mod simulation;
+mod stats;
+mod steal;
+mod tenant;
+mod timer;
+
+pub use backpressure::{RejectionPolicy, TrySubmitError};
+pub use cancel::CancellationToken;
+pub use error::SchedulerError;
+pub use events::{EventSink, SchedulerEvent, StderrSink};
+pub use executor::block_on;
+pub use graph::{FailurePolicy, GraphHandle, GraphReport, NodeId, NodeOutcome, TaskGraph, TaskGraphBuilder};
+pub use group::{GroupResults, TaskGroup};
+pub use handle::{JoinError, PanicPayload, TaskHandle};
+pub use hooks::{SchedulerHooks, TaskEnd, TaskStart};
+pub use journal::{JobHandler, JobRegistry};
+pub use limits::ClassLimit;
+pub use placement::{KeyAffinity, LeastLoaded, PlacementContext, PlacementStrategy, PowerOfTwoChoices, RoundRobin};
+pub use priority::Priority;
+pub use retry::{Backoff, DeadLetter, RetryPolicy, RetryPredicate, TaskFailure};
+pub use scope::Scope;
+pub use shutdown::{PendingTask, ShutdownReport};
+pub use stats::{LatencySummary, SchedulerStats, WorkerStats};
+pub use steal::{RandomVictim, StealContext, StealHalf, StealOne, StealStrategy};
+pub use tenant::TenantStats;
+pub use timer::{sleep, Sleep};
+
+use limits::TaskClasses;
+use queue::{DelayQueue, Injector, WorkerQueue, WorkerStealer};
+use simulation::{Clock, Simulation};
+use stats::{Histogram, WorkerCounters};
+use tenant::Tenants;
+
 /// A task is a boxed closure that takes no arguments and returns nothing
 pub type Task = Box<dyn FnOnce() + Send + 'static>;
 
+/// How a task body finished, as seen by the worker that ran it
+enum TaskExit {
+    Completed,
+    /// The task panicked; its payload has already been handed to a `TaskHandle`
+    /// or discarded
+    Panicked,
+    /// The attempt failed and `job` should run again once `delay`, less up
+    /// to the `jitter` fraction of it, has passed
+    Retry { delay: Duration, jitter: f64, job: Job },
+    /// The task failed for good and becomes a dead letter
+    Failed { attempts: u32, failure: TaskFailure },
+    /// A run of a periodic task completed and `job` is its next run, due at `due`
+    Recur { due: Instant, job: Job },
+    /// A spawned future is waiting to be woken; it stays outstanding until a
+    /// later poll completes it
+    Pending,
+}
+
+/// Type-erased task body as stored in worker queues
+type Job = Box<dyn FnOnce() -> TaskExit + Send + 'static>;
+
 /// Special marker task to signal shutdown
 pub struct PoisonPill;
 
+/// Rounds of own-queue polling and stealing an idle worker makes before parking
+const IDLE_STEAL_ROUNDS: usize = 4;
+
+/// Upper bound on how long a parked worker sleeps between checks
+const PARK_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Where a delay too long for `Instant` falls due instead: a century away
+const FAR_FUTURE: Duration = Duration::from_secs(100 * 365 * 24 * 60 * 60);
+
+/// Configuration shared by the tests: `num_workers` workers and none of the
+/// supervisor's timeouts or aging, which would otherwise act on slow test runs
+#[cfg(test)]
+pub(super) fn test_config(num_workers: usize) -> SchedulerConfig {
+    SchedulerConfig {
+        num_workers,
+        timeout_seconds: 0,
+        aging_interval_ms: 0,
+        execution_timeout_ms: 0,
+        ..SchedulerConfig::default()
+    }
+}
+
 /// Task wrapper that includes metadata for timeout detection
 #[derive(Debug)]
 struct TaskMetadata {
     id: u64,
     submitted_at: Instant,
+    priority: Priority,
+    /// When the task entered its current priority level, used for aging
+    enqueued_at: Instant,
+    /// The next run of a periodic task, which is not counted as outstanding
+    /// while it waits in the delay queue
+    recurring: bool,
+    tags: TaskTags,
+}
+
+/// Class, tenant and placement key a task is submitted with
+///
+/// Kept small, since every queued task carries them.
+#[derive(Debug, Clone, Copy, Default)]
+struct TaskTags {
+    /// Index of the task's class in `SchedulerState::classes`
+    class: Option<u16>,
+    /// Index of the task's tenant in `SchedulerState::tenants`
+    tenant: Option<u16>,
+    /// Hash of the key given to `TaskScheduler::submit_with_key`
+    key: Option<u32>,
 }
 
 /// Task with metadata for the scheduler
 struct ScheduledTask {
-    task: Task,
+    task: Job,
     metadata: TaskMetadata,
+    cancel_token: CancellationToken,
+}
+
+impl ScheduledTask {
+    fn new(id: u64, task: Job, priority: Priority, cancel_token: CancellationToken, now: Instant) -> Self {
+        Self {
+            task,
+            metadata: TaskMetadata {
+                id,
+                submitted_at: now,
+                priority,
+                enqueued_at: now,
+                recurring: false,
+                tags: TaskTags::default(),
+            },
+            cancel_token,
+        }
+    }
+}
+
+/// Task a worker is currently executing, tracked for cancellation and execution timeouts
+struct RunningTask {
+    id: u64,
+    started_at: Instant,
+    cancel_token: CancellationToken,
+    /// Set once the supervisor has reacted to this task overrunning
+    overrun_reported: bool,
+}
+
+/// Details of a task that has been running longer than the execution timeout
+#[derive(Debug, Clone)]
+pub struct OverrunningTask {
+    pub task_id: u64,
+    pub worker_id: usize,
+    pub running_for: Duration,
+}
+
+/// How the supervisor reacts to a task exceeding the execution timeout
+#[derive(Clone)]
+pub enum ExecutionTimeoutAction {
+    /// Report a `TaskOverrunning` event to the event sink
+    Log,
+    /// Hand the overrunning task to a user supplied callback
+    Callback(Arc<dyn Fn(&OverrunningTask) + Send + Sync>),
+    /// Warn, retire the stuck worker once its task returns and start a
+    /// replacement in a spare slot so the pool keeps its capacity
+    ReplaceWorker,
 }
 
 /// Configuration for the task scheduler
 #[derive(Clone)]
 pub struct SchedulerConfig {
+    /// Workers started by `start`; with an elastic pool, clamped to `min_workers..=max_workers`
     pub num_workers: usize,
+    /// How long a task may wait in the injector before a warning (0 disables)
     pub timeout_seconds: u64,
     pub enable_work_stealing: bool,
+    /// Promote a task waiting in the injector one priority level after this long (0 disables aging)
+    pub aging_interval_ms: u64,
+    /// How long a task may run on a worker before the supervisor reacts (0 disables)
+    pub execution_timeout_ms: u64,
+    pub execution_timeout_action: ExecutionTimeoutAction,
+    /// Upper bound for an elastic pool that grows and shrinks with load (0 keeps `num_workers` fixed)
+    pub max_workers: usize,
+    /// Workers an elastic pool keeps however idle it gets (at least 1)
+    pub min_workers: usize,
+    /// Start another worker once queued work has waited this long
+    pub scale_up_wait_ms: u64,
+    /// Retire a worker that has been idle this long, down to `min_workers` (0 never retires)
+    pub worker_keep_alive_ms: u64,
+    /// Most tasks that may be queued or running at once (0 = unbounded)
+    pub queue_capacity: usize,
+    /// Most tasks a worker keeps in its own queue; tasks it spawns beyond
+    /// that go to the injector (0 = unbounded)
+    pub worker_queue_capacity: usize,
+    /// What `submit` and friends do with a task while `queue_capacity` is reached
+    pub rejection_policy: RejectionPolicy,
+    /// Receives panics, timeout warnings and internal errors (default: stderr)
+    pub event_sink: Arc<dyn EventSink>,
+    /// Observers of workers and tasks, called in order
+    pub hooks: Vec<Arc<dyn SchedulerHooks>>,
+    /// Worker threads are named `<prefix>-<worker id>`, and the supervisor `<prefix>-supervisor`
+    pub thread_name_prefix: String,
+    /// Stack size for worker threads in bytes (0 = the platform default)
+    pub stack_size: usize,
+    /// CPU sets to pin workers to, on Linux: worker N runs on
+    /// `cpu_sets[N % cpu_sets.len()]`, and prefers to steal from workers
+    /// sharing its set (empty = unpinned)
+    pub cpu_sets: Vec<Vec<usize>>,
+    /// Called with its id on every new worker thread before it looks for
+    /// work, e.g. to set up thread-locals
+    pub worker_init: Option<Arc<dyn Fn(usize) + Send + Sync>>,
+    /// Run deterministically with this seed instead of on threads: workers
+    /// are stepped one task at a time on the caller's thread in a seeded
+    /// order, and the supervisor follows a virtual clock (see `TaskScheduler::step`)
+    pub simulation_seed: Option<u64>,
+    /// Concurrency caps and rate limits of the task classes named in
+    /// `TaskScheduler::submit_with_class`
+    pub class_limits: HashMap<String, ClassLimit>,
+    /// Weights of the tenants named in `TaskScheduler::submit_for_tenant`;
+    /// queued work is shared between tenants in proportion to them
+    pub tenant_weights: HashMap<String, u32>,
+    /// Which worker a submitted task is queued for (`None` = a shared
+    /// injector every worker takes from)
+    pub placement: Option<Arc<dyn PlacementStrategy>>,
+    /// Which worker an idle worker steals from, and how much it takes
+    pub steal_strategy: Arc<dyn StealStrategy>,
+}
+
+impl SchedulerConfig {
+    fn is_elastic(&self) -> bool {
+        self.max_workers > 0
+    }
+
+    fn min_workers(&self) -> usize {
+        self.min_workers.max(1).min(self.max_workers())
+    }
+
+    /// Most workers the pool may run at once
+    fn max_workers(&self) -> usize {
+        if self.is_elastic() { self.max_workers } else { self.num_workers }
+    }
+
+    /// Whether anything needs the supervisor's periodic checks
+    fn needs_supervisor(&self) -> bool {
+        self.timeout_seconds > 0 || self.aging_interval_ms > 0 || self.execution_timeout_ms > 0 || self.is_elastic()
+    }
+
+    fn initial_workers(&self) -> usize {
+        if self.is_elastic() {
+            self.num_workers.clamp(self.min_workers(), self.max_workers())
+        } else {
+            self.num_workers
+        }
+    }
 }
 
 impl Default for SchedulerConfig {
@@ -36,183 +284,578 @@ impl Default for SchedulerConfig {
             num_workers: num_cpus::get(),
             timeout_seconds: 30,
             enable_work_stealing: true,
+            aging_interval_ms: 1000,
+            execution_timeout_ms: 30_000,
+            execution_timeout_action: ExecutionTimeoutAction::Log,
+            max_workers: 0,
+            min_workers: 1,
+            scale_up_wait_ms: 100,
+            worker_keep_alive_ms: 60_000,
+            queue_capacity: 0,
+            worker_queue_capacity: 0,
+            rejection_policy: RejectionPolicy::Reject,
+            event_sink: Arc::new(StderrSink),
+            hooks: Vec::new(),
+            thread_name_prefix: "scheduler".to_string(),
+            stack_size: 0,
+            cpu_sets: Vec::new(),
+            worker_init: None,
+            simulation_seed: None,
+            class_limits: HashMap::new(),
+            tenant_weights: HashMap::new(),
+            placement: None,
+            steal_strategy: Arc::new(StealOne),
         }
     }
 }
 
-/// Individual worker queue for work stealing
-struct WorkerQueue {
-    queue: VecDeque<ScheduledTask>,
+/// A place for one worker thread: its queue and the task it is running
+///
+/// There are twice as many slots as the pool's maximum size, so a stuck
+/// worker can be replaced while its thread still occupies its own slot,
+/// and an elastic pool grows by starting threads in vacant slots.
+struct WorkerSlot {
+    /// Owner side of the slot's queue; `None` while a thread occupies the slot
+    queue: Mutex<Option<WorkerQueue>>,
+    stealer: WorkerStealer,
+    /// Tasks the placement strategy queued for the slot's worker
+    inbox: Injector,
+    running: Mutex<Option<RunningTask>>,
+    /// When the occupying thread last ran out of work, while it stays idle
+    idle_since: Mutex<Option<Instant>>,
+    /// Asks the occupying thread to exit once its current task returns
+    retiring: AtomicBool,
+    thread: Mutex<Option<thread::JoinHandle<()>>>,
+    counters: WorkerCounters,
 }
 
-impl WorkerQueue {
-    fn new() -> Self {
+impl WorkerSlot {
+    fn new(lane_weights: Vec<u32>) -> Self {
+        let (queue, stealer) = WorkerQueue::new();
         Self {
-            queue: VecDeque::new(),
+            queue: Mutex::new(Some(queue)),
+            stealer,
+            inbox: Injector::new(lane_weights),
+            running: Mutex::new(None),
+            idle_since: Mutex::new(None),
+            retiring: AtomicBool::new(false),
+            thread: Mutex::new(None),
+            counters: WorkerCounters::default(),
         }
     }
 
-    fn push(&mut self, task: ScheduledTask) {
-        self.queue.push_back(task);
+    /// Whether a thread occupies the slot; it holds the queue while it does
+    fn is_occupied(&self) -> bool {
+        self.queue.lock().map(|queue| queue.is_none()).unwrap_or(true)
     }
 
-    fn pop(&mut self) -> Option<ScheduledTask> {
-        self.queue.pop_front()
+    /// Tasks waiting in the slot's queue and inbox
+    fn queued(&self) -> usize {
+        self.stealer.len() + self.inbox.len()
     }
+}
 
-    fn steal(&mut self) -> Option<ScheduledTask> {
-        // Steal from the back to minimize contention with the owner
-        self.queue.pop_back()
-    }
+/// The worker queue of the current thread, if it is a worker
+struct LocalQueue {
+    /// Identifies the scheduler the worker belongs to
+    state: *const SchedulerState,
+    worker_id: usize,
+    queue: Rc<WorkerQueue>,
+}
 
-    fn len(&self) -> usize {
-        self.queue.len()
-    }
+thread_local! {
+    /// Lets tasks spawned from inside a task go straight onto the worker's own queue
+    static LOCAL_QUEUE: RefCell<Option<LocalQueue>> = const { RefCell::new(None) };
 }
 
 /// Shared state for the task scheduler
 struct SchedulerState {
-    worker_queues: Vec<Mutex<WorkerQueue>>,
-    worker_condvars: Vec<Condvar>,
+    /// Tasks submitted from outside the worker threads
+    injector: Injector,
+    /// Retries waiting out their backoff; workers move them to the injector when due
+    delayed: DelayQueue,
+    slots: Vec<WorkerSlot>,
+    /// Whether tasks spawned by a task may be stolen, so are worth waking a worker for
+    work_stealing: bool,
+    placement: Option<Arc<dyn PlacementStrategy>>,
+    steal_strategy: Arc<dyn StealStrategy>,
+    /// Threads occupying a slot, including any that are retiring
+    workers: AtomicUsize,
+    /// Idle workers park on `idle_condvar`; submitters wake them after pushing work
+    idle_lock: Mutex<()>,
+    idle_condvar: Condvar,
+    /// Number of workers parked (or about to park), so submitters can skip the idle lock
+    parked_workers: AtomicUsize,
+    /// Poison pills not yet taken; each stops one idle worker
+    poison_pills: AtomicUsize,
+    /// Threads replaced as stuck, with the slot each still occupies; joined at shutdown
+    replaced_threads: Mutex<Vec<(usize, thread::JoinHandle<()>)>>,
     shutdown: Mutex<bool>,
     shutdown_condvar: Condvar,
-    task_counter: Mutex<u64>,
+    task_counter: AtomicU64,
+    /// Accepted tasks that have not finished, been cancelled or been dropped
+    outstanding_tasks: AtomicUsize,
+    /// Paired with `outstanding_condvar` to wait for `outstanding_tasks` to
+    /// hit zero, or drop below `queue_capacity`
+    outstanding_lock: Mutex<()>,
+    outstanding_condvar: Condvar,
+    queue_capacity: usize,
+    worker_queue_capacity: usize,
+    rejection_policy: RejectionPolicy,
+    /// Submitters blocked until `outstanding_tasks` drops below `queue_capacity`
+    waiting_submitters: AtomicUsize,
+    /// Tasks refused or evicted because the queues were at capacity
+    rejected_tasks: AtomicUsize,
+    event_sink: Arc<dyn EventSink>,
+    /// Distinct errors reported so far, for the shutdown report
+    errors: Mutex<Vec<SchedulerError>>,
+    hooks: Vec<Arc<dyn SchedulerHooks>>,
+    cpu_sets: Vec<Vec<usize>>,
+    completed_tasks: AtomicUsize,
+    panicked_tasks: AtomicUsize,
+    /// Tasks that returned `Err` on their last attempt
+    failed_tasks: AtomicUsize,
+    dead_letters: Mutex<Vec<DeadLetter>>,
+    /// Time from submission to a worker starting the task
+    queue_wait: Histogram,
+    execution_time: Histogram,
+    peak_injector_depth: AtomicUsize,
+    /// Limits of the task classes, and the tasks each holds back
+    classes: TaskClasses,
+    tenants: Tenants,
+    clock: Clock,
+    /// Set in simulation mode, where it stands in for the worker and supervisor threads
+    simulation: Option<Simulation>,
+}
+
+impl SchedulerState {
+    /// Wake parked workers after pushing work onto a queue
+    ///
+    /// With `any_worker` a single worker is woken, since any of them can
+    /// take the task; otherwise all are woken.
+    fn notify_work(&self, any_worker: bool) {
+        // Order the push before reading the parked count; `park` does the
+        // reverse, so either it sees the work or we see it parked
+        fence(Ordering::SeqCst);
+        if self.parked_workers.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+
+        let _guard = self.idle_lock.lock();
+        if any_worker {
+            self.idle_condvar.notify_one();
+        } else {
+            self.idle_condvar.notify_all();
+        }
+    }
+
+    /// Account for an accepted task leaving the scheduler, waking drain
+    /// waiters and submitters blocked on a full queue
+    fn task_finished(&self) {
+        if self.outstanding_tasks.fetch_sub(1, Ordering::SeqCst) == 1
+            || self.waiting_submitters.load(Ordering::SeqCst) > 0
+        {
+            let _guard = self.outstanding_lock.lock();
+            self.outstanding_condvar.notify_all();
+        }
+    }
+
+    /// Accept a job into the scheduler, keeping the outstanding task count in
+    /// step, or apply the rejection policy if the queues are full
+    fn enqueue(&self, task: Job, priority: Priority, cancel_token: CancellationToken) -> Result<u64, SchedulerError> {
+        self.enqueue_tagged(task, priority, TaskTags::default(), cancel_token)
+    }
+
+    /// Accept a job in a class or for a tenant
+    fn enqueue_tagged(
+        &self,
+        task: Job,
+        priority: Priority,
+        tags: TaskTags,
+        cancel_token: CancellationToken,
+    ) -> Result<u64, SchedulerError> {
+        // Count the task before it becomes visible to workers, so it cannot
+        // finish before being counted
+        if !self.try_reserve() {
+            return self.reject(task, priority, tags, cancel_token);
+        }
+        self.enqueue_reserved(task, priority, tags, cancel_token)
+    }
+
+    /// Accept a job already counted as outstanding
+    fn enqueue_reserved(
+        &self,
+        task: Job,
+        priority: Priority,
+        tags: TaskTags,
+        cancel_token: CancellationToken,
+    ) -> Result<u64, SchedulerError> {
+        let result = self.place(task, priority, tags, cancel_token);
+        if result.is_err() {
+            self.task_finished();
+        }
+        result
+    }
+
+    /// Refuse new work once shutdown has begun
+    fn accepting(&self) -> Result<(), SchedulerError> {
+        if *self.shutdown.lock().map_err(|_| SchedulerError::Poisoned { which: "shutdown" })? {
+            return Err(SchedulerError::ShuttingDown);
+        }
+        Ok(())
+    }
+
+    /// Generate the ID of a newly accepted task and tell the hooks;
+    /// `fetch_add` wraps on overflow
+    fn next_task_id(&self) -> u64 {
+        let task_id = self.task_counter.fetch_add(1, Ordering::Relaxed).wrapping_add(1);
+        self.notify_hooks("on_submit", |hooks| hooks.on_submit(task_id));
+        task_id
+    }
+
+    /// Assign an id to a job, unless the scheduler is shutting down
+    fn new_task(&self, task: Job, priority: Priority, cancel_token: CancellationToken) -> Result<ScheduledTask, SchedulerError> {
+        self.accepting()?;
+        Ok(ScheduledTask::new(self.next_task_id(), task, priority, cancel_token, self.now()))
+    }
+
+    /// Place a job on the current worker's queue, or the injector when
+    /// submitted from outside the pool
+    fn place(
+        &self,
+        task: Job,
+        priority: Priority,
+        tags: TaskTags,
+        cancel_token: CancellationToken,
+    ) -> Result<u64, SchedulerError> {
+        let mut scheduled_task = self.new_task(task, priority, cancel_token)?;
+        scheduled_task.metadata.tags = tags;
+        self.tenants.submitted(tags.tenant);
+        let task_id = scheduled_task.metadata.id;
+        self.push(scheduled_task);
+        Ok(task_id)
+    }
+
+    /// Queue an accepted task, on the current worker when called from one
+    fn push(&self, scheduled_task: ScheduledTask) {
+        // Tasks spawned by a task stay on its worker, without touching any
+        // lock, unless they carry a key for the placement strategy
+        let keyed = scheduled_task.metadata.tags.key.is_some() && self.placement.is_some();
+        let scheduled_task = LOCAL_QUEUE.with(|local| match &*local.borrow() {
+            Some(local)
+                if !keyed
+                    && std::ptr::eq(local.state, self)
+                    && (self.worker_queue_capacity == 0 || local.queue.len() < self.worker_queue_capacity) =>
+            {
+                local.queue.push(scheduled_task);
+                None
+            }
+            _ => Some(scheduled_task),
+        });
+
+        match scheduled_task {
+            Some(scheduled_task) => self.push_shared(scheduled_task),
+            // Only other workers can help with it, by stealing
+            None if self.work_stealing => self.notify_work(true),
+            None => {}
+        }
+    }
+
+    /// Move delayed tasks and retries whose time has come to the injector
+    fn release_due(&self) {
+        // Checked without the lock, so this is nearly free while nothing waits
+        let Some(next_due) = self.delayed.next_due() else {
+            return;
+        };
+        let now = self.now();
+        if next_due > now {
+            return;
+        }
+
+        for mut scheduled_task in self.delayed.pop_due(now) {
+            if scheduled_task.metadata.recurring {
+                self.outstanding_tasks.fetch_add(1, Ordering::SeqCst);
+            }
+            // Waiting for its due time does not count towards queue wait,
+            // injector timeouts or scaling up
+            scheduled_task.metadata.submitted_at = now;
+            scheduled_task.metadata.enqueued_at = now;
+            self.injector.push(scheduled_task);
+        }
+        self.notify_work(false);
+    }
+
+    /// When a delayed task falls due or a held task gets a rate token,
+    /// whichever comes first
+    fn next_wake(&self) -> Option<Instant> {
+        [self.delayed.next_due(), self.classes.next_token(), self.next_timer()]
+            .into_iter()
+            .flatten()
+            .min()
+    }
+
+    /// Index of a slot no thread occupies
+    fn vacant_slot(&self) -> Option<usize> {
+        self.slots.iter().position(|slot| !slot.is_occupied())
+    }
 }
 
 /// Main task scheduler implementation
 pub struct TaskScheduler {
     state: Arc<SchedulerState>,
     config: SchedulerConfig,
-    worker_handles: Vec<thread::JoinHandle<()>>,
     supervisor_handle: Option<thread::JoinHandle<()>>,
+    /// Set by `open_journal`
+    journal: Option<Arc<journal::Journal>>,
 }
 
 impl TaskScheduler {
     /// Create a new task scheduler with the given configuration
     pub fn new(config: SchedulerConfig) -> Self {
-        let worker_queues = (0..config.num_workers)
-            .map(|_| Mutex::new(WorkerQueue::new()))
-            .collect();
-
-        let worker_condvars = (0..config.num_workers)
-            .map(|_| Condvar::new())
-            .collect();
+        let (clock, simulation) = Simulation::for_seed(&config);
+        let tenants = Tenants::new(&config.tenant_weights);
+        // One spare slot per worker for replacing stuck workers
+        let slots = (0..config.max_workers() * 2).map(|_| WorkerSlot::new(tenants.lane_weights())).collect();
 
         let state = Arc::new(SchedulerState {
-            worker_queues,
-            worker_condvars,
+            injector: Injector::new(tenants.lane_weights()),
+            delayed: DelayQueue::new(),
+            slots,
+            work_stealing: config.enable_work_stealing,
+            placement: config.placement.clone(),
+            steal_strategy: Arc::clone(&config.steal_strategy),
+            workers: AtomicUsize::new(0),
+            idle_lock: Mutex::new(()),
+            idle_condvar: Condvar::new(),
+            parked_workers: AtomicUsize::new(0),
+            poison_pills: AtomicUsize::new(0),
+            replaced_threads: Mutex::new(Vec::new()),
             shutdown: Mutex::new(false),
             shutdown_condvar: Condvar::new(),
-            task_counter: Mutex::new(0),
+            task_counter: AtomicU64::new(0),
+            outstanding_tasks: AtomicUsize::new(0),
+            outstanding_lock: Mutex::new(()),
+            outstanding_condvar: Condvar::new(),
+            queue_capacity: config.queue_capacity,
+            worker_queue_capacity: config.worker_queue_capacity,
+            rejection_policy: config.rejection_policy,
+            waiting_submitters: AtomicUsize::new(0),
+            rejected_tasks: AtomicUsize::new(0),
+            event_sink: Arc::clone(&config.event_sink),
+            errors: Mutex::new(Vec::new()),
+            hooks: config.hooks.clone(),
+            cpu_sets: config.cpu_sets.clone(),
+            completed_tasks: AtomicUsize::new(0),
+            panicked_tasks: AtomicUsize::new(0),
+            failed_tasks: AtomicUsize::new(0),
+            dead_letters: Mutex::new(Vec::new()),
+            queue_wait: Histogram::new(),
+            execution_time: Histogram::new(),
+            peak_injector_depth: AtomicUsize::new(0),
+            classes: TaskClasses::new(&config.class_limits, clock.now()),
+            tenants,
+            clock,
+            simulation,
         });
 
         Self {
             state,
             config,
-            worker_handles: Vec::new(),
             supervisor_handle: None,
+            journal: None,
         }
     }
 
     /// Start the scheduler with all worker threads and supervisor
     pub fn start(&mut self) {
         // Start worker threads
-        for worker_id in 0..self.config.num_workers {
-            let state = Arc::clone(&self.state);
-            let config = self.config.clone();
-            
-            let handle = thread::spawn(move || {
-                Self::worker_loop(worker_id, state, config);
-            });
-            
-            self.worker_handles.push(handle);
+        for worker_id in 0..self.config.initial_workers() {
+            Self::spawn_worker(worker_id, &self.state, &self.config);
         }
 
-        // Start supervisor thread
-        if self.config.timeout_seconds > 0 {
+        if self.state.simulation.is_some() {
+            self.start_simulation();
+            return;
+        }
+
+        // Start supervisor thread for timeout detection, priority aging,
+        // execution timeouts and pool sizing
+        if self.config.needs_supervisor() {
             let state = Arc::clone(&self.state);
-            let timeout_duration = Duration::from_secs(self.config.timeout_seconds);
+            let config = self.config.clone();
             
-            let handle = thread::spawn(move || {
-                Self::supervisor_loop(state, timeout_duration);
+            let builder = thread::Builder::new().name(format!("{}-supervisor", config.thread_name_prefix));
+            let spawned = builder.spawn(move || {
+                let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    Self::supervisor_loop(Arc::clone(&state), config);
+                }));
+                // Reported as it happens, then rethrown so `stop` sees it too
+                if let Err(payload) = result {
+                    state.record_error(None, SchedulerError::SupervisorDied);
+                    std::panic::resume_unwind(payload);
+                }
             });
-            
-            self.supervisor_handle = Some(handle);
+
+            match spawned {
+                Ok(handle) => self.supervisor_handle = Some(handle),
+                Err(_) => self.state.record_error(None, SchedulerError::SupervisorDied),
+            }
         }
     }
 
     /// Submit a new task to the scheduler
-    pub fn submit<F>(&self, task: F) -> Result<u64, &'static str>
+    ///
+    /// At `queue_capacity` the rejection policy decides what happens; a
+    /// rejected closure is dropped, so use `try_submit` to get it back.
+    pub fn submit<F>(&self, task: F) -> Result<u64, SchedulerError>
     where
         F: FnOnce() + Send + 'static,
     {
-        // Check if scheduler is shutting down
-        if *self.state.shutdown.lock().map_err(|_| "Shutdown lock poisoned")? {
-            return Err("Scheduler is shutting down");
-        }
+        self.enqueue(Self::job(task), Priority::NORMAL, CancellationToken::new())
+    }
 
-        // Generate task ID with overflow protection
-        let task_id = {
-            let mut counter = self.state.task_counter.lock().map_err(|_| "Task counter lock poisoned")?;
-            *counter = counter.wrapping_add(1);
-            *counter
-        };
+    /// Submit a new task that runs ahead of any queued lower-priority work
+    pub fn submit_with_priority<F>(&self, priority: Priority, task: F) -> Result<u64, SchedulerError>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.enqueue(Self::job(task), priority, CancellationToken::new())
+    }
 
-        let scheduled_task = ScheduledTask {
-            task: Box::new(task),
-            metadata: TaskMetadata {
-                id: task_id,
-                submitted_at: Instant::now(),
-            },
-        };
+    /// Submit a task that can poll for cooperative cancellation while it runs
+    ///
+    /// The token passed to the closure is signalled when `cancel` is called
+    /// with this task's id.
+    pub fn submit_cancellable<F>(&self, task: F) -> Result<u64, SchedulerError>
+    where
+        F: FnOnce(&CancellationToken) + Send + 'static,
+    {
+        let token = CancellationToken::new();
+        let task_token = token.clone();
+        self.enqueue(Self::job(move || task(&task_token)), Priority::NORMAL, token)
+    }
 
-        // Find the worker queue with the least tasks (load balancing with retry)
-        let mut attempts = 0;
-        const MAX_ATTEMPTS: usize = 3;
-        
-        while attempts < MAX_ATTEMPTS {
-            let mut min_queue_size = usize::MAX;
-            let mut best_worker = 0;
-            let mut best_queue_guard = None;
-
-            // Try to find and lock the best queue atomically
-            for (i, queue_mutex) in self.state.worker_queues.iter().enumerate() {
-                if let Ok(queue) = queue_mutex.try_lock() {
-                    let size = queue.len();
-                    if size < min_queue_size {
-                        min_queue_size = size;
-                        best_worker = i;
-                        best_queue_guard = Some(queue);
-                        // If we found an empty queue, use it immediately
-                        if size == 0 {
-                            break;
-                        }
+    /// Cancel a task by id
+    ///
+    /// Returns `true` if the task had not started yet: it is removed from
+    /// whichever queue holds it and will never run. A task that is already
+    /// running only has its `CancellationToken` signalled, and `false` is
+    /// returned since it may still run to completion. Either way a periodic
+    /// task is not scheduled again.
+    pub fn cancel(&self, task_id: u64) -> bool {
+        let removed = self
+            .state
+            .injector
+            .remove(task_id)
+            .or_else(|| self.state.delayed.remove(task_id))
+            .or_else(|| self.state.classes.remove(task_id))
+            .or_else(|| self.state.slots.iter().find_map(|slot| slot.inbox.remove(task_id)))
+            .or_else(|| {
+                if self.signal_running(task_id) {
+                    return None;
+                }
+
+                // Worker queues cannot be searched in place, so steal from each
+                // until the task turns up and hand the rest to the injector
+                let mut found = None;
+                for slot in &self.state.slots {
+                    let (task, others) = slot.stealer.steal_until(task_id);
+                    for scheduled_task in others {
+                        self.state.injector.push(scheduled_task);
+                    }
+                    if task.is_some() {
+                        found = task;
+                        break;
                     }
                 }
-            }
+                self.state.notify_work(false);
+                found
+            });
 
-            // If we got a lock on the best queue, use it
-            if let Some(mut queue) = best_queue_guard {
-                queue.push(scheduled_task);
-                // Notify the worker
-                self.state.worker_condvars[best_worker].notify_one();
-                return Ok(task_id);
+        // The task is dropped here, outside the injector lock
+        match removed {
+            Some(scheduled_task) => {
+                scheduled_task.cancel_token.cancel();
+                let counted = !scheduled_task.metadata.recurring;
+                drop(scheduled_task);
+                if counted {
+                    self.state.task_finished();
+                }
+                true
             }
+            None => false,
+        }
+    }
 
-            // If we couldn't get any locks, fall back to blocking on the first worker
-            if attempts == MAX_ATTEMPTS - 1 {
-                let mut queue = self.state.worker_queues[0].lock().map_err(|_| "Worker queue lock poisoned")?;
-                queue.push(scheduled_task);
-                self.state.worker_condvars[0].notify_one();
-                return Ok(task_id);
+    /// Signal the token of a running task, returning whether one was found
+    fn signal_running(&self, task_id: u64) -> bool {
+        self.state.slots.iter().any(|slot| {
+            let Ok(running) = slot.running.lock() else {
+                return false;
+            };
+            match running.as_ref().filter(|task| task.id == task_id) {
+                Some(task) => {
+                    task.cancel_token.cancel();
+                    true
+                }
+                None => false,
             }
+        })
+    }
 
-            attempts += 1;
-            // Brief yield before retrying
-            std::thread::yield_now();
-        }
+    /// Wrap a plain closure as a job; panics are caught by the worker
+    fn job<F>(task: F) -> Job
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        Box::new(move || {
+            task();
+            TaskExit::Completed
+        })
+    }
+
+    /// Accept a job into the scheduler, keeping the outstanding task count in step
+    fn enqueue(&self, task: Job, priority: Priority, cancel_token: CancellationToken) -> Result<u64, SchedulerError> {
+        self.state.enqueue(task, priority, cancel_token)
+    }
+
+    /// Submit a task that produces a value and get a handle to wait for it
+    ///
+    /// A panic inside the task is captured and surfaced through the handle
+    /// as `JoinError::Panicked` rather than being logged by the worker.
+    pub fn submit_with_result<F, T>(&self, task: F) -> Result<TaskHandle<T>, SchedulerError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        self.submit_with_result_and_priority(Priority::NORMAL, task)
+    }
 
-        Err("Failed to submit task after multiple attempts")
+    /// Submit a value-producing task at the given priority
+    pub fn submit_with_result_and_priority<F, T>(
+        &self,
+        priority: Priority,
+        task: F,
+    ) -> Result<TaskHandle<T>, SchedulerError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let cancel_token = CancellationToken::new();
+        let (completer, handle) = handle::pair(cancel_token.clone());
+
+        let task_id = self.enqueue(
+            Box::new(move || {
+                let outcome = std::panic::catch_unwind(std::panic::AssertUnwindSafe(task));
+                let exit = if outcome.is_ok() { TaskExit::Completed } else { TaskExit::Panicked };
+                completer.complete(outcome.map_err(JoinError::Panicked));
+                exit
+            }),
+            priority,
+            cancel_token,
+        )?;
+
+        Ok(handle.with_id(task_id).driven_by(&self.state))
     }
 
     /// Submit a poison pill to trigger shutdown
@@ -220,273 +863,642 @@ impl TaskScheduler {
         self.submit_poison_pill_safe();
     }
 
-    /// Shutdown the scheduler and wait for all threads to complete
-    pub fn shutdown(self) {
-        // Signal shutdown first
-        {
-            let shutdown_result = self.state.shutdown.lock();
-            match shutdown_result {
-                Ok(mut shutdown) => {
-                    *shutdown = true;
-                    self.state.shutdown_condvar.notify_all();
-                }
-                Err(_) => {
-                    eprintln!("Warning: Shutdown lock poisoned during shutdown");
-                    return;
-                }
-            }
-        }
+    /// Start a thread in the vacant slot `worker_id`
+    fn spawn_worker(worker_id: usize, state: &Arc<SchedulerState>, config: &SchedulerConfig) {
+        let slot = &state.slots[worker_id];
+        let Some(queue) = slot.queue.lock().ok().and_then(|mut queue| queue.take()) else {
+            state.record_error(Some(worker_id), SchedulerError::NoVacantSlot);
+            return;
+        };
+        // Clear a retirement request that raced with the previous thread's exit
+        slot.retiring.store(false, Ordering::SeqCst);
 
-        // Submit poison pills to ensure all workers wake up
-        // Use a separate method that handles errors gracefully
-        self.submit_poison_pill_safe();
+        state.workers.fetch_add(1, Ordering::SeqCst);
+        if let Some(simulation) = &state.simulation {
+            Self::start_simulated_worker(worker_id, queue, state, config, simulation);
+            return;
+        }
 
-        // Wait for all worker threads with panic detection
-        let mut worker_panics = 0;
-        for (i, handle) in self.worker_handles.into_iter().enumerate() {
-            match handle.join() {
-                Ok(_) => {
-                    // Worker completed successfully
-                }
-                Err(_) => {
-                    eprintln!("Warning: Worker thread {} panicked during shutdown", i);
-                    worker_panics += 1;
-                }
-            }
+        let mut builder = thread::Builder::new().name(format!("{}-{}", config.thread_name_prefix, worker_id));
+        if config.stack_size > 0 {
+            builder = builder.stack_size(config.stack_size);
         }
 
-        // Wait for supervisor thread with panic detection
-        if let Some(handle) = self.supervisor_handle {
-            match handle.join() {
-                Ok(_) => {
-                    // Supervisor completed successfully
+        // The queue is handed over once the thread exists, so a failed spawn
+        // can put it back and leave the slot vacant
+        let (queue_tx, queue_rx) = std::sync::mpsc::channel::<WorkerQueue>();
+        let spawned = {
+            let state = Arc::clone(state);
+            let config = config.clone();
+            builder.spawn(move || {
+                let Ok(queue) = queue_rx.recv() else {
+                    return;
+                };
+                let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    Self::worker_loop(worker_id, queue, Arc::clone(&state), config);
+                }));
+                if let Err(payload) = result {
+                    state.record_error(Some(worker_id), SchedulerError::WorkerDied { id: worker_id });
+                    std::panic::resume_unwind(payload);
                 }
-                Err(_) => {
-                    eprintln!("Warning: Supervisor thread panicked during shutdown");
+            })
+        };
+        let handle = match spawned {
+            Ok(handle) => handle,
+            Err(_) => {
+                state.workers.fetch_sub(1, Ordering::SeqCst);
+                if let Ok(mut vacant) = slot.queue.lock() {
+                    *vacant = Some(queue);
                 }
+                state.record_error(Some(worker_id), SchedulerError::SpawnFailed { id: worker_id });
+                return;
             }
-        }
+        };
+        let _ = queue_tx.send(queue);
 
-        if worker_panics > 0 {
-            eprintln!("Warning: {} worker threads panicked during shutdown", worker_panics);
+        match slot.thread.lock() {
+            Ok(mut thread) => *thread = Some(handle),
+            Err(_) => state.record_error(Some(worker_id), SchedulerError::Poisoned { which: "worker thread" }),
         }
     }
 
+    /// Shutdown the scheduler: running tasks finish, queued tasks are dropped
+    ///
+    /// See `shutdown_drain`, `shutdown_now` and `shutdown_timeout` for the
+    /// other shutdown modes.
+    pub fn shutdown(mut self) -> ShutdownReport {
+        let (mut report, unexecuted) = self.stop(None);
+        report.dropped += unexecuted.len();
+        report
+    }
+
     /// Submit poison pills with error handling
     fn submit_poison_pill_safe(&self) {
-        for (i, queue_mutex) in self.state.worker_queues.iter().enumerate() {
-            match queue_mutex.lock() {
-                Ok(mut queue) => {
-                    let poison_task = ScheduledTask {
-                        task: Box::new(|| {
-                            // This is the poison pill task - it will trigger shutdown
-                        }),
-                        metadata: TaskMetadata {
-                            id: u64::MAX, // Special ID for poison pill
-                            submitted_at: Instant::now(),
-                        },
-                    };
-                    queue.push(poison_task);
-                    
-                    // Notify the worker
-                    self.state.worker_condvars[i].notify_one();
-                }
-                Err(_) => {
-                    eprintln!("Warning: Could not submit poison pill to worker {}, queue lock poisoned", i);
-                    // Try to notify anyway in case the worker is waiting
-                    self.state.worker_condvars[i].notify_one();
-                }
-            }
-        }
+        // One pill per worker; each is only taken by a worker that has run
+        // out of work, so pills land behind already queued tasks
+        self.state
+            .poison_pills
+            .fetch_add(self.state.workers.load(Ordering::SeqCst), Ordering::SeqCst);
+        self.state.notify_work(false);
     }
 
     /// Worker thread main loop
-    fn worker_loop(worker_id: usize, state: Arc<SchedulerState>, config: SchedulerConfig) {
+    fn worker_loop(worker_id: usize, queue: WorkerQueue, state: Arc<SchedulerState>, config: SchedulerConfig) {
+        let slot = &state.slots[worker_id];
+        let queue = Rc::new(queue);
+        LOCAL_QUEUE.with(|local| {
+            *local.borrow_mut() = Some(LocalQueue {
+                state: Arc::as_ptr(&state),
+                worker_id,
+                queue: Rc::clone(&queue),
+            });
+        });
+
+        if let Some(cpus) = state.cpu_set(worker_id)
+            && !affinity::pin_current_thread(cpus)
+        {
+            state.record_error(Some(worker_id), SchedulerError::PinningFailed { id: worker_id });
+        }
+        if let Some(worker_init) = &config.worker_init {
+            worker_init(worker_id);
+        }
+        state.notify_hooks("on_worker_start", |hooks| hooks.on_worker_start(worker_id));
+
+        // Mirrors `slot.idle_since`, so busy workers do not take its lock per task
+        let mut idle = false;
         loop {
             // Check for shutdown with error handling
             let should_shutdown = match state.shutdown.lock() {
                 Ok(shutdown) => *shutdown,
                 Err(_) => {
-                    eprintln!("Worker {}: Shutdown lock poisoned, exiting", worker_id);
-                    return;
+                    state.record_error(Some(worker_id), SchedulerError::Poisoned { which: "shutdown" });
+                    break;
                 }
             };
             
-            if should_shutdown {
+            // Retired by the supervisor, or replaced while stuck on a task
+            if should_shutdown || slot.retiring.load(Ordering::SeqCst) {
                 break;
             }
 
-            // Try to get a task from our own queue first
-            let task = {
-                let queue_mutex = &state.worker_queues[worker_id];
-                let condvar = &state.worker_condvars[worker_id];
-                
-                let mut queue_guard = match queue_mutex.lock() {
-                    Ok(guard) => guard,
-                    Err(_) => {
-                        eprintln!("Worker {}: Queue lock poisoned, exiting", worker_id);
-                        return;
+            match Self::find_work(worker_id, &queue, &state, &config) {
+                Some((scheduled_task, stolen)) => {
+                    if idle {
+                        idle = false;
+                        Self::set_idle_since(slot, None);
                     }
-                };
-                
-                // Wait for tasks if queue is empty
-                loop {
-                    if queue_guard.len() > 0 {
-                        break queue_guard.pop();
+                    Self::run_task(worker_id, &state, scheduled_task, stolen);
+                }
+                None => {
+                    if Self::take_poison_pill(&state) {
+                        break;
                     }
-                    
-                    // Check shutdown again before waiting
-                    let should_shutdown = match state.shutdown.lock() {
-                        Ok(shutdown) => *shutdown,
-                        Err(_) => {
-                            eprintln!("Worker {}: Shutdown lock poisoned while waiting, exiting", worker_id);
-                            return;
-                        }
-                    };
-                    
-                    if should_shutdown {
-                        return;
+                    let parked_at = Instant::now();
+                    if !idle {
+                        idle = true;
+                        Self::set_idle_since(slot, Some(parked_at));
                     }
-                    
-                    queue_guard = match condvar.wait(queue_guard) {
-                        Ok(guard) => guard,
-                        Err(_) => {
-                            eprintln!("Worker {}: Condition variable wait failed, exiting", worker_id);
-                            return;
-                        }
-                    };
-                }
-            };
-
-            if let Some(scheduled_task) = task {
-                // Check if this is a poison pill
-                if scheduled_task.metadata.id == u64::MAX {
-                    break;
-                }
-
-                // Execute the task with panic protection
-                std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-                    (scheduled_task.task)();
-                })).unwrap_or_else(|_| {
-                    eprintln!("Worker {}: Task {} panicked during execution", worker_id, scheduled_task.metadata.id);
-                });
-                continue;
-            }
-
-            // Try work stealing if enabled and no task found
-            if config.enable_work_stealing {
-                let stolen_task = Self::try_steal_work(worker_id, &state);
-                if let Some(scheduled_task) = stolen_task {
-                    // Check if this is a poison pill
-                    if scheduled_task.metadata.id == u64::MAX {
+                    let keep_running = Self::park(worker_id, &queue, &state, &config);
+                    slot.counters.add_idle(parked_at.elapsed());
+                    if !keep_running {
                         break;
                     }
-
-                    // Execute the stolen task with panic protection
-                    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-                        (scheduled_task.task)();
-                    })).unwrap_or_else(|_| {
-                        eprintln!("Worker {}: Stolen task {} panicked during execution", worker_id, scheduled_task.metadata.id);
-                    });
-                    continue;
                 }
             }
+        }
 
-            // No work found, yield to other threads
-            thread::yield_now();
+        state.notify_hooks("on_worker_stop", |hooks| hooks.on_worker_stop(worker_id));
+        LOCAL_QUEUE.with(|local| local.borrow_mut().take());
+
+        // Hand leftover local work to the other workers and vacate the slot
+        // so a later thread can occupy it
+        while let Some(scheduled_task) = queue.pop() {
+            state.injector.push(scheduled_task);
+        }
+        state.notify_work(false);
+
+        Self::set_idle_since(slot, None);
+        slot.retiring.store(false, Ordering::SeqCst);
+        state.workers.fetch_sub(1, Ordering::SeqCst);
+        if let Ok(queue) = Rc::try_unwrap(queue)
+            && let Ok(mut vacant) = slot.queue.lock()
+        {
+            *vacant = Some(queue);
         }
+        state.vacate_inbox(worker_id);
     }
 
-    /// Try to steal work from other worker queues
-    fn try_steal_work(worker_id: usize, state: &Arc<SchedulerState>) -> Option<ScheduledTask> {
-        let num_workers = state.worker_queues.len();
-        
-        // Try to steal from other workers in round-robin fashion
-        for i in 1..num_workers {
-            let target_worker = (worker_id + i) % num_workers;
-            
-            if let Ok(mut queue) = state.worker_queues[target_worker].try_lock() {
-                if let Some(task) = queue.steal() {
-                    // Don't steal poison pills - they are meant for specific workers
-                    if task.metadata.id == u64::MAX {
-                        // Put the poison pill back at the end of the queue
-                        queue.push(task);
-                        continue;
-                    }
-                    return Some(task);
-                }
-            }
+    fn set_idle_since(slot: &WorkerSlot, idle_since: Option<Instant>) {
+        if let Ok(mut slot_idle_since) = slot.idle_since.lock() {
+            *slot_idle_since = idle_since;
         }
-        
-        None
     }
 
-    /// Supervisor thread main loop for timeout detection
-    fn supervisor_loop(state: Arc<SchedulerState>, timeout_duration: Duration) {
-        let check_interval = Duration::from_millis(500); // More responsive checking
-        
-        loop {
-            // Check for shutdown with error handling
-            let should_shutdown = match state.shutdown.lock() {
-                Ok(shutdown) => *shutdown,
-                Err(_) => {
-                    eprintln!("Supervisor: Shutdown lock poisoned, exiting");
-                    return;
+    /// Take a poison pill if one is pending
+    fn take_poison_pill(state: &SchedulerState) -> bool {
+        state
+            .poison_pills
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |pills| pills.checked_sub(1))
+            .is_ok()
+    }
+
+    /// Look for work in our own queue and the injector first, then spend a
+    /// few rounds stealing
+    ///
+    /// Tasks over their class's limits are held back along the way. Returns
+    /// the task and whether it was stolen.
+    fn find_work(
+        worker_id: usize,
+        queue: &WorkerQueue,
+        state: &SchedulerState,
+        config: &SchedulerConfig,
+    ) -> Option<(ScheduledTask, bool)> {
+        state.release_due();
+        state.release_held();
+
+        for _ in 0..IDLE_STEAL_ROUNDS {
+            while let Some(task) = Self::next_task(worker_id, queue, state, config) {
+                if let Some(task) = state.admit(task) {
+                    return Some((task, false));
                 }
-            };
-            
-            if should_shutdown {
-                break;
             }
 
-            // Sleep with shorter intervals for more responsive shutdown
-            thread::sleep(check_interval);
+            if !config.enable_work_stealing {
+                break;
+            }
 
-            // Check all queues for stale tasks
-            let now = Instant::now();
-            for (worker_id, queue_mutex) in state.worker_queues.iter().enumerate() {
-                // Use try_lock to avoid blocking and reduce contention
-                if let Ok(queue) = queue_mutex.try_lock() {
-                    for (task_index, scheduled_task) in queue.queue.iter().enumerate() {
-                        let age = now.duration_since(scheduled_task.metadata.submitted_at);
-                        if age > timeout_duration {
-                            eprintln!(
-                                "Warning: Task {} in worker {} queue position {} has been waiting for {:?} (timeout: {:?})",
-                                scheduled_task.metadata.id,
-                                worker_id,
-                                task_index,
-                                age,
-                                timeout_duration
-                            );
-                        }
-                    }
-                }
-                // If we can't get the lock, skip this queue to avoid blocking
+            if let Some(task) = Self::try_steal_work(worker_id, queue, state)
+                && let Some(task) = state.admit(task)
+            {
+                return Some((task, true));
             }
+
+            thread::yield_now();
         }
-    }
-}
 
-/// Helper function to get the number of logical CPUs
-mod num_cpus {
-    pub fn get() -> usize {
-        std::thread::available_parallelism()
-            .map(|n| n.get())
-            .unwrap_or(4)
+        None
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::sync::atomic::{AtomicUsize, Ordering};
-    use std::time::Duration;
+    /// Pop our own queue, unless the injector or our inbox holds more urgent work
+    fn next_task(worker_id: usize, queue: &WorkerQueue, state: &SchedulerState, config: &SchedulerConfig) -> Option<ScheduledTask> {
+        let shared = state.injector.top_priority().max(state.slots[worker_id].inbox.top_priority());
+        if shared > queue.top_priority()
+            && let Some(task) = Self::take_injected(worker_id, queue, state, config)
+        {
+            return Some(task);
+        }
 
-    #[test]
-    fn test_scheduler_creation() {
-        let config = SchedulerConfig::default();
-        let scheduler = TaskScheduler::new(config);
-        assert_eq!(scheduler.config.num_workers, num_cpus::get());
+        queue.pop().or_else(|| Self::take_injected(worker_id, queue, state, config))
+    }
+
+    /// Take a task from our inbox or the injector, keeping a batch of its
+    /// peers locally so the next few tasks do not need the lock
+    fn take_injected(
+        worker_id: usize,
+        queue: &WorkerQueue,
+        state: &SchedulerState,
+        config: &SchedulerConfig,
+    ) -> Option<ScheduledTask> {
+        // A bounded local queue keeps only the batch it has room for
+        let room = match state.worker_queue_capacity {
+            0 => usize::MAX,
+            capacity => capacity.saturating_sub(queue.len()),
+        };
+
+        // Tasks placed for us come first, unless the injector's are more urgent
+        let inbox = &state.slots[worker_id].inbox;
+        if !inbox.is_empty()
+            && inbox.top_priority() >= state.injector.top_priority()
+            && let Some((task, batch)) = inbox.pop_batch(1, room)
+        {
+            // Pushed newest first so our LIFO pops keep placement order
+            for scheduled_task in batch.into_iter().rev() {
+                queue.push(scheduled_task);
+            }
+            return Some(task);
+        }
+
+        // Without stealing, a batch would wait behind whatever this worker runs next
+        if !config.enable_work_stealing {
+            return state.injector.pop();
+        }
+
+        let (task, batch) = state.injector.pop_batch(state.workers.load(Ordering::Relaxed), room)?;
+        if !batch.is_empty() {
+            // Pushed newest first so our LIFO pops keep submission order
+            for scheduled_task in batch.into_iter().rev() {
+                queue.push(scheduled_task);
+            }
+            state.notify_work(true);
+        }
+
+        Some(task)
+    }
+
+    /// Sleep until a submission may have work for this worker
+    ///
+    /// Returns `false` if the worker should exit because a lock is poisoned.
+    fn park(worker_id: usize, queue: &WorkerQueue, state: &SchedulerState, config: &SchedulerConfig) -> bool {
+        let idle_guard = match state.idle_lock.lock() {
+            Ok(guard) => guard,
+            Err(_) => {
+                state.record_error(Some(worker_id), SchedulerError::Poisoned { which: "idle" });
+                return false;
+            }
+        };
+
+        // Announce ourselves before the final check: a submitter either sees
+        // us parked and wakes us, or pushed early enough for the check to see it
+        state.parked_workers.fetch_add(1, Ordering::SeqCst);
+
+        let should_shutdown = match state.shutdown.lock() {
+            Ok(shutdown) => *shutdown,
+            Err(_) => {
+                state.record_error(Some(worker_id), SchedulerError::Poisoned { which: "shutdown" });
+                state.parked_workers.fetch_sub(1, Ordering::SeqCst);
+                return false;
+            }
+        };
+
+        let now = Instant::now();
+        let next_due = state.next_wake();
+        let has_work = queue.len() > 0
+            || !state.injector.is_empty()
+            || !state.slots[worker_id].inbox.is_empty()
+            || state.poison_pills.load(Ordering::SeqCst) > 0
+            || next_due.is_some_and(|due| due <= now)
+            || (config.enable_work_stealing
+                && state.slots.iter().any(|slot| !slot.stealer.is_empty() || !slot.inbox.is_empty()));
+
+        if !should_shutdown && !has_work {
+            // Submissions wake us explicitly; the timeout is a safety net, and
+            // wakes us for the next retry that falls due or rate token that arrives
+            let timeout = next_due.map_or(PARK_TIMEOUT, |due| (due - now).min(PARK_TIMEOUT));
+            let _ = state.idle_condvar.wait_timeout(idle_guard, timeout);
+        }
+
+        state.parked_workers.fetch_sub(1, Ordering::SeqCst);
+        true
+    }
+
+    /// Execute a task with panic protection, publishing it as this worker's running task
+    fn run_task(worker_id: usize, state: &SchedulerState, scheduled_task: ScheduledTask, stolen: bool) {
+        let ScheduledTask { task, metadata, cancel_token } = scheduled_task;
+
+        // Cancelled between being dequeued and starting; dropping it is enough
+        if cancel_token.is_cancelled() {
+            state.leave_class(&metadata);
+            state.task_finished();
+            return;
+        }
+
+        let slot = &state.slots[worker_id];
+        let started_at = state.now();
+        let queue_wait = started_at.saturating_duration_since(metadata.submitted_at);
+        state.queue_wait.record(queue_wait);
+        state.before_task(&metadata, Some(worker_id), queue_wait, stolen);
+        if let Ok(mut running) = slot.running.lock() {
+            *running = Some(RunningTask {
+                id: metadata.id,
+                started_at,
+                cancel_token: cancel_token.clone(),
+                overrun_reported: false,
+            });
+        }
+
+        let exit = std::panic::catch_unwind(std::panic::AssertUnwindSafe(task)).unwrap_or_else(|_| {
+            state.emit(SchedulerEvent::TaskPanicked {
+                task_id: metadata.id,
+                worker_id: Some(worker_id),
+                stolen,
+            });
+            TaskExit::Panicked
+        });
+
+        if let Ok(mut running) = slot.running.lock() {
+            *running = None;
+        }
+        state.leave_class(&metadata);
+        let execution_time = state.now().saturating_duration_since(started_at);
+        state.execution_time.record(execution_time);
+        state.tenant_ran(&metadata, queue_wait, execution_time);
+        state.after_task(&metadata, Some(worker_id), queue_wait, execution_time, &exit);
+
+        slot.counters.executed.fetch_add(1, Ordering::Relaxed);
+        Self::settle(Some(worker_id), state, metadata, cancel_token, exit);
+    }
+
+    /// Run a task on the thread that submitted it, for `RejectionPolicy::CallerRuns`
+    fn run_on_caller(state: &SchedulerState, scheduled_task: ScheduledTask) {
+        let ScheduledTask { task, metadata, cancel_token } = scheduled_task;
+
+        let started_at = state.now();
+        let queue_wait = started_at.saturating_duration_since(metadata.submitted_at);
+        state.before_task(&metadata, None, queue_wait, false);
+        let exit = std::panic::catch_unwind(std::panic::AssertUnwindSafe(task)).unwrap_or_else(|_| {
+            state.emit(SchedulerEvent::TaskPanicked {
+                task_id: metadata.id,
+                worker_id: None,
+                stolen: false,
+            });
+            TaskExit::Panicked
+        });
+        let execution_time = state.now().saturating_duration_since(started_at);
+        state.execution_time.record(execution_time);
+        state.tenant_ran(&metadata, queue_wait, execution_time);
+        state.after_task(&metadata, None, queue_wait, execution_time, &exit);
+
+        Self::settle(None, state, metadata, cancel_token, exit);
+    }
+
+    /// Record how a task's run ended, and queue its next run if it has one
+    fn settle(
+        worker_id: Option<usize>,
+        state: &SchedulerState,
+        metadata: TaskMetadata,
+        cancel_token: CancellationToken,
+        exit: TaskExit,
+    ) {
+        let slot = worker_id.map(|worker_id| &state.slots[worker_id]);
+        match exit {
+            TaskExit::Completed => {
+                state.completed_tasks.fetch_add(1, Ordering::SeqCst);
+            }
+            TaskExit::Panicked => {
+                if let Some(slot) = slot {
+                    slot.counters.panicked.fetch_add(1, Ordering::Relaxed);
+                }
+                state.panicked_tasks.fetch_add(1, Ordering::SeqCst);
+            }
+            // Still outstanding: the task comes back through the delay queue,
+            // unless it was cancelled while it ran
+            TaskExit::Retry { delay, jitter, job } if !cancel_token.is_cancelled() => {
+                // Jitter comes from the scheduler, so a simulation seed replays it
+                let delay = retry::jittered(delay, jitter, state.random());
+                state.delayed.push(ScheduledTask { task: job, metadata, cancel_token }, state.due_after(delay));
+                return;
+            }
+            TaskExit::Retry { .. } => {}
+            TaskExit::Pending => return,
+            TaskExit::Recur { due, job } => {
+                state.completed_tasks.fetch_add(1, Ordering::SeqCst);
+                let shutting_down = state.shutdown.lock().map(|shutdown| *shutdown).unwrap_or(true);
+                if !cancel_token.is_cancelled() && !shutting_down {
+                    let metadata = TaskMetadata { recurring: true, ..metadata };
+                    state.delayed.push(ScheduledTask { task: job, metadata, cancel_token }, due);
+                }
+            }
+            TaskExit::Failed { attempts, failure } => {
+                state.emit(SchedulerEvent::TaskFailed {
+                    task_id: metadata.id,
+                    worker_id,
+                    attempts,
+                    failure: failure.clone(),
+                });
+                match &failure {
+                    TaskFailure::Panicked(_) => {
+                        if let Some(slot) = slot {
+                            slot.counters.panicked.fetch_add(1, Ordering::Relaxed);
+                        }
+                        state.panicked_tasks.fetch_add(1, Ordering::SeqCst);
+                    }
+                    TaskFailure::Error(_) => {
+                        state.failed_tasks.fetch_add(1, Ordering::SeqCst);
+                    }
+                }
+                if let Ok(mut dead_letters) = state.dead_letters.lock() {
+                    dead_letters.push(DeadLetter {
+                        task_id: metadata.id,
+                        attempts,
+                        failure,
+                    });
+                }
+            }
+        }
+        state.task_finished();
+    }
+
+    /// Supervisor thread main loop for timeout detection, priority aging,
+    /// pool sizing and queue depth sampling
+    fn supervisor_loop(state: Arc<SchedulerState>, config: SchedulerConfig) {
+        let check_interval = Self::check_interval(&config);
+        loop {
+            // Check for shutdown with error handling
+            let should_shutdown = match state.shutdown.lock() {
+                Ok(shutdown) => *shutdown,
+                Err(_) => {
+                    state.record_error(None, SchedulerError::Poisoned { which: "shutdown" });
+                    return;
+                }
+            };
+            
+            if should_shutdown {
+                break;
+            }
+
+            // Sleep with shorter intervals for more responsive shutdown
+            thread::sleep(check_interval);
+            Self::supervise(&state, &config, state.now());
+        }
+    }
+
+    /// How often the supervisor checks: more responsive than the default,
+    /// and at least as often as tasks age or overrun
+    fn check_interval(config: &SchedulerConfig) -> Duration {
+        let mut check_interval = Duration::from_millis(500);
+        if config.aging_interval_ms > 0 {
+            check_interval = check_interval.min(Duration::from_millis(config.aging_interval_ms));
+        }
+        if config.execution_timeout_ms > 0 {
+            check_interval = check_interval.min(Duration::from_millis(config.execution_timeout_ms));
+        }
+        if config.is_elastic() {
+            check_interval = check_interval.min(Duration::from_millis(config.scale_up_wait_ms.max(1)));
+            if config.worker_keep_alive_ms > 0 {
+                check_interval = check_interval.min(Duration::from_millis(config.worker_keep_alive_ms));
+            }
+        }
+        check_interval
+    }
+
+    /// One round of the supervisor's checks, as of `now`
+    fn supervise(state: &Arc<SchedulerState>, config: &SchedulerConfig, now: Instant) {
+        let timeout_duration = Duration::from_secs(config.timeout_seconds);
+        let aging_interval = Duration::from_millis(config.aging_interval_ms);
+        let execution_timeout = Duration::from_millis(config.execution_timeout_ms);
+
+        // Tasks handed to a worker are about to run, so only the injector
+        // and the inboxes of placed tasks are checked for stale tasks
+        let shared = || std::iter::once(&state.injector).chain(state.slots.iter().map(|slot| &slot.inbox));
+        if config.aging_interval_ms > 0 {
+            for queue in shared() {
+                queue.promote_aged(now, aging_interval);
+            }
+        }
+
+        if config.timeout_seconds > 0 {
+            for (task_id, position, waited) in shared().flat_map(|queue| queue.overdue(now, timeout_duration)) {
+                let warning = SchedulerEvent::TaskWaiting {
+                    task_id,
+                    position,
+                    waited,
+                    timeout: timeout_duration,
+                };
+                state.notify_hooks("on_timeout_warning", |hooks| hooks.on_timeout_warning(&warning));
+                state.emit(warning);
+            }
+        }
+
+        if config.execution_timeout_ms > 0 {
+            Self::check_running_tasks(state, config, now, execution_timeout);
+        }
+
+        if config.is_elastic() {
+            // Release due tasks even while every worker is busy, so
+            // they count towards the backlog that grows the pool
+            state.release_due();
+            Self::scale_pool(state, config, now);
+        }
+
+        state.sample_queue_depths();
+    }
+
+    /// React once to every task that has been running longer than the execution timeout
+    fn check_running_tasks(
+        state: &Arc<SchedulerState>,
+        config: &SchedulerConfig,
+        now: Instant,
+        execution_timeout: Duration,
+    ) {
+        for (worker_id, slot) in state.slots.iter().enumerate() {
+            let overrunning = match slot.running.lock() {
+                Ok(mut running) => running.as_mut().and_then(|task| {
+                    let running_for = now.saturating_duration_since(task.started_at);
+                    if task.overrun_reported || running_for <= execution_timeout {
+                        return None;
+                    }
+
+                    task.overrun_reported = true;
+                    Some(OverrunningTask {
+                        task_id: task.id,
+                        worker_id,
+                        running_for,
+                    })
+                }),
+                Err(_) => continue,
+            };
+
+            let Some(task) = overrunning else {
+                continue;
+            };
+
+            let warning = SchedulerEvent::TaskOverrunning {
+                task: task.clone(),
+                timeout: execution_timeout,
+            };
+            state.notify_hooks("on_timeout_warning", |hooks| hooks.on_timeout_warning(&warning));
+
+            match &config.execution_timeout_action {
+                ExecutionTimeoutAction::Log => state.emit(warning),
+                ExecutionTimeoutAction::Callback(callback) => callback(&task),
+                ExecutionTimeoutAction::ReplaceWorker => {
+                    state.emit(SchedulerEvent::WorkerStuck { task });
+                    Self::replace_worker(worker_id, state, config);
+                }
+            }
+        }
+    }
+
+    /// Retire the thread in slot `worker_id` and start a fresh one in a spare slot
+    fn replace_worker(worker_id: usize, state: &Arc<SchedulerState>, config: &SchedulerConfig) {
+        let Some(spare) = state.vacant_slot() else {
+            state.record_error(Some(worker_id), SchedulerError::NoVacantSlot);
+            return;
+        };
+
+        // The stuck thread exits on its own once its task returns and it
+        // notices it is retiring; shutdown joins it
+        let slot = &state.slots[worker_id];
+        slot.retiring.store(true, Ordering::SeqCst);
+        let handle = match slot.thread.lock() {
+            Ok(mut thread) => thread.take(),
+            Err(_) => {
+                state.record_error(Some(worker_id), SchedulerError::Poisoned { which: "worker thread" });
+                None
+            }
+        };
+        if let Some(handle) = handle {
+            state
+                .replaced_threads
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push((worker_id, handle));
+        }
+
+        Self::spawn_worker(spare, state, config);
+    }
+}
+
+/// Helper function to get the number of logical CPUs
+mod num_cpus {
+    pub fn get() -> usize {
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn test_scheduler_creation() {
+        let config = SchedulerConfig::default();
+        let scheduler = TaskScheduler::new(config);
+        assert_eq!(scheduler.config.num_workers, num_cpus::get());
     }
 
     #[test]
@@ -495,6 +1507,7 @@ mod tests {
             num_workers: 2,
             timeout_seconds: 5,
             enable_work_stealing: true,
+            ..SchedulerConfig::default()
         };
         
         let mut scheduler = TaskScheduler::new(config);
@@ -504,14 +1517,14 @@ mod tests {
         let counter_clone = Arc::clone(&counter);
 
         // Submit a task
-        let task_id = scheduler.submit(move || {
+        let handle = scheduler.submit_with_result(move || {
             counter_clone.fetch_add(1, Ordering::SeqCst);
         }).unwrap();
 
-        assert!(task_id > 0);
+        assert!(handle.id() > 0);
 
-        // Wait a bit for task execution
-        thread::sleep(Duration::from_millis(100));
+        // Wait for task execution
+        handle.join().unwrap();
 
         // Check that task was executed
         assert_eq!(counter.load(Ordering::SeqCst), 1);
@@ -525,6 +1538,7 @@ mod tests {
             num_workers: 2,
             timeout_seconds: 5,
             enable_work_stealing: true,
+            ..SchedulerConfig::default()
         };
         
         let mut scheduler = TaskScheduler::new(config);
@@ -533,15 +1547,19 @@ mod tests {
         let counter = Arc::new(AtomicUsize::new(0));
         
         // Submit multiple tasks
-        for _ in 0..10 {
-            let counter_clone = Arc::clone(&counter);
-            scheduler.submit(move || {
-                counter_clone.fetch_add(1, Ordering::SeqCst);
-            }).unwrap();
-        }
+        let handles: Vec<_> = (0..10)
+            .map(|_| {
+                let counter_clone = Arc::clone(&counter);
+                scheduler.submit_with_result(move || {
+                    counter_clone.fetch_add(1, Ordering::SeqCst);
+                }).unwrap()
+            })
+            .collect();
 
         // Wait for all tasks to complete
-        thread::sleep(Duration::from_millis(200));
+        for handle in handles {
+            handle.join().unwrap();
+        }
 
         assert_eq!(counter.load(Ordering::SeqCst), 10);
 
@@ -554,6 +1572,7 @@ mod tests {
             num_workers: 1,
             timeout_seconds: 5,
             enable_work_stealing: false,
+            ..SchedulerConfig::default()
         };
         
         let mut scheduler = TaskScheduler::new(config);
@@ -565,4 +1584,584 @@ mod tests {
         // Scheduler should shut down cleanly
         scheduler.shutdown();
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_task_handle_returns_value() {
+        let config = SchedulerConfig {
+            num_workers: 2,
+            timeout_seconds: 5,
+            enable_work_stealing: true,
+            ..SchedulerConfig::default()
+        };
+
+        let mut scheduler = TaskScheduler::new(config);
+        scheduler.start();
+
+        let handles: Vec<_> = (0..8u64)
+            .map(|i| scheduler.submit_with_result(move || i * i).unwrap())
+            .collect();
+
+        let results: Vec<u64> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(results, vec![0, 1, 4, 9, 16, 25, 36, 49]);
+
+        scheduler.shutdown();
+    }
+
+    #[test]
+    fn test_task_handle_captures_panic_payload() {
+        let config = SchedulerConfig {
+            num_workers: 1,
+            timeout_seconds: 5,
+            enable_work_stealing: false,
+            ..SchedulerConfig::default()
+        };
+
+        let mut scheduler = TaskScheduler::new(config);
+        scheduler.start();
+
+        let handle = scheduler
+            .submit_with_result(|| -> u32 { panic!("task exploded") })
+            .unwrap();
+
+        let err = handle.join().unwrap_err();
+        assert!(err.is_panic());
+        assert_eq!(err.panic_message(), Some("task exploded"));
+
+        // The worker survives the panic and keeps serving tasks
+        let handle = scheduler.submit_with_result(|| 7).unwrap();
+        assert_eq!(handle.join().unwrap(), 7);
+
+        scheduler.shutdown();
+    }
+
+    #[test]
+    fn test_task_handle_try_join_and_timeout() {
+        let config = SchedulerConfig {
+            num_workers: 1,
+            timeout_seconds: 5,
+            enable_work_stealing: false,
+            ..SchedulerConfig::default()
+        };
+
+        let mut scheduler = TaskScheduler::new(config);
+        scheduler.start();
+
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        let handle = scheduler
+            .submit_with_result(move || {
+                release_rx.recv().unwrap();
+                "done"
+            })
+            .unwrap();
+
+        let handle = handle.try_join().unwrap_err();
+        let handle = handle.join_timeout(Duration::from_millis(20)).unwrap_err();
+        assert!(!handle.is_finished());
+
+        release_tx.send(()).unwrap();
+        let outcome = handle.join_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(outcome.unwrap(), "done");
+
+        // A timeout past the end of `Instant` waits without a deadline
+        let handle = scheduler.submit_with_result(|| "unbounded").unwrap();
+        assert_eq!(handle.join_timeout(Duration::MAX).unwrap().unwrap(), "unbounded");
+
+        scheduler.shutdown();
+    }
+
+    #[test]
+    fn test_task_handle_reports_dropped_task() {
+        let config = SchedulerConfig {
+            enable_work_stealing: false,
+            ..test_config(1)
+        };
+
+        let mut scheduler = TaskScheduler::new(config);
+        scheduler.start();
+
+        let (started_tx, started_rx) = std::sync::mpsc::channel::<()>();
+        let blocker = scheduler
+            .submit_with_result(move || {
+                started_tx.send(()).unwrap();
+                thread::sleep(Duration::from_millis(50));
+            })
+            .unwrap();
+        started_rx.recv().unwrap();
+
+        // Queued behind the blocker and discarded when the scheduler shuts down
+        let queued = scheduler.submit_with_result(|| 1).unwrap();
+        scheduler.shutdown();
+
+        blocker.join().unwrap();
+        assert!(matches!(queued.join(), Err(JoinError::Dropped)));
+    }
+
+    #[test]
+    fn test_higher_priority_tasks_run_first() {
+        let config = SchedulerConfig {
+            enable_work_stealing: false,
+            ..test_config(1)
+        };
+
+        let mut scheduler = TaskScheduler::new(config);
+        scheduler.start();
+
+        // Occupy the only worker so everything below queues up
+        let (started_tx, started_rx) = std::sync::mpsc::channel::<()>();
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        scheduler.submit(move || {
+            started_tx.send(()).unwrap();
+            release_rx.recv().unwrap();
+        }).unwrap();
+        started_rx.recv().unwrap();
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let handles: Vec<_> = [("low", Priority::LOW), ("normal", Priority::NORMAL), ("high", Priority::HIGH)]
+            .into_iter()
+            .map(|(label, priority)| {
+                let order = Arc::clone(&order);
+                scheduler
+                    .submit_with_result_and_priority(priority, move || order.lock().unwrap().push(label))
+                    .unwrap()
+            })
+            .collect();
+
+        release_tx.send(()).unwrap();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(*order.lock().unwrap(), vec!["high", "normal", "low"]);
+
+        scheduler.shutdown();
+    }
+
+    #[test]
+    fn test_aging_prevents_low_priority_starvation() {
+        let config = SchedulerConfig {
+            enable_work_stealing: false,
+            aging_interval_ms: 10,
+            ..test_config(1)
+        };
+
+        let mut scheduler = TaskScheduler::new(config);
+        scheduler.start();
+
+        let (started_tx, started_rx) = std::sync::mpsc::channel::<()>();
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        scheduler.submit(move || {
+            started_tx.send(()).unwrap();
+            release_rx.recv().unwrap();
+        }).unwrap();
+        started_rx.recv().unwrap();
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let order_clone = Arc::clone(&order);
+        let low = scheduler
+            .submit_with_result_and_priority(Priority::LOW, move || order_clone.lock().unwrap().push("low"))
+            .unwrap();
+
+        // Give the supervisor time to promote the waiting low-priority task
+        thread::sleep(Duration::from_millis(200));
+
+        let normal: Vec<_> = (0..3)
+            .map(|_| {
+                let order = Arc::clone(&order);
+                scheduler.submit_with_result(move || order.lock().unwrap().push("normal")).unwrap()
+            })
+            .collect();
+
+        release_tx.send(()).unwrap();
+        low.join().unwrap();
+        for handle in normal {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(order.lock().unwrap()[0], "low");
+
+        scheduler.shutdown();
+    }
+
+    #[test]
+    fn test_cancel_removes_queued_task() {
+        let config = SchedulerConfig {
+            enable_work_stealing: false,
+            ..test_config(1)
+        };
+
+        let mut scheduler = TaskScheduler::new(config);
+        scheduler.start();
+
+        let (started_tx, started_rx) = std::sync::mpsc::channel::<()>();
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        scheduler.submit(move || {
+            started_tx.send(()).unwrap();
+            release_rx.recv().unwrap();
+        }).unwrap();
+        started_rx.recv().unwrap();
+
+        let counter = Arc::new(AtomicUsize::new(0));
+        let counter_clone = Arc::clone(&counter);
+        let cancelled = scheduler
+            .submit_with_result(move || {
+                counter_clone.fetch_add(1, Ordering::SeqCst);
+            })
+            .unwrap();
+        let kept = scheduler.submit_with_result(|| "kept").unwrap();
+
+        assert!(scheduler.cancel(cancelled.id()));
+        assert!(!scheduler.cancel(cancelled.id()), "a task can only be cancelled once");
+        assert!(!scheduler.cancel(12345), "unknown ids are not cancelled");
+
+        release_tx.send(()).unwrap();
+        assert_eq!(kept.join().unwrap(), "kept");
+        assert!(cancelled.join().unwrap_err().is_cancelled());
+        assert_eq!(counter.load(Ordering::SeqCst), 0);
+
+        scheduler.shutdown();
+    }
+
+    #[test]
+    fn test_cancel_removes_task_from_worker_queue() {
+        let config = SchedulerConfig {
+            enable_work_stealing: false,
+            ..test_config(1)
+        };
+
+        let mut scheduler = TaskScheduler::new(config);
+        scheduler.start();
+        let scheduler = Arc::new(scheduler);
+
+        // The child is spawned by a running task, so it waits on the worker's own queue
+        let (child_tx, child_rx) = std::sync::mpsc::channel();
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        let parent = {
+            let inner_scheduler = Arc::clone(&scheduler);
+            scheduler.submit_with_result(move || {
+                let child = inner_scheduler.submit_with_result(|| "child").unwrap();
+                let sibling = inner_scheduler.submit_with_result(|| "sibling").unwrap();
+                child_tx.send((child, sibling)).unwrap();
+                release_rx.recv().unwrap();
+            }).unwrap()
+        };
+
+        let (child, sibling) = child_rx.recv().unwrap();
+        assert!(scheduler.cancel(child.id()));
+        assert!(!scheduler.cancel(child.id()));
+
+        release_tx.send(()).unwrap();
+        parent.join().unwrap();
+        assert!(child.join().unwrap_err().is_cancelled());
+        assert_eq!(sibling.join().unwrap(), "sibling");
+
+        sole_owner(scheduler).shutdown();
+    }
+
+    #[test]
+    fn test_cancel_signals_running_task_token() {
+        let config = SchedulerConfig {
+            enable_work_stealing: false,
+            ..test_config(1)
+        };
+
+        let mut scheduler = TaskScheduler::new(config);
+        scheduler.start();
+
+        let (started_tx, started_rx) = std::sync::mpsc::channel::<()>();
+        let (done_tx, done_rx) = std::sync::mpsc::channel::<bool>();
+        let task_id = scheduler
+            .submit_cancellable(move |token| {
+                started_tx.send(()).unwrap();
+                let deadline = Instant::now() + Duration::from_secs(5);
+                while !token.is_cancelled() && Instant::now() < deadline {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                done_tx.send(token.is_cancelled()).unwrap();
+            })
+            .unwrap();
+        started_rx.recv().unwrap();
+
+        // Already running, so it is only asked to stop
+        assert!(!scheduler.cancel(task_id));
+        assert!(done_rx.recv_timeout(Duration::from_secs(5)).unwrap());
+
+        scheduler.shutdown();
+    }
+
+    #[test]
+    fn test_execution_timeout_reports_long_running_task() {
+        let overruns = Arc::new(Mutex::new(Vec::new()));
+        let overruns_clone = Arc::clone(&overruns);
+
+        let config = SchedulerConfig {
+            enable_work_stealing: false,
+            execution_timeout_ms: 50,
+            execution_timeout_action: ExecutionTimeoutAction::Callback(Arc::new(move |task| {
+                overruns_clone.lock().unwrap().push(task.clone());
+            })),
+            ..test_config(1)
+        };
+
+        let mut scheduler = TaskScheduler::new(config);
+        scheduler.start();
+
+        let quick = scheduler.submit_with_result(|| {}).unwrap();
+        quick.join().unwrap();
+
+        let slow = scheduler
+            .submit_with_result(|| thread::sleep(Duration::from_millis(300)))
+            .unwrap();
+        let slow_id = slow.id();
+        slow.join().unwrap();
+
+        // Reported exactly once, and only the slow task
+        let overruns = overruns.lock().unwrap();
+        assert_eq!(overruns.len(), 1);
+        assert_eq!(overruns[0].task_id, slow_id);
+        assert_eq!(overruns[0].worker_id, 0);
+        assert!(overruns[0].running_for > Duration::from_millis(50));
+        drop(overruns);
+
+        scheduler.shutdown();
+    }
+
+    #[test]
+    fn test_stuck_worker_is_replaced() {
+        let config = SchedulerConfig {
+            enable_work_stealing: false,
+            execution_timeout_ms: 50,
+            execution_timeout_action: ExecutionTimeoutAction::ReplaceWorker,
+            ..test_config(1)
+        };
+
+        let mut scheduler = TaskScheduler::new(config);
+        scheduler.start();
+
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        let stuck = scheduler
+            .submit_with_result(move || release_rx.recv_timeout(Duration::from_secs(10)).is_ok())
+            .unwrap();
+
+        // The only worker is blocked, so this can only run on a replacement
+        let handle = scheduler.submit_with_result(|| 42).unwrap();
+        let outcome = handle.join_timeout(Duration::from_secs(5)).expect("replacement worker never ran the task");
+        assert_eq!(outcome.unwrap(), 42);
+        assert!(!stuck.is_finished());
+
+        release_tx.send(()).unwrap();
+        assert!(stuck.join().unwrap());
+
+        scheduler.shutdown();
+    }
+
+    fn active_workers(scheduler: &TaskScheduler) -> usize {
+        scheduler.stats().workers.iter().filter(|worker| worker.active).count()
+    }
+
+    #[test]
+    fn test_elastic_pool_grows_while_work_waits() {
+        let config = SchedulerConfig {
+            max_workers: 3,
+            scale_up_wait_ms: 20,
+            ..test_config(1)
+        };
+
+        let mut scheduler = TaskScheduler::new(config);
+        scheduler.start();
+        assert_eq!(active_workers(&scheduler), 1);
+
+        // Three tasks that can only finish once all three run at the same time
+        let barrier = Arc::new(std::sync::Barrier::new(3));
+        let handles: Vec<_> = (0..3)
+            .map(|_| {
+                let barrier = Arc::clone(&barrier);
+                scheduler.submit_with_result(move || barrier.wait()).unwrap()
+            })
+            .collect();
+
+        for handle in handles {
+            handle
+                .join_timeout(Duration::from_secs(5))
+                .expect("the pool never grew to three workers")
+                .unwrap();
+        }
+        assert_eq!(active_workers(&scheduler), 3);
+
+        scheduler.shutdown();
+    }
+
+    #[test]
+    fn test_elastic_pool_retires_idle_workers() {
+        let config = SchedulerConfig {
+            max_workers: 3,
+            min_workers: 1,
+            worker_keep_alive_ms: 30,
+            ..test_config(3)
+        };
+
+        let mut scheduler = TaskScheduler::new(config);
+        scheduler.start();
+        assert_eq!(active_workers(&scheduler), 3);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while active_workers(&scheduler) > 1 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(active_workers(&scheduler), 1);
+
+        // Never below the minimum, and the remaining worker still serves tasks
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(active_workers(&scheduler), 1);
+        let handle = scheduler.submit_with_result(|| 5).unwrap();
+        assert_eq!(handle.join_timeout(Duration::from_secs(5)).unwrap().unwrap(), 5);
+
+        let report = scheduler.shutdown();
+        assert_eq!(report.worker_panics, 0);
+    }
+
+    #[test]
+    fn test_stats_track_outcomes_and_latencies() {
+        let config = SchedulerConfig {
+            enable_work_stealing: false,
+            ..test_config(1)
+        };
+
+        let mut scheduler = TaskScheduler::new(config);
+        scheduler.start();
+
+        scheduler.submit(|| thread::sleep(Duration::from_millis(20))).unwrap();
+        for _ in 0..3 {
+            scheduler.submit(|| {}).unwrap();
+        }
+        scheduler.submit(|| panic!("counted, not fatal")).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let stats = loop {
+            let stats = scheduler.stats();
+            if stats.in_flight == 0 || Instant::now() > deadline {
+                break stats;
+            }
+            thread::sleep(Duration::from_millis(1));
+        };
+
+        assert_eq!((stats.submitted, stats.completed, stats.panicked, stats.in_flight), (5, 4, 1, 0));
+        assert_eq!(stats.injector_depth, 0);
+        assert_eq!(stats.queue_wait.count, 5);
+        assert_eq!(stats.execution_time.count, 5);
+        assert!(stats.execution_time.max >= Duration::from_millis(20));
+        assert!(stats.execution_time.p50 < Duration::from_millis(20));
+
+        assert_eq!(stats.workers.len(), 1);
+        let worker = &stats.workers[0];
+        assert!(worker.active);
+        assert_eq!((worker.executed, worker.panicked, worker.stolen_by), (5, 1, 0));
+
+        assert!(stats.to_string().contains("worker 0: executed 5, panicked 1"));
+        assert!(stats.to_json().contains("\"submitted\":5,"));
+
+        scheduler.shutdown();
+    }
+
+    /// Unwrap a scheduler shared with tasks, once the last of them has dropped its clone
+    fn sole_owner(mut scheduler: Arc<TaskScheduler>) -> TaskScheduler {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            match Arc::try_unwrap(scheduler) {
+                Ok(scheduler) => return scheduler,
+                Err(shared) if Instant::now() < deadline => scheduler = shared,
+                Err(_) => panic!("tasks still hold the scheduler"),
+            }
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn test_idle_worker_steals_from_blocked_worker() {
+        let config = SchedulerConfig {
+            enable_work_stealing: true,
+            ..test_config(2)
+        };
+
+        let mut scheduler = TaskScheduler::new(config);
+        scheduler.start();
+        let scheduler = Arc::new(scheduler);
+
+        // Tasks spawned from inside a task land on that worker's own queue,
+        // and the worker then blocks, so only the other worker can run them
+        let worker_threads = Arc::new(Mutex::new(std::collections::HashSet::new()));
+        let (spawned_tx, spawned_rx) = std::sync::mpsc::channel();
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        let spawner = {
+            let inner_scheduler = Arc::clone(&scheduler);
+            let worker_threads = Arc::clone(&worker_threads);
+            scheduler.submit_with_result(move || {
+                let handles: Vec<_> = (0..10)
+                    .map(|_| {
+                        let worker_threads = Arc::clone(&worker_threads);
+                        inner_scheduler.submit_with_result(move || {
+                            worker_threads.lock().unwrap().insert(thread::current().id());
+                        }).unwrap()
+                    })
+                    .collect();
+                spawned_tx.send(handles).unwrap();
+                let _ = release_rx.recv();
+                thread::current().id()
+            }).unwrap()
+        };
+
+        let handles = spawned_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        for handle in handles {
+            handle
+                .join_timeout(Duration::from_secs(5))
+                .expect("work queued behind the blocked worker was never stolen")
+                .unwrap();
+        }
+
+        release_tx.send(()).unwrap();
+        let spawner_thread = spawner.join().unwrap();
+        let worker_threads = worker_threads.lock().unwrap();
+        assert_eq!(worker_threads.len(), 1);
+        assert!(!worker_threads.contains(&spawner_thread));
+        drop(worker_threads);
+
+        let stats = scheduler.stats();
+        assert_eq!(stats.workers.iter().map(|worker| worker.stolen_by).sum::<usize>(), 10);
+        assert_eq!(stats.workers.iter().map(|worker| worker.stolen_from).max(), Some(10));
+
+        sole_owner(scheduler).shutdown();
+    }
+
+    #[test]
+    fn test_nested_tasks_run_on_worker_queues() {
+        let config = test_config(4);
+
+        let mut scheduler = TaskScheduler::new(config);
+        scheduler.start();
+
+        // A small fan-out tree: every task below the root is spawned by a worker
+        fn spawn_tree(group: &TaskGroup<()>, depth: u32) {
+            if depth == 0 {
+                return;
+            }
+            for _ in 0..4 {
+                let parent = group.clone();
+                group.submit(move || spawn_tree(&parent, depth - 1)).unwrap();
+            }
+        }
+
+        let group = scheduler.group();
+        let parent = group.clone();
+        group.submit(move || spawn_tree(&parent, 5)).unwrap();
+
+        // 1 + 4 + 16 + ... + 4^5 tasks; the injector only ever saw the root
+        let expected = (0..=5).map(|depth| 4usize.pow(depth)).sum::<usize>();
+        let results = group
+            .wait_all_timeout(Duration::from_secs(10))
+            .expect("task tree never finished");
+        assert_eq!(results.completed.len(), expected);
+
+        let report = scheduler.shutdown_drain();
+        assert_eq!(report.completed, expected);
+        assert_eq!(report.dropped, 0);
+    }
+}
@@ -0,0 +1,126 @@
+use super::SchedulerState;
+
+impl SchedulerState {
+    /// The CPUs the worker in `slot` is pinned to, if `cpu_sets` is configured
+    pub(super) fn cpu_set(&self, slot: usize) -> Option<&[usize]> {
+        if self.cpu_sets.is_empty() {
+            return None;
+        }
+        Some(&self.cpu_sets[slot % self.cpu_sets.len()])
+    }
+
+    /// Whether two workers share a CPU set, so stealing between them keeps
+    /// data in the same caches; always true when workers are not pinned
+    pub(super) fn same_core_group(&self, a: usize, b: usize) -> bool {
+        self.cpu_set(a) == self.cpu_set(b)
+    }
+}
+
+/// Restrict the calling thread to `cpus`; returns whether it worked
+#[cfg(target_os = "linux")]
+pub(super) fn pin_current_thread(cpus: &[usize]) -> bool {
+    /// Same layout as glibc's `cpu_set_t`, which has room for 1024 CPUs
+    #[repr(C)]
+    struct CpuSet([u64; 16]);
+
+    unsafe extern "C" {
+        fn sched_setaffinity(pid: i32, cpusetsize: usize, mask: *const CpuSet) -> i32;
+    }
+
+    let mut set = CpuSet([0; 16]);
+    for &cpu in cpus {
+        let Some(word) = set.0.get_mut(cpu / 64) else {
+            return false;
+        };
+        *word |= 1 << (cpu % 64);
+    }
+
+    // SAFETY: pid 0 is the calling thread, and `set` is a valid `cpu_set_t`
+    // of the size passed, which the kernel only reads
+    !cpus.is_empty() && unsafe { sched_setaffinity(0, std::mem::size_of::<CpuSet>(), &set) } == 0
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(super) fn pin_current_thread(_cpus: &[usize]) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::task_scheduler::{SchedulerConfig, SchedulerError, TaskScheduler, test_config};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    #[test]
+    fn test_workers_are_named_and_initialised_on_their_threads() {
+        let initialised = Arc::new(Mutex::new(Vec::new()));
+        let initialised_clone = Arc::clone(&initialised);
+        let config = SchedulerConfig {
+            thread_name_prefix: "pool".to_string(),
+            stack_size: 4 * 1024 * 1024,
+            worker_init: Some(Arc::new(move |worker_id| {
+                let name = thread::current().name().map(str::to_string);
+                initialised_clone.lock().unwrap().push((worker_id, name));
+            })),
+            ..test_config(2)
+        };
+
+        let mut scheduler = TaskScheduler::new(config);
+        scheduler.start();
+
+        let name = scheduler
+            .submit_with_result(|| thread::current().name().map(str::to_string))
+            .unwrap()
+            .join()
+            .unwrap();
+        assert!(matches!(name.as_deref(), Some("pool-0" | "pool-1")));
+
+        scheduler.shutdown();
+        let mut initialised = initialised.lock().unwrap().clone();
+        initialised.sort();
+        assert_eq!(
+            initialised,
+            vec![(0, Some("pool-0".to_string())), (1, Some("pool-1".to_string()))]
+        );
+    }
+
+    #[test]
+    fn test_failed_pinning_is_reported_and_the_worker_still_runs() {
+        let config = SchedulerConfig {
+            cpu_sets: vec![vec![100_000]],
+            event_sink: Arc::new(|_: &_| {}),
+            ..test_config(1)
+        };
+
+        let mut scheduler = TaskScheduler::new(config);
+        scheduler.start();
+        let ran = Arc::new(AtomicUsize::new(0));
+        let ran_clone = Arc::clone(&ran);
+        scheduler.submit(move || {
+            ran_clone.fetch_add(1, Ordering::SeqCst);
+        }).unwrap();
+
+        let report = scheduler.shutdown_drain();
+        assert_eq!(ran.load(Ordering::SeqCst), 1);
+        assert_eq!(report.errors, vec![SchedulerError::PinningFailed { id: 0 }]);
+    }
+
+    #[test]
+    fn test_core_groups_follow_the_cpu_sets() {
+        let config = SchedulerConfig {
+            num_workers: 4,
+            cpu_sets: vec![vec![0, 1], vec![2, 3]],
+            ..SchedulerConfig::default()
+        };
+        let scheduler = TaskScheduler::new(config);
+        let state = &scheduler.state;
+
+        assert_eq!(state.cpu_set(3), Some(&[2, 3][..]));
+        assert!(state.same_core_group(0, 2));
+        assert!(!state.same_core_group(0, 1));
+
+        let unpinned = TaskScheduler::new(SchedulerConfig::default());
+        assert!(unpinned.state.same_core_group(0, 1));
+    }
+}
@@ -0,0 +1,444 @@
+use std::fmt;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::{CancellationToken, Job, LOCAL_QUEUE, Priority, ScheduledTask, SchedulerError, SchedulerState, TaskScheduler, TaskTags};
+
+/// What happens to a task submitted while the scheduler is at `queue_capacity`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectionPolicy {
+    /// Refuse the task with an error
+    Reject,
+    /// Run the task on the submitting thread before returning, which also
+    /// slows the submitter down to the pool's pace
+    CallerRuns,
+    /// Make room by dropping the oldest of the least urgent tasks waiting in
+    /// the injector, or else in the fullest worker inbox the placement
+    /// strategy queued for, from the tenant with the most of them; rejects
+    /// the task if there is none. Tasks on a worker's own queue are never
+    /// dropped.
+    DropOldest,
+}
+
+/// A task `try_submit` or `submit_timeout` did not accept, handed back to the caller
+///
+/// Tasks the rejection policy runs or makes room for are accepted, so only
+/// `RejectionPolicy::Reject`, or `DropOldest` with nothing to drop, hands
+/// back `QueueFull`.
+pub enum TrySubmitError<F> {
+    /// The queues were at capacity
+    QueueFull(F),
+    /// The scheduler is shutting down
+    ShuttingDown(F),
+}
+
+impl<F> TrySubmitError<F> {
+    /// Whether the task was refused because the queues were full
+    pub fn is_full(&self) -> bool {
+        matches!(self, TrySubmitError::QueueFull(_))
+    }
+
+    /// Take back the rejected closure, e.g. to retry or run it elsewhere
+    pub fn into_task(self) -> F {
+        match self {
+            TrySubmitError::QueueFull(task) | TrySubmitError::ShuttingDown(task) => task,
+        }
+    }
+}
+
+impl<F> fmt::Debug for TrySubmitError<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrySubmitError::QueueFull(_) => f.write_str("QueueFull(..)"),
+            TrySubmitError::ShuttingDown(_) => f.write_str("ShuttingDown(..)"),
+        }
+    }
+}
+
+impl<F> fmt::Display for TrySubmitError<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrySubmitError::QueueFull(_) => write!(f, "task queue is full"),
+            TrySubmitError::ShuttingDown(_) => write!(f, "scheduler is shutting down"),
+        }
+    }
+}
+
+impl<F> std::error::Error for TrySubmitError<F> {}
+
+impl<F> From<TrySubmitError<F>> for SchedulerError {
+    fn from(error: TrySubmitError<F>) -> Self {
+        match error {
+            TrySubmitError::QueueFull(_) => SchedulerError::QueueFull,
+            TrySubmitError::ShuttingDown(_) => SchedulerError::ShuttingDown,
+        }
+    }
+}
+
+impl SchedulerState {
+    /// Whether the current thread is one of this scheduler's workers
+    fn on_worker(&self) -> bool {
+        LOCAL_QUEUE.with(|local| {
+            local
+                .borrow()
+                .as_ref()
+                .is_some_and(|local| std::ptr::eq(local.state, self))
+        })
+    }
+
+    /// Count a task as outstanding if there is room for it
+    pub(super) fn try_reserve(&self) -> bool {
+        if self.queue_capacity == 0 {
+            self.outstanding_tasks.fetch_add(1, Ordering::SeqCst);
+            return true;
+        }
+
+        self.outstanding_tasks
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |outstanding| {
+                (outstanding < self.queue_capacity).then_some(outstanding + 1)
+            })
+            .is_ok()
+    }
+
+    /// Wait until there is room for a task, or `deadline` passes
+    ///
+    /// Workers never wait, since only workers can make room.
+    fn reserve_until(&self, deadline: Option<Instant>) -> bool {
+        if self.try_reserve() {
+            return true;
+        }
+        if self.on_worker() {
+            return false;
+        }
+
+        // Registered before checking again, so `task_finished` either leaves
+        // room for that check or sees us waiting and notifies
+        self.waiting_submitters.fetch_add(1, Ordering::SeqCst);
+        let mut guard = self.outstanding_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let reserved = loop {
+            if self.try_reserve() {
+                break true;
+            }
+
+            guard = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break false;
+                    }
+                    self.outstanding_condvar
+                        .wait_timeout(guard, deadline - now)
+                        .map(|(guard, _)| guard)
+                        .unwrap_or_else(|e| e.into_inner().0)
+                }
+                None => self.outstanding_condvar.wait(guard).unwrap_or_else(|e| e.into_inner()),
+            };
+        };
+        drop(guard);
+        self.waiting_submitters.fetch_sub(1, Ordering::SeqCst);
+        reserved
+    }
+
+    /// Apply the rejection policy to a job that did not fit
+    pub(super) fn reject(
+        &self,
+        task: Job,
+        priority: Priority,
+        tags: TaskTags,
+        cancel_token: CancellationToken,
+    ) -> Result<u64, SchedulerError> {
+        match self.rejection_policy {
+            RejectionPolicy::Reject => {}
+            RejectionPolicy::CallerRuns => {
+                self.accepting()?;
+                self.outstanding_tasks.fetch_add(1, Ordering::SeqCst);
+                let task_id = self.next_task_id();
+                let mut scheduled_task = ScheduledTask::new(task_id, task, priority, cancel_token, self.now());
+                // Class limits do not apply on the caller, but the tenant still counts it
+                scheduled_task.metadata.tags.tenant = tags.tenant;
+                self.tenants.submitted(tags.tenant);
+                TaskScheduler::run_on_caller(self, scheduled_task);
+                return Ok(task_id);
+            }
+            RejectionPolicy::DropOldest => {
+                if let Some(evicted) = self.evict_oldest() {
+                    // The evicted task's place in the count goes to the new one
+                    evicted.cancel_token.cancel();
+                    drop(evicted);
+                    self.rejected_tasks.fetch_add(1, Ordering::SeqCst);
+                    return self.enqueue_reserved(task, priority, tags, cancel_token);
+                }
+            }
+        }
+
+        self.rejected_tasks.fetch_add(1, Ordering::SeqCst);
+        Err(SchedulerError::QueueFull)
+    }
+
+    /// The task `DropOldest` gives up: from the injector, or else from the
+    /// fullest placement inbox
+    fn evict_oldest(&self) -> Option<ScheduledTask> {
+        self.injector.evict().or_else(|| {
+            let slot = self
+                .slots
+                .iter()
+                .filter(|slot| !slot.inbox.is_empty())
+                .max_by_key(|slot| slot.inbox.len())?;
+            slot.inbox.evict()
+        })
+    }
+}
+
+impl TaskScheduler {
+    /// Submit a task, handing it back at once if the queues are at capacity
+    pub fn try_submit<F>(&self, task: F) -> Result<u64, TrySubmitError<F>>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.submit_before(task, Some(Instant::now()))
+    }
+
+    /// Submit a task, waiting up to `timeout` for room in the queues
+    ///
+    /// On a worker thread this does not wait, like `try_submit`. A timeout
+    /// too large to add to the current time waits like `submit_blocking`.
+    pub fn submit_timeout<F>(&self, task: F, timeout: Duration) -> Result<u64, TrySubmitError<F>>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.submit_before(task, Instant::now().checked_add(timeout))
+    }
+
+    /// Submit a task, waiting as long as it takes for room in the queues
+    ///
+    /// On a worker thread this does not wait, since only workers can make
+    /// room, and fails if the queues are full.
+    pub fn submit_blocking<F>(&self, task: F) -> Result<u64, SchedulerError>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.submit_before(task, None).map_err(SchedulerError::from)
+    }
+
+    /// Accept `task` once there is room before `deadline`, or else apply the
+    /// rejection policy; a task that is not accepted is handed back
+    fn submit_before<F>(&self, task: F, deadline: Option<Instant>) -> Result<u64, TrySubmitError<F>>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        // Shared with the job, so the closure can be taken back if the job
+        // is refused without running
+        let slot = Arc::new(Mutex::new(Some(task)));
+        let job_slot = Arc::clone(&slot);
+        let job = Self::job(move || {
+            if let Some(task) = job_slot.lock().unwrap_or_else(|e| e.into_inner()).take() {
+                task();
+            }
+        });
+
+        let result = if self.state.reserve_until(deadline) {
+            self.state.enqueue_reserved(job, Priority::NORMAL, TaskTags::default(), CancellationToken::new())
+        } else {
+            self.state.reject(job, Priority::NORMAL, TaskTags::default(), CancellationToken::new())
+        };
+
+        result.map_err(|error| {
+            let task = slot
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .take()
+                .expect("a refused task never ran");
+            match error {
+                SchedulerError::QueueFull => TrySubmitError::QueueFull(task),
+                _ => TrySubmitError::ShuttingDown(task),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task_scheduler::{JoinError, RoundRobin, SchedulerConfig, test_config};
+    use std::sync::mpsc::{self, Sender};
+    use std::thread;
+
+    fn bounded_scheduler(queue_capacity: usize, rejection_policy: RejectionPolicy) -> TaskScheduler {
+        let config = SchedulerConfig {
+            queue_capacity,
+            rejection_policy,
+            ..test_config(1)
+        };
+
+        let mut scheduler = TaskScheduler::new(config);
+        scheduler.start();
+        scheduler
+    }
+
+    /// Occupy the only worker until the returned sender is used or dropped
+    fn block_worker(scheduler: &TaskScheduler) -> Sender<()> {
+        let (started_tx, started_rx) = mpsc::channel::<()>();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        scheduler
+            .submit(move || {
+                started_tx.send(()).unwrap();
+                let _ = release_rx.recv();
+            })
+            .unwrap();
+        started_rx.recv().unwrap();
+        release_tx
+    }
+
+    #[test]
+    fn test_full_queue_rejects_and_hands_the_task_back() {
+        let scheduler = bounded_scheduler(2, RejectionPolicy::Reject);
+        let release = block_worker(&scheduler);
+        scheduler.submit(|| {}).unwrap();
+
+        let (ran_tx, ran_rx) = mpsc::channel();
+        let error = scheduler.try_submit(move || ran_tx.send("retried").unwrap()).unwrap_err();
+        assert!(error.is_full());
+        error.into_task()();
+        assert_eq!(ran_rx.try_recv(), Ok("retried"));
+        assert_eq!(scheduler.submit(|| {}), Err(SchedulerError::QueueFull));
+        assert_eq!(scheduler.stats().rejected, 2);
+
+        drop(release);
+        let report = scheduler.shutdown_drain();
+        assert_eq!(report.completed, 2);
+    }
+
+    #[test]
+    fn test_timed_submit_gives_up_and_blocking_submit_waits_for_room() {
+        let scheduler = bounded_scheduler(1, RejectionPolicy::Reject);
+        let release = block_worker(&scheduler);
+
+        let started = Instant::now();
+        let error = scheduler.submit_timeout(|| {}, Duration::from_millis(20)).unwrap_err();
+        assert!(error.is_full());
+        assert!(started.elapsed() >= Duration::from_millis(20));
+
+        let releaser = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(30));
+            release.send(()).unwrap();
+        });
+        let started = Instant::now();
+        scheduler.submit_blocking(|| {}).unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(20));
+        releaser.join().unwrap();
+
+        // A timeout past the end of `Instant` waits as long as `submit_blocking`
+        scheduler.submit_timeout(|| {}, Duration::MAX).unwrap();
+
+        let report = scheduler.shutdown_drain();
+        assert_eq!(report.completed, 3);
+    }
+
+    #[test]
+    fn test_caller_runs_policy_runs_the_task_on_the_submitter() {
+        let scheduler = bounded_scheduler(1, RejectionPolicy::CallerRuns);
+        let release = block_worker(&scheduler);
+
+        let (ran_on_tx, ran_on_rx) = mpsc::channel();
+        let try_ran_on_tx = ran_on_tx.clone();
+        scheduler.submit(move || ran_on_tx.send(thread::current().id()).unwrap()).unwrap();
+        // Already done by the time `submit` returns
+        assert_eq!(ran_on_rx.try_recv(), Ok(thread::current().id()));
+
+        // The policy applies to every submit variant
+        scheduler.try_submit(move || try_ran_on_tx.send(thread::current().id()).unwrap()).unwrap();
+        assert_eq!(ran_on_rx.try_recv(), Ok(thread::current().id()));
+
+        drop(release);
+        let report = scheduler.shutdown_drain();
+        assert_eq!(report.completed, 3);
+    }
+
+    #[test]
+    fn test_drop_oldest_policy_evicts_the_least_urgent_queued_task() {
+        let scheduler = bounded_scheduler(3, RejectionPolicy::DropOldest);
+        let release = block_worker(&scheduler);
+
+        let low = scheduler.submit_with_result_and_priority(Priority::LOW, || "low").unwrap();
+        let high = scheduler.submit_with_result_and_priority(Priority::HIGH, || "high").unwrap();
+        let normal = scheduler.submit_with_result(|| "normal").unwrap();
+
+        drop(release);
+        assert!(matches!(low.join(), Err(JoinError::Cancelled | JoinError::Dropped)));
+        assert_eq!(high.join().unwrap(), "high");
+        assert_eq!(normal.join().unwrap(), "normal");
+        assert_eq!(scheduler.stats().rejected, 1);
+        scheduler.shutdown();
+    }
+
+    #[test]
+    fn test_drop_oldest_policy_evicts_from_placement_inboxes() {
+        let config = SchedulerConfig {
+            queue_capacity: 3,
+            rejection_policy: RejectionPolicy::DropOldest,
+            placement: Some(Arc::new(RoundRobin::default())),
+            ..test_config(1)
+        };
+        let mut scheduler = TaskScheduler::new(config);
+        scheduler.start();
+        let release = block_worker(&scheduler);
+
+        // Placed in the worker's inbox, so the injector stays empty
+        let low = scheduler.submit_with_result_and_priority(Priority::LOW, || "low").unwrap();
+        scheduler.submit(|| {}).unwrap();
+        assert_eq!(scheduler.stats().injector_depth, 0);
+        scheduler.try_submit(|| {}).unwrap();
+
+        drop(release);
+        assert!(matches!(low.join(), Err(JoinError::Cancelled | JoinError::Dropped)));
+        assert_eq!(scheduler.stats().rejected, 1);
+        let report = scheduler.shutdown_drain();
+        assert_eq!(report.completed, 3);
+    }
+
+    #[test]
+    fn test_tasks_spawned_past_worker_queue_capacity_go_to_the_injector() {
+        let config = SchedulerConfig {
+            worker_queue_capacity: 2,
+            ..test_config(1)
+        };
+        let mut scheduler = TaskScheduler::new(config);
+        scheduler.start();
+        let scheduler = Arc::new(scheduler);
+
+        let scheduler_clone = Arc::clone(&scheduler);
+        let handle = scheduler
+            .submit_with_result(move || {
+                for _ in 0..10 {
+                    scheduler_clone.submit(|| {}).unwrap();
+                }
+                let stats = scheduler_clone.stats();
+                (stats.workers[0].queue_depth, stats.injector_depth)
+            })
+            .unwrap();
+
+        assert_eq!(handle.join().unwrap(), (2, 8));
+    }
+
+    #[test]
+    fn test_injector_batches_respect_worker_queue_capacity() {
+        let config = SchedulerConfig {
+            worker_queue_capacity: 2,
+            simulation_seed: Some(1),
+            ..test_config(1)
+        };
+        let mut scheduler = TaskScheduler::new(config);
+        scheduler.start();
+
+        for _ in 0..10 {
+            scheduler.submit(|| {}).unwrap();
+        }
+        assert!(scheduler.step());
+        let stats = scheduler.stats();
+        assert_eq!((stats.workers[0].queue_depth, stats.injector_depth), (2, 7));
+
+        assert!(scheduler.run_until_idle());
+        assert_eq!(scheduler.shutdown().completed, 10);
+    }
+}
@@ -0,0 +1,360 @@
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::atomic::{AtomicIsize, AtomicPtr, Ordering, fence};
+use std::sync::{Arc, Mutex};
+
+/// Slots allocated for a new deque; the buffer doubles whenever it fills up
+const MIN_CAPACITY: usize = 32;
+
+/// Outcome of a steal attempt
+pub(crate) enum Steal<T> {
+    Empty,
+    Success(T),
+    /// Lost a race with the owner or another thief; the deque may still have items
+    Retry,
+}
+
+/// Circular array of slots; the capacity is always a power of two
+struct Buffer<T> {
+    slots: *mut MaybeUninit<T>,
+    capacity: usize,
+}
+
+impl<T> Buffer<T> {
+    fn alloc(capacity: usize) -> *mut Buffer<T> {
+        let slots: Box<[MaybeUninit<T>]> = (0..capacity).map(|_| MaybeUninit::uninit()).collect();
+        Box::into_raw(Box::new(Buffer {
+            slots: Box::into_raw(slots) as *mut MaybeUninit<T>,
+            capacity,
+        }))
+    }
+
+    unsafe fn slot(&self, index: isize) -> *mut MaybeUninit<T> {
+        unsafe { self.slots.add(index as usize & (self.capacity - 1)) }
+    }
+
+    unsafe fn write(&self, index: isize, value: T) {
+        unsafe { ptr::write_volatile(self.slot(index), MaybeUninit::new(value)) }
+    }
+
+    /// Bitwise copy of a slot; the caller decides whether it now owns the value
+    unsafe fn read(&self, index: isize) -> MaybeUninit<T> {
+        unsafe { ptr::read_volatile(self.slot(index)) }
+    }
+}
+
+impl<T> Drop for Buffer<T> {
+    fn drop(&mut self) {
+        // Frees the slots without dropping their contents; `Inner` drops the live ones
+        unsafe {
+            drop(Box::from_raw(ptr::slice_from_raw_parts_mut(self.slots, self.capacity)));
+        }
+    }
+}
+
+/// State shared by the owner and every thief
+///
+/// Items live at indices `top..bottom`. The owner pushes and pops at
+/// `bottom`; thieves take from `top` with a CAS, as in Chase and Lev's
+/// "Dynamic Circular Work-Stealing Deque" with the orderings from Lê et al.
+struct Inner<T> {
+    top: AtomicIsize,
+    bottom: AtomicIsize,
+    buffer: AtomicPtr<Buffer<T>>,
+    /// Buffers replaced while growing; a thief may still be reading one, so
+    /// they are only freed with the deque
+    retired: Mutex<Vec<*mut Buffer<T>>>,
+}
+
+unsafe impl<T: Send> Send for Inner<T> {}
+unsafe impl<T: Send> Sync for Inner<T> {}
+
+impl<T> Inner<T> {
+    fn len(&self) -> usize {
+        let bottom = self.bottom.load(Ordering::SeqCst);
+        let top = self.top.load(Ordering::SeqCst);
+        (bottom - top).max(0) as usize
+    }
+}
+
+impl<T> Drop for Inner<T> {
+    fn drop(&mut self) {
+        let top = *self.top.get_mut();
+        let bottom = *self.bottom.get_mut();
+        let buffer = *self.buffer.get_mut();
+
+        unsafe {
+            for index in top..bottom {
+                drop((*buffer).read(index).assume_init());
+            }
+            drop(Box::from_raw(buffer));
+
+            let retired = self.retired.get_mut().unwrap_or_else(|e| e.into_inner());
+            for old in retired.drain(..) {
+                drop(Box::from_raw(old));
+            }
+        }
+    }
+}
+
+/// Owner end of a work-stealing deque
+///
+/// Only the thread holding the `Worker` may push and pop, so it is `Send`
+/// but not `Sync`. Pops are LIFO; thieves steal the oldest items.
+pub(crate) struct Worker<T> {
+    inner: Arc<Inner<T>>,
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+/// Thief end of a work-stealing deque; cheap to clone and share
+pub(crate) struct Stealer<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Worker<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                top: AtomicIsize::new(0),
+                bottom: AtomicIsize::new(0),
+                buffer: AtomicPtr::new(Buffer::alloc(MIN_CAPACITY)),
+                retired: Mutex::new(Vec::new()),
+            }),
+            _not_sync: PhantomData,
+        }
+    }
+
+    pub(crate) fn stealer(&self) -> Stealer<T> {
+        Stealer {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+
+    pub(crate) fn push(&self, value: T) {
+        let bottom = self.inner.bottom.load(Ordering::Relaxed);
+        let top = self.inner.top.load(Ordering::Acquire);
+        let mut buffer = self.inner.buffer.load(Ordering::Relaxed);
+
+        unsafe {
+            if bottom - top >= (*buffer).capacity as isize {
+                buffer = self.grow(buffer, top, bottom);
+            }
+            (*buffer).write(bottom, value);
+        }
+
+        // Publishes the slot to thieves that observe the new bottom
+        self.inner.bottom.store(bottom + 1, Ordering::Release);
+    }
+
+    pub(crate) fn pop(&self) -> Option<T> {
+        let bottom = self.inner.bottom.load(Ordering::Relaxed) - 1;
+        let buffer = self.inner.buffer.load(Ordering::Relaxed);
+
+        // Reserve the bottom slot before looking at top, so a thief either
+        // sees the reservation or we see its increment
+        self.inner.bottom.store(bottom, Ordering::Relaxed);
+        fence(Ordering::SeqCst);
+        let top = self.inner.top.load(Ordering::Relaxed);
+
+        if top > bottom {
+            self.inner.bottom.store(bottom + 1, Ordering::Relaxed);
+            return None;
+        }
+
+        let value = unsafe { (*buffer).read(bottom) };
+        if top == bottom {
+            // Last item: thieves may be after it too, so race them for it
+            let won = self
+                .inner
+                .top
+                .compare_exchange(top, top + 1, Ordering::SeqCst, Ordering::Relaxed)
+                .is_ok();
+            self.inner.bottom.store(bottom + 1, Ordering::Relaxed);
+            if !won {
+                return None;
+            }
+        }
+
+        Some(unsafe { value.assume_init() })
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Move the live items into a buffer twice the size
+    unsafe fn grow(&self, old: *mut Buffer<T>, top: isize, bottom: isize) -> *mut Buffer<T> {
+        unsafe {
+            let new = Buffer::alloc((*old).capacity * 2);
+            for index in top..bottom {
+                ptr::copy_nonoverlapping((*old).slot(index), (*new).slot(index), 1);
+            }
+
+            self.inner.buffer.store(new, Ordering::Release);
+            self.inner
+                .retired
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(old);
+            new
+        }
+    }
+}
+
+impl<T> Stealer<T> {
+    pub(crate) fn steal(&self) -> Steal<T> {
+        let top = self.inner.top.load(Ordering::Acquire);
+        fence(Ordering::SeqCst);
+        let bottom = self.inner.bottom.load(Ordering::Acquire);
+
+        if top >= bottom {
+            return Steal::Empty;
+        }
+
+        // The slot may be overwritten as soon as another thread moves top,
+        // so only claim the copy if the CAS succeeds
+        let buffer = self.inner.buffer.load(Ordering::Acquire);
+        let value = unsafe { (*buffer).read(top) };
+        if self
+            .inner
+            .top
+            .compare_exchange(top, top + 1, Ordering::SeqCst, Ordering::Relaxed)
+            .is_err()
+        {
+            return Steal::Retry;
+        }
+
+        Steal::Success(unsafe { value.assume_init() })
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Clone for Stealer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize};
+    use std::thread;
+
+    fn steal_now<T>(stealer: &Stealer<T>) -> Option<T> {
+        loop {
+            match stealer.steal() {
+                Steal::Success(value) => return Some(value),
+                Steal::Empty => return None,
+                Steal::Retry => {}
+            }
+        }
+    }
+
+    #[test]
+    fn test_owner_pops_newest_and_thieves_steal_oldest() {
+        let worker = Worker::new();
+        let stealer = worker.stealer();
+        for i in 0..4 {
+            worker.push(i);
+        }
+
+        assert_eq!(steal_now(&stealer), Some(0));
+        assert_eq!(worker.pop(), Some(3));
+        assert_eq!(worker.len(), 2);
+        assert_eq!(steal_now(&stealer), Some(1));
+        assert_eq!(worker.pop(), Some(2));
+        assert_eq!(worker.pop(), None);
+        assert_eq!(steal_now(&stealer), None);
+    }
+
+    #[test]
+    fn test_deque_grows_past_initial_capacity() {
+        let worker = Worker::new();
+        let stealer = worker.stealer();
+        for i in 0..MIN_CAPACITY * 5 {
+            worker.push(i);
+        }
+
+        // Steal a few so the live range wraps before the next growth
+        assert_eq!(steal_now(&stealer), Some(0));
+        assert_eq!(steal_now(&stealer), Some(1));
+        for i in MIN_CAPACITY * 5..MIN_CAPACITY * 9 {
+            worker.push(i);
+        }
+
+        let mut popped: Vec<usize> = std::iter::from_fn(|| worker.pop()).collect();
+        popped.reverse();
+        assert_eq!(popped, (2..MIN_CAPACITY * 9).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_concurrent_thieves_take_every_item_exactly_once() {
+        const ITEMS: usize = 50_000;
+        let worker = Worker::<usize>::new();
+        let done = Arc::new(AtomicBool::new(false));
+        let seen: Arc<Vec<AtomicUsize>> = Arc::new((0..ITEMS).map(|_| AtomicUsize::new(0)).collect());
+
+        let thieves: Vec<_> = (0..3)
+            .map(|_| {
+                let stealer = worker.stealer();
+                let done = Arc::clone(&done);
+                let seen = Arc::clone(&seen);
+                thread::spawn(move || {
+                    while !done.load(Ordering::SeqCst) || !stealer.is_empty() {
+                        if let Steal::Success(item) = stealer.steal() {
+                            seen[item].fetch_add(1, Ordering::SeqCst);
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        // Interleave pushes and pops so the owner races thieves for the last item
+        for item in 0..ITEMS {
+            worker.push(item);
+            if item % 3 == 0
+                && let Some(popped) = worker.pop()
+            {
+                seen[popped].fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        while let Some(popped) = worker.pop() {
+            seen[popped].fetch_add(1, Ordering::SeqCst);
+        }
+
+        done.store(true, Ordering::SeqCst);
+        for thief in thieves {
+            thief.join().unwrap();
+        }
+
+        assert!(seen.iter().all(|count| count.load(Ordering::SeqCst) == 1));
+    }
+
+    #[test]
+    fn test_dropping_deque_drops_remaining_items() {
+        let item = Arc::new(());
+        let worker = Worker::new();
+        let stealer = worker.stealer();
+        for _ in 0..MIN_CAPACITY * 2 {
+            worker.push(Arc::clone(&item));
+        }
+        drop(steal_now(&stealer));
+
+        drop(worker);
+        assert_eq!(Arc::strong_count(&item), MIN_CAPACITY * 2);
+        drop(stealer);
+        assert_eq!(Arc::strong_count(&item), 1);
+    }
+}
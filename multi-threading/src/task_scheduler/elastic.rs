@@ -0,0 +1,78 @@
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use super::{SchedulerConfig, SchedulerError, SchedulerState, TaskScheduler};
+
+impl SchedulerState {
+    /// Occupied slots whose thread has not been asked to retire
+    fn live_workers(&self) -> usize {
+        self.slots
+            .iter()
+            .filter(|slot| slot.is_occupied() && !slot.retiring.load(Ordering::SeqCst))
+            .count()
+    }
+
+    /// How long queued work has gone without a worker picking it up
+    ///
+    /// Worker queues cannot be inspected in place, so a non-empty one counts
+    /// as waiting for as long as its owner has been busy on its current task.
+    fn backlog_wait(&self, now: Instant) -> Duration {
+        let shared_wait = std::iter::once(&self.injector)
+            .chain(self.slots.iter().map(|slot| &slot.inbox))
+            .filter_map(|queue| queue.oldest_wait(now))
+            .fold(Duration::ZERO, Duration::max);
+
+        self.slots
+            .iter()
+            .filter(|slot| !slot.stealer.is_empty())
+            .filter_map(|slot| {
+                let running = slot.running.lock().ok()?;
+                running
+                    .as_ref()
+                    .map(|task| now.saturating_duration_since(task.started_at))
+            })
+            .fold(shared_wait, Duration::max)
+    }
+}
+
+impl TaskScheduler {
+    /// Grow the pool by one worker while work waits too long, or retire one
+    /// worker that has been idle past its keep-alive
+    ///
+    /// One change per supervisor check keeps the pool from overshooting on a
+    /// burst that is already being absorbed.
+    pub(super) fn scale_pool(state: &Arc<SchedulerState>, config: &SchedulerConfig, now: Instant) {
+        let live = state.live_workers();
+        let scale_up_wait = Duration::from_millis(config.scale_up_wait_ms);
+
+        if live < config.max_workers() && state.backlog_wait(now) > scale_up_wait {
+            match state.vacant_slot() {
+                Some(worker_id) => Self::spawn_worker(worker_id, state, config),
+                None => state.record_error(None, SchedulerError::NoVacantSlot),
+            }
+            return;
+        }
+
+        if config.worker_keep_alive_ms == 0 || live <= config.min_workers() {
+            return;
+        }
+
+        let keep_alive = Duration::from_millis(config.worker_keep_alive_ms);
+        let idle_worker = state.slots.iter().find(|slot| {
+            slot.is_occupied()
+                && !slot.retiring.load(Ordering::SeqCst)
+                && slot
+                    .idle_since
+                    .lock()
+                    .map(|idle_since| idle_since.is_some_and(|since| now.saturating_duration_since(since) > keep_alive))
+                    .unwrap_or(false)
+        });
+
+        // The worker exits on waking, handing anything left in its queue to the injector
+        if let Some(slot) = idle_worker {
+            slot.retiring.store(true, Ordering::SeqCst);
+            state.notify_work(false);
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use std::fmt;
+
+/// Why the scheduler refused a request, or what went wrong inside it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchedulerError {
+    /// The scheduler is shutting down and accepts no new tasks
+    ShuttingDown,
+    /// The queues were at `queue_capacity` and the rejection policy refused the task
+    QueueFull,
+    /// A thread panicked while holding the named lock
+    Poisoned { which: &'static str },
+    /// A worker thread panicked outside of task execution
+    WorkerDied { id: usize },
+    /// The supervisor thread panicked, or could not be started
+    SupervisorDied,
+    /// The OS refused to start a worker thread
+    SpawnFailed { id: usize },
+    /// A worker could not be pinned to its CPU set, and runs unpinned
+    PinningFailed { id: usize },
+    /// Every worker slot was taken when the pool tried to grow or replace a worker
+    NoVacantSlot,
+    /// A periodic task was given a zero interval
+    ZeroInterval,
+    /// A task graph dependency refers to a node from another builder
+    UnknownDependency,
+    /// A task graph node depends on itself
+    SelfDependency,
+    /// A task graph's dependencies form a cycle
+    DependencyCycle,
+    /// Reading or writing the job journal failed
+    Journal { kind: std::io::ErrorKind },
+    /// `submit_job` was called before `open_journal`
+    NoJournal,
+    /// No handler is registered under a job's name
+    UnknownJob,
+    /// `submit_with_class` named a class without limits in `SchedulerConfig::class_limits`
+    UnknownClass,
+    /// `submit_for_tenant` named a tenant missing from `SchedulerConfig::tenant_weights`
+    UnknownTenant,
+}
+
+impl fmt::Display for SchedulerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchedulerError::ShuttingDown => write!(f, "scheduler is shutting down"),
+            SchedulerError::QueueFull => write!(f, "task queue is full"),
+            SchedulerError::Poisoned { which } => write!(f, "{} lock poisoned", which),
+            SchedulerError::WorkerDied { id } => write!(f, "worker {} panicked outside of a task", id),
+            SchedulerError::SupervisorDied => write!(f, "supervisor panicked"),
+            SchedulerError::SpawnFailed { id } => write!(f, "could not start a thread for worker {}", id),
+            SchedulerError::PinningFailed { id } => write!(f, "could not pin worker {} to its CPU set", id),
+            SchedulerError::NoVacantSlot => write!(f, "no vacant worker slot"),
+            SchedulerError::ZeroInterval => write!(f, "periodic task interval must be greater than zero"),
+            SchedulerError::UnknownDependency => write!(f, "task graph dependency refers to an unknown node"),
+            SchedulerError::SelfDependency => write!(f, "task graph node depends on itself"),
+            SchedulerError::DependencyCycle => write!(f, "task graph contains a cycle"),
+            SchedulerError::Journal { kind } => write!(f, "job journal i/o failed: {}", kind),
+            SchedulerError::NoJournal => write!(f, "no job journal is open"),
+            SchedulerError::UnknownJob => write!(f, "no handler is registered for the job"),
+            SchedulerError::UnknownClass => write!(f, "no limits are configured for the task class"),
+            SchedulerError::UnknownTenant => write!(f, "no weight is configured for the tenant"),
+        }
+    }
+}
+
+impl std::error::Error for SchedulerError {}
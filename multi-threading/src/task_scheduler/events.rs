@@ -0,0 +1,212 @@
+use std::fmt;
+use std::time::Duration;
+
+use super::{OverrunningTask, SchedulerError, SchedulerState, TaskFailure};
+
+/// Something worth logging that happened inside the scheduler
+#[derive(Debug, Clone)]
+pub enum SchedulerEvent {
+    /// A task panicked; `worker_id` is `None` when it ran on its submitter
+    TaskPanicked {
+        task_id: u64,
+        worker_id: Option<usize>,
+        stolen: bool,
+    },
+    /// A retried task gave up and went to the dead letters
+    TaskFailed {
+        task_id: u64,
+        worker_id: Option<usize>,
+        attempts: u32,
+        failure: TaskFailure,
+    },
+    /// A task has waited in the injector longer than `timeout_seconds`
+    TaskWaiting {
+        task_id: u64,
+        position: usize,
+        waited: Duration,
+        timeout: Duration,
+    },
+    /// A task has run longer than the execution timeout, under `ExecutionTimeoutAction::Log`
+    TaskOverrunning { task: OverrunningTask, timeout: Duration },
+    /// A worker is being replaced for running a task too long, under `ExecutionTimeoutAction::ReplaceWorker`
+    WorkerStuck { task: OverrunningTask },
+    /// A `SchedulerHooks` method panicked; the scheduler carried on without it
+    HookPanicked { hook: &'static str },
+    /// Something went wrong inside the scheduler; `worker_id` is the worker
+    /// it concerns, or `None` for the supervisor and the scheduler as a whole
+    Error {
+        worker_id: Option<usize>,
+        error: SchedulerError,
+    },
+}
+
+impl fmt::Display for SchedulerEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchedulerEvent::TaskPanicked { task_id, worker_id: Some(worker_id), stolen } => {
+                let kind = if *stolen { "Stolen task" } else { "Task" };
+                write!(f, "Worker {}: {} {} panicked during execution", worker_id, kind, task_id)
+            }
+            SchedulerEvent::TaskPanicked { task_id, worker_id: None, .. } => {
+                write!(f, "Task {} panicked while running on its submitter", task_id)
+            }
+            SchedulerEvent::TaskFailed { task_id, worker_id, attempts, failure } => {
+                if let Some(worker_id) = worker_id {
+                    write!(f, "Worker {}: ", worker_id)?;
+                }
+                write!(f, "Task {} failed after {} attempts ({})", task_id, attempts, failure)
+            }
+            SchedulerEvent::TaskWaiting { task_id, position, waited, timeout } => write!(
+                f,
+                "Warning: Task {} in injector position {} has been waiting for {:?} (timeout: {:?})",
+                task_id, position, waited, timeout
+            ),
+            SchedulerEvent::TaskOverrunning { task, timeout } => write!(
+                f,
+                "Warning: Task {} on worker {} has been running for {:?} (execution timeout: {:?})",
+                task.task_id, task.worker_id, task.running_for, timeout
+            ),
+            SchedulerEvent::WorkerStuck { task } => write!(
+                f,
+                "Warning: Worker {} is stuck on task {} for {:?}, starting a replacement",
+                task.worker_id, task.task_id, task.running_for
+            ),
+            SchedulerEvent::HookPanicked { hook } => write!(f, "Hook {} panicked", hook),
+            SchedulerEvent::Error { worker_id: Some(worker_id), error } => write!(f, "Worker {}: {}", worker_id, error),
+            SchedulerEvent::Error { worker_id: None, error } => write!(f, "Scheduler: {}", error),
+        }
+    }
+}
+
+/// Receives the scheduler's events, e.g. to forward them to a logging framework
+///
+/// Called on worker and supervisor threads as events happen, so it should
+/// return quickly. Any `Fn(&SchedulerEvent)` closure is a sink.
+pub trait EventSink: Send + Sync {
+    fn on_event(&self, event: &SchedulerEvent);
+}
+
+impl<F> EventSink for F
+where
+    F: Fn(&SchedulerEvent) + Send + Sync,
+{
+    fn on_event(&self, event: &SchedulerEvent) {
+        self(event)
+    }
+}
+
+/// The default sink, which prints every event to stderr
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StderrSink;
+
+impl EventSink for StderrSink {
+    fn on_event(&self, event: &SchedulerEvent) {
+        eprintln!("{}", event);
+    }
+}
+
+impl SchedulerState {
+    pub(super) fn emit(&self, event: SchedulerEvent) {
+        self.event_sink.on_event(&event);
+    }
+
+    /// Report an error to the sink and keep it for the shutdown report
+    pub(super) fn record_error(&self, worker_id: Option<usize>, error: SchedulerError) {
+        let mut errors = self.errors.lock().unwrap_or_else(|e| e.into_inner());
+        // Recurring errors, like a full pool on every supervisor check, are kept once
+        if !errors.contains(&error) {
+            errors.push(error);
+        }
+        drop(errors);
+
+        self.emit(SchedulerEvent::Error { worker_id, error });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task_scheduler::{ExecutionTimeoutAction, SchedulerConfig, TaskScheduler, test_config};
+    use std::sync::{Arc, Mutex, mpsc};
+    use std::thread;
+    use std::time::Instant;
+
+    /// A sink that keeps every event, and the scheduler reporting to it
+    fn recording_scheduler(config: SchedulerConfig) -> (TaskScheduler, Arc<Mutex<Vec<SchedulerEvent>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let events_clone = Arc::clone(&events);
+        let config = SchedulerConfig {
+            event_sink: Arc::new(move |event: &SchedulerEvent| events_clone.lock().unwrap().push(event.clone())),
+            ..config
+        };
+
+        let mut scheduler = TaskScheduler::new(config);
+        scheduler.start();
+        (scheduler, events)
+    }
+
+    #[test]
+    fn test_task_panics_go_to_the_event_sink() {
+        let (scheduler, events) = recording_scheduler(test_config(1));
+
+        let task_id = scheduler.submit(|| panic!("expected test panic")).unwrap();
+        let report = scheduler.shutdown_drain();
+        assert_eq!(report.panicked, 1);
+        assert!(report.errors.is_empty());
+
+        let events = events.lock().unwrap();
+        assert!(matches!(
+            events[..],
+            [SchedulerEvent::TaskPanicked { task_id: id, worker_id: Some(0), stolen: false }] if id == task_id
+        ));
+        assert_eq!(events[0].to_string(), format!("Worker 0: Task {} panicked during execution", task_id));
+    }
+
+    #[test]
+    fn test_scheduler_errors_are_reported_and_collected_for_shutdown() {
+        let (scheduler, events) = recording_scheduler(SchedulerConfig {
+            enable_work_stealing: false,
+            execution_timeout_ms: 30,
+            execution_timeout_action: ExecutionTimeoutAction::ReplaceWorker,
+            ..test_config(1)
+        });
+
+        // The first stuck worker is replaced in the only spare slot, which
+        // leaves nowhere to replace the second. Each task is submitted once
+        // the previous one has started, so the replacement is the one to run it.
+        let (started_tx, started_rx) = mpsc::channel::<()>();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let release_rx = Arc::new(Mutex::new(release_rx));
+        for _ in 0..2 {
+            let started_tx = started_tx.clone();
+            let release_rx = Arc::clone(&release_rx);
+            scheduler
+                .submit(move || {
+                    started_tx.send(()).unwrap();
+                    let _ = release_rx.lock().unwrap().recv_timeout(Duration::from_secs(10));
+                })
+                .unwrap();
+            started_rx.recv_timeout(Duration::from_secs(5)).expect("stuck task never started");
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let no_vacant_slot = |events: &[SchedulerEvent]| {
+            events.iter().any(|event| {
+                matches!(event, SchedulerEvent::Error { error: SchedulerError::NoVacantSlot, .. })
+            })
+        };
+        while !no_vacant_slot(&events.lock().unwrap()) {
+            assert!(Instant::now() < deadline, "second stuck worker was never reported");
+            thread::sleep(Duration::from_millis(10));
+        }
+        drop(release_tx);
+
+        let report = scheduler.shutdown();
+        assert_eq!(report.errors, vec![SchedulerError::NoVacantSlot]);
+        assert!(report.to_string().ends_with("error: no vacant worker slot"));
+
+        let events = events.lock().unwrap();
+        let stuck = events.iter().filter(|event| matches!(event, SchedulerEvent::WorkerStuck { .. })).count();
+        assert_eq!(stuck, 2);
+    }
+}
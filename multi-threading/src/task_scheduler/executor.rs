@@ -0,0 +1,387 @@
+use std::future::Future;
+use std::pin::{Pin, pin};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+use super::handle::{self, Completer};
+use super::{
+    CancellationToken, JoinError, Job, Priority, ScheduledTask, SchedulerError, SchedulerState, TaskExit, TaskHandle,
+    TaskScheduler,
+};
+
+/// Waiting for a wake, with no poll queued
+const IDLE: u8 = 0;
+/// A poll is queued on the scheduler
+const SCHEDULED: u8 = 1;
+/// Being polled by a worker
+const RUNNING: u8 = 2;
+/// Woken while being polled, so it is polled again straight after
+const NOTIFIED: u8 = 3;
+/// Finished, or abandoned without finishing
+const DONE: u8 = 4;
+
+/// A spawned future with the sender for its result, type-erased for `FutureTask`
+trait SpawnedFuture: Send {
+    fn poll(&mut self, cx: &mut Context<'_>) -> Poll<TaskExit>;
+}
+
+struct Spawned<F: Future> {
+    future: Pin<Box<F>>,
+    completer: Option<Completer<F::Output>>,
+}
+
+impl<F> SpawnedFuture for Spawned<F>
+where
+    F: Future + Send,
+    F::Output: Send,
+{
+    fn poll(&mut self, cx: &mut Context<'_>) -> Poll<TaskExit> {
+        let (outcome, exit) = match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| self.future.as_mut().poll(cx))) {
+            Ok(Poll::Pending) => return Poll::Pending,
+            Ok(Poll::Ready(value)) => (Ok(value), TaskExit::Completed),
+            Err(payload) => (Err(JoinError::Panicked(payload)), TaskExit::Panicked),
+        };
+        if let Some(completer) = self.completer.take() {
+            completer.complete(outcome);
+        }
+        Poll::Ready(exit)
+    }
+}
+
+/// A future spawned on the scheduler; it is its own waker
+///
+/// The whole future counts as one outstanding task, from `spawn_future` until
+/// it completes. At most one poll of it is queued or running at a time.
+struct FutureTask {
+    id: u64,
+    state: AtomicU8,
+    future: Mutex<Option<Box<dyn SpawnedFuture>>>,
+    scheduler: Weak<SchedulerState>,
+    cancel_token: CancellationToken,
+}
+
+impl FutureTask {
+    /// Queue a poll under the task's id, or drop the future if the scheduler has stopped
+    fn requeue(self: &Arc<Self>) {
+        let Some(state) = self.scheduler.upgrade() else {
+            self.abandon();
+            return;
+        };
+
+        match state.accepting() {
+            Ok(()) => state.push(ScheduledTask::new(
+                self.id,
+                poll_job(Arc::clone(self)),
+                Priority::NORMAL,
+                self.cancel_token.clone(),
+                state.now(),
+            )),
+            Err(_) => {
+                self.abandon();
+                state.task_finished();
+            }
+        }
+    }
+
+    /// Drop the future unfinished; its handle reports it as dropped or cancelled
+    fn abandon(&self) {
+        self.state.store(DONE, Ordering::SeqCst);
+        let future = self.future.lock().unwrap_or_else(|e| e.into_inner()).take();
+        drop(future);
+    }
+
+    fn poll(self: Arc<Self>) -> TaskExit {
+        self.state.store(RUNNING, Ordering::SeqCst);
+        let waker = Waker::from(Arc::clone(&self));
+        let mut cx = Context::from_waker(&waker);
+
+        let mut slot = self.future.lock().unwrap_or_else(|e| e.into_inner());
+        let poll = match slot.as_mut() {
+            Some(future) => future.poll(&mut cx),
+            // Only queued polls take the future, and there is one at a time
+            None => Poll::Ready(TaskExit::Completed),
+        };
+
+        match poll {
+            Poll::Ready(exit) => {
+                self.state.store(DONE, Ordering::SeqCst);
+                let future = slot.take();
+                drop(slot);
+                drop(future);
+                exit
+            }
+            Poll::Pending => {
+                drop(slot);
+                if self
+                    .state
+                    .compare_exchange(RUNNING, IDLE, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_err()
+                {
+                    // Woken mid-poll; go to the back of the queue rather than
+                    // polling again on the spot
+                    self.state.store(SCHEDULED, Ordering::SeqCst);
+                    self.requeue();
+                }
+                TaskExit::Pending
+            }
+        }
+    }
+}
+
+impl Wake for FutureTask {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        let mut current = self.state.load(Ordering::SeqCst);
+        loop {
+            let next = match current {
+                IDLE => SCHEDULED,
+                RUNNING => NOTIFIED,
+                _ => return,
+            };
+            match self.state.compare_exchange(current, next, Ordering::SeqCst, Ordering::SeqCst) {
+                Ok(_) if next == SCHEDULED => return self.requeue(),
+                Ok(_) => return,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+}
+
+/// A queued poll; abandons the future if the scheduler discards it without running it
+struct PollJob(Option<Arc<FutureTask>>);
+
+impl Drop for PollJob {
+    fn drop(&mut self) {
+        if let Some(task) = self.0.take() {
+            task.abandon();
+        }
+    }
+}
+
+fn poll_job(task: Arc<FutureTask>) -> Job {
+    let mut job = PollJob(Some(task));
+    Box::new(move || match job.0.take() {
+        Some(task) => task.poll(),
+        None => TaskExit::Completed,
+    })
+}
+
+impl TaskScheduler {
+    /// Run a future on the worker pool and get a handle to await or join its output
+    ///
+    /// The future is polled by the same workers as closures, and while it
+    /// waits its waker queues the next poll. A panic while polling is
+    /// captured and surfaced through the handle. `shutdown_drain` waits for
+    /// spawned futures to complete.
+    pub fn spawn_future<F>(&self, future: F) -> Result<TaskHandle<F::Output>, SchedulerError>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        // Counted first, like `enqueue`, so it cannot finish before being counted
+        if !self.state.try_reserve() {
+            self.state.rejected_tasks.fetch_add(1, Ordering::SeqCst);
+            return Err(SchedulerError::QueueFull);
+        }
+        if let Err(e) = self.state.accepting() {
+            self.state.task_finished();
+            return Err(e);
+        }
+
+        let id = self.state.next_task_id();
+        let cancel_token = CancellationToken::new();
+        let (completer, handle) = handle::pair(cancel_token.clone());
+        let task = Arc::new(FutureTask {
+            id,
+            state: AtomicU8::new(SCHEDULED),
+            future: Mutex::new(Some(Box::new(Spawned {
+                future: Box::pin(future),
+                completer: Some(completer),
+            }))),
+            scheduler: Arc::downgrade(&self.state),
+            cancel_token: cancel_token.clone(),
+        });
+
+        self.state.push(ScheduledTask::new(id, poll_job(task), Priority::NORMAL, cancel_token, self.now()));
+        Ok(handle.with_id(id).driven_by(&self.state))
+    }
+}
+
+/// Wakes the thread blocked in `block_on`
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Run a future to completion on the current thread, parking it while the future waits
+///
+/// Meant for the main thread, e.g. to await a `TaskHandle`; calling it from a
+/// task blocks that worker until the future completes.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        thread::park();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task_scheduler::{sleep, test_config};
+    use std::sync::atomic::AtomicUsize;
+    use std::time::{Duration, Instant};
+
+    fn started_scheduler(num_workers: usize) -> TaskScheduler {
+        let mut scheduler = TaskScheduler::new(test_config(num_workers));
+        scheduler.start();
+        scheduler
+    }
+
+    /// Returns `Pending` `times` times, waking itself each time
+    struct YieldTimes {
+        times: usize,
+        polls: Arc<AtomicUsize>,
+    }
+
+    impl Future for YieldTimes {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            self.polls.fetch_add(1, Ordering::SeqCst);
+            if self.times == 0 {
+                return Poll::Ready(());
+            }
+            self.times -= 1;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    #[test]
+    fn test_spawned_future_output_can_be_awaited_or_joined() {
+        let scheduler = started_scheduler(2);
+
+        let handle = scheduler.spawn_future(async { 6 * 7 }).unwrap();
+        assert_eq!(block_on(handle).unwrap(), 42);
+
+        let polls = Arc::new(AtomicUsize::new(0));
+        let yields = YieldTimes { times: 3, polls: Arc::clone(&polls) };
+        let handle = scheduler.spawn_future(yields).unwrap();
+        handle.join().unwrap();
+        assert_eq!(polls.load(Ordering::SeqCst), 4);
+
+        // A future can await closures and other futures on the same pool
+        let inner = scheduler.submit_with_result(|| "closure").unwrap();
+        let nested = scheduler.spawn_future(async { "future" }).unwrap();
+        let outer = scheduler
+            .spawn_future(async move { (inner.await.unwrap(), nested.await.unwrap()) })
+            .unwrap();
+        assert_eq!(block_on(outer).unwrap(), ("closure", "future"));
+
+        let report = scheduler.shutdown_drain();
+        assert_eq!((report.completed, report.dropped), (5, 0));
+    }
+
+    #[test]
+    fn test_sleeping_futures_do_not_hold_workers() {
+        let scheduler = started_scheduler(1);
+
+        let started = Instant::now();
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                scheduler
+                    .spawn_future(async move {
+                        sleep(Duration::from_millis(50)).await;
+                        i
+                    })
+                    .unwrap()
+            })
+            .collect();
+
+        let outputs: Vec<_> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+        assert_eq!(outputs, (0..8).collect::<Vec<_>>());
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert!(started.elapsed() < Duration::from_millis(300), "sleeps ran one after another");
+
+        scheduler.shutdown();
+    }
+
+    #[test]
+    fn test_sleep_past_the_end_of_instant_never_ends() {
+        let mut sleeping = pin!(sleep(Duration::MAX));
+        assert_eq!(sleeping.deadline(), None);
+
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(sleeping.as_mut().poll(&mut cx).is_pending());
+        assert!(sleeping.as_mut().poll(&mut cx).is_pending());
+    }
+
+    #[test]
+    fn test_panicking_future_reports_through_handle() {
+        let scheduler = started_scheduler(1);
+
+        let handle = scheduler
+            .spawn_future(async {
+                sleep(Duration::from_millis(1)).await;
+                panic!("future failed")
+            })
+            .unwrap();
+        let error = block_on(handle).unwrap_err();
+        assert_eq!(error.panic_message(), Some("future failed"));
+
+        let report = scheduler.shutdown_drain();
+        assert_eq!((report.completed, report.panicked), (0, 1));
+    }
+
+    #[test]
+    fn test_shutdown_drain_waits_for_sleeping_futures() {
+        let scheduler = started_scheduler(1);
+        let finished = Arc::new(AtomicUsize::new(0));
+        let finished_clone = Arc::clone(&finished);
+
+        scheduler
+            .spawn_future(async move {
+                sleep(Duration::from_millis(30)).await;
+                finished_clone.fetch_add(1, Ordering::SeqCst);
+            })
+            .unwrap();
+
+        let report = scheduler.shutdown_drain();
+        assert_eq!(finished.load(Ordering::SeqCst), 1);
+        assert_eq!(report.completed, 1);
+    }
+
+    #[test]
+    fn test_shutdown_now_drops_waiting_futures() {
+        let scheduler = started_scheduler(1);
+
+        let handle = scheduler
+            .spawn_future(async { sleep(Duration::from_millis(20)).await })
+            .unwrap();
+        while scheduler.stats().workers[0].executed == 0 {
+            thread::yield_now();
+        }
+
+        let (_, pending) = scheduler.shutdown_now();
+        assert!(pending.is_empty());
+        assert!(matches!(handle.join(), Err(JoinError::Dropped)));
+    }
+}
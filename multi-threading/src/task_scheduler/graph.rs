@@ -0,0 +1,588 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use super::handle::panic_message;
+use super::{CancellationToken, Priority, SchedulerError, SchedulerState, TaskExit, TaskFailure, TaskScheduler};
+
+/// Node closure, with failures already turned into a message
+type NodeTask = Box<dyn FnOnce() -> Result<(), String> + Send + 'static>;
+
+/// Identifies a node within the `TaskGraphBuilder` that created it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+impl NodeId {
+    /// Position of the node in the order it was added
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+/// What happens to the rest of a graph when a node fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FailurePolicy {
+    /// Skip the failed node's dependents; independent branches keep running
+    #[default]
+    SkipDependents,
+    /// Cancel every node that has not started yet
+    CancelGraph,
+}
+
+/// How a node of a submitted graph ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeOutcome {
+    Completed,
+    Failed(TaskFailure),
+    /// Not run because a node it depends on did not complete
+    Skipped,
+    /// Not run because another node failed under `FailurePolicy::CancelGraph`
+    Cancelled,
+    /// Not run because the scheduler shut down first
+    Dropped,
+}
+
+impl NodeOutcome {
+    pub fn is_completed(&self) -> bool {
+        matches!(self, NodeOutcome::Completed)
+    }
+}
+
+struct BuilderNode {
+    task: NodeTask,
+    dependencies: Vec<NodeId>,
+}
+
+/// Collects closures and the dependencies between them
+#[derive(Default)]
+pub struct TaskGraphBuilder {
+    nodes: Vec<BuilderNode>,
+    policy: FailurePolicy,
+    priority: Priority,
+}
+
+impl TaskGraphBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a node; it fails if it panics
+    pub fn add_task<F>(&mut self, task: F) -> NodeId
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.push(Box::new(move || {
+            task();
+            Ok(())
+        }))
+    }
+
+    /// Add a node that fails if it returns `Err` or panics
+    pub fn add_fallible_task<F, E>(&mut self, task: F) -> NodeId
+    where
+        F: FnOnce() -> Result<(), E> + Send + 'static,
+        E: fmt::Display,
+    {
+        self.push(Box::new(move || task().map_err(|error| error.to_string())))
+    }
+
+    fn push(&mut self, task: NodeTask) -> NodeId {
+        self.nodes.push(BuilderNode {
+            task,
+            dependencies: Vec::new(),
+        });
+        NodeId(self.nodes.len() - 1)
+    }
+
+    /// Run `node` only after `dependency` has completed
+    pub fn add_dependency(&mut self, node: NodeId, dependency: NodeId) -> &mut Self {
+        if let Some(builder_node) = self.nodes.get_mut(node.0) {
+            builder_node.dependencies.push(dependency);
+        }
+        self
+    }
+
+    pub fn failure_policy(&mut self, policy: FailurePolicy) -> &mut Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Priority every node of the graph is submitted at
+    pub fn priority(&mut self, priority: Priority) -> &mut Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Check the dependencies and produce a graph ready to submit
+    pub fn build(self) -> Result<TaskGraph, SchedulerError> {
+        let num_nodes = self.nodes.len();
+        let mut dependents = vec![Vec::new(); num_nodes];
+        let mut pending = vec![0; num_nodes];
+
+        for (index, node) in self.nodes.iter().enumerate() {
+            for dependency in &node.dependencies {
+                if dependency.0 >= num_nodes {
+                    return Err(SchedulerError::UnknownDependency);
+                }
+                if dependency.0 == index {
+                    return Err(SchedulerError::SelfDependency);
+                }
+                if !dependents[dependency.0].contains(&index) {
+                    dependents[dependency.0].push(index);
+                    pending[index] += 1;
+                }
+            }
+        }
+
+        // Kahn's algorithm: every node is reachable from a root only if there is no cycle
+        let mut remaining = pending.clone();
+        let mut ready: Vec<usize> = (0..num_nodes).filter(|&index| remaining[index] == 0).collect();
+        let mut visited = 0;
+        while let Some(index) = ready.pop() {
+            visited += 1;
+            for &dependent in &dependents[index] {
+                remaining[dependent] -= 1;
+                if remaining[dependent] == 0 {
+                    ready.push(dependent);
+                }
+            }
+        }
+        if visited < num_nodes {
+            return Err(SchedulerError::DependencyCycle);
+        }
+
+        Ok(TaskGraph {
+            tasks: self.nodes.into_iter().map(|node| node.task).collect(),
+            dependents,
+            pending,
+            policy: self.policy,
+            priority: self.priority,
+        })
+    }
+}
+
+/// A validated, acyclic set of tasks, submitted with `TaskScheduler::submit_graph`
+pub struct TaskGraph {
+    tasks: Vec<NodeTask>,
+    dependents: Vec<Vec<usize>>,
+    /// Number of dependencies of each node
+    pending: Vec<usize>,
+    policy: FailurePolicy,
+    priority: Priority,
+}
+
+impl TaskGraph {
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+}
+
+/// Progress of a submitted graph, shared by its node jobs and its handle
+struct GraphRun {
+    state: Weak<SchedulerState>,
+    tasks: Vec<Mutex<Option<NodeTask>>>,
+    dependents: Vec<Vec<usize>>,
+    /// Dependencies of each node that have not finished yet
+    pending: Vec<AtomicUsize>,
+    /// Set on a node when a dependency did not complete
+    blocked: Vec<AtomicBool>,
+    /// Set by the first failure under `FailurePolicy::CancelGraph`
+    cancelled: AtomicBool,
+    policy: FailurePolicy,
+    priority: Priority,
+    outcomes: Mutex<Vec<Option<NodeOutcome>>>,
+    unfinished: AtomicUsize,
+    finished: Condvar,
+}
+
+impl GraphRun {
+    /// Record the outcome of `node` and start or settle whichever dependents it unblocks
+    fn finish(self: &Arc<Self>, node: usize, outcome: NodeOutcome) {
+        let mut settled = vec![(node, outcome)];
+
+        while let Some((node, outcome)) = settled.pop() {
+            let completed = outcome.is_completed();
+            if matches!(outcome, NodeOutcome::Failed(_)) && self.policy == FailurePolicy::CancelGraph {
+                self.cancelled.store(true, Ordering::SeqCst);
+            }
+
+            {
+                let mut outcomes = self.outcomes.lock().unwrap_or_else(|e| e.into_inner());
+                if outcomes[node].is_some() {
+                    continue;
+                }
+                outcomes[node] = Some(outcome);
+            }
+
+            for &dependent in &self.dependents[node] {
+                if !completed {
+                    self.blocked[dependent].store(true, Ordering::SeqCst);
+                }
+                if self.pending[dependent].fetch_sub(1, Ordering::SeqCst) == 1
+                    && let Some(outcome) = self.start(dependent)
+                {
+                    settled.push((dependent, outcome));
+                }
+            }
+
+            if self.unfinished.fetch_sub(1, Ordering::SeqCst) == 1 {
+                let _guard = self.outcomes.lock().unwrap_or_else(|e| e.into_inner());
+                self.finished.notify_all();
+            }
+        }
+    }
+
+    /// Submit a node whose dependencies have all finished, or return the
+    /// outcome it settles with when it cannot run
+    fn start(self: &Arc<Self>, node: usize) -> Option<NodeOutcome> {
+        if self.cancelled.load(Ordering::SeqCst) {
+            return Some(NodeOutcome::Cancelled);
+        }
+        if self.blocked[node].load(Ordering::SeqCst) {
+            return Some(NodeOutcome::Skipped);
+        }
+        let Some(state) = self.state.upgrade() else {
+            return Some(NodeOutcome::Dropped);
+        };
+
+        let job = NodeJob {
+            run: Some(Arc::clone(self)),
+            node,
+        };
+        match state.enqueue(Box::new(move || job.run()), self.priority, CancellationToken::new()) {
+            Ok(_) => None,
+            Err(_) => Some(NodeOutcome::Dropped),
+        }
+    }
+}
+
+/// A node queued on the scheduler; settles the node as dropped if the
+/// scheduler discards it without running it
+struct NodeJob {
+    run: Option<Arc<GraphRun>>,
+    node: usize,
+}
+
+impl NodeJob {
+    fn run(mut self) -> TaskExit {
+        let Some(run) = self.run.take() else {
+            return TaskExit::Completed;
+        };
+
+        // Another node failed while this one waited in a queue
+        if run.cancelled.load(Ordering::SeqCst) {
+            run.finish(self.node, NodeOutcome::Cancelled);
+            return TaskExit::Completed;
+        }
+
+        let task = run.tasks[self.node]
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+        let outcome = task.map(|task| std::panic::catch_unwind(std::panic::AssertUnwindSafe(task)));
+
+        let (outcome, exit) = match outcome {
+            Some(Ok(Ok(()))) | None => (NodeOutcome::Completed, TaskExit::Completed),
+            Some(Ok(Err(message))) => (NodeOutcome::Failed(TaskFailure::Error(message)), TaskExit::Completed),
+            Some(Err(payload)) => {
+                let message = panic_message(payload.as_ref()).unwrap_or("<non-string payload>");
+                (NodeOutcome::Failed(TaskFailure::Panicked(message.to_string())), TaskExit::Panicked)
+            }
+        };
+        run.finish(self.node, outcome);
+        exit
+    }
+}
+
+impl Drop for NodeJob {
+    fn drop(&mut self) {
+        if let Some(run) = self.run.take() {
+            run.finish(self.node, NodeOutcome::Dropped);
+        }
+    }
+}
+
+/// Outcome of every node of a finished graph
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphReport {
+    outcomes: Vec<NodeOutcome>,
+}
+
+impl GraphReport {
+    pub fn outcome(&self, node: NodeId) -> &NodeOutcome {
+        &self.outcomes[node.0]
+    }
+
+    /// Outcomes in the order the nodes were added
+    pub fn outcomes(&self) -> &[NodeOutcome] {
+        &self.outcomes
+    }
+
+    pub fn all_completed(&self) -> bool {
+        self.outcomes.iter().all(NodeOutcome::is_completed)
+    }
+}
+
+/// Handle to a submitted graph
+pub struct GraphHandle {
+    run: Arc<GraphRun>,
+}
+
+impl GraphHandle {
+    /// Whether every node has an outcome
+    pub fn is_finished(&self) -> bool {
+        self.run.unfinished.load(Ordering::SeqCst) == 0
+    }
+
+    /// Outcome of `node`, if it has finished
+    pub fn outcome(&self, node: NodeId) -> Option<NodeOutcome> {
+        self.run
+            .outcomes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(node.0)
+            .cloned()
+            .flatten()
+    }
+
+    /// Block until every node has finished
+    pub fn wait(self) -> GraphReport {
+        match self.wait_until(None) {
+            Ok(report) => report,
+            Err(_) => unreachable!("waiting without a deadline always finishes"),
+        }
+    }
+
+    /// Wait up to `timeout` for the graph, handing the handle back if it is still running
+    ///
+    /// A timeout too large to add to the current time waits as long as `wait`.
+    pub fn wait_timeout(self, timeout: Duration) -> Result<GraphReport, Self> {
+        self.wait_until(Instant::now().checked_add(timeout))
+    }
+
+    fn wait_until(self, deadline: Option<Instant>) -> Result<GraphReport, Self> {
+        let mut outcomes = self.run.outcomes.lock().unwrap_or_else(|e| e.into_inner());
+        while self.run.unfinished.load(Ordering::SeqCst) > 0 {
+            outcomes = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        drop(outcomes);
+                        return Err(self);
+                    }
+                    self.run
+                        .finished
+                        .wait_timeout(outcomes, deadline - now)
+                        .map(|(guard, _)| guard)
+                        .unwrap_or_else(|e| e.into_inner().0)
+                }
+                None => self.run.finished.wait(outcomes).unwrap_or_else(|e| e.into_inner()),
+            };
+        }
+
+        let outcomes = outcomes.iter().map(|outcome| outcome.clone().unwrap_or(NodeOutcome::Dropped)).collect();
+        Ok(GraphReport { outcomes })
+    }
+}
+
+impl TaskScheduler {
+    /// Submit every node of `graph`; each runs on the worker queues once all
+    /// of its dependencies have completed
+    pub fn submit_graph(&self, graph: TaskGraph) -> Result<GraphHandle, SchedulerError> {
+        self.state.accepting()?;
+
+        let num_nodes = graph.len();
+        let run = Arc::new(GraphRun {
+            state: Arc::downgrade(&self.state),
+            tasks: graph.tasks.into_iter().map(|task| Mutex::new(Some(task))).collect(),
+            dependents: graph.dependents,
+            pending: graph.pending.iter().map(|&pending| AtomicUsize::new(pending)).collect(),
+            blocked: (0..num_nodes).map(|_| AtomicBool::new(false)).collect(),
+            cancelled: AtomicBool::new(false),
+            policy: graph.policy,
+            priority: graph.priority,
+            outcomes: Mutex::new(vec![None; num_nodes]),
+            unfinished: AtomicUsize::new(num_nodes),
+            finished: Condvar::new(),
+        });
+
+        for (node, &pending) in graph.pending.iter().enumerate() {
+            if pending == 0
+                && let Some(outcome) = run.start(node)
+            {
+                run.finish(node, outcome);
+            }
+        }
+
+        Ok(GraphHandle { run })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task_scheduler::{SchedulerConfig, test_config};
+    use std::sync::mpsc;
+    use std::thread;
+
+    fn started_scheduler() -> TaskScheduler {
+        let mut scheduler = TaskScheduler::new(test_config(3));
+        scheduler.start();
+        scheduler
+    }
+
+    fn record(log: &Arc<Mutex<Vec<&'static str>>>, label: &'static str) -> impl FnOnce() + Send + 'static {
+        let log = Arc::clone(log);
+        move || {
+            thread::sleep(Duration::from_millis(5));
+            log.lock().unwrap().push(label);
+        }
+    }
+
+    #[test]
+    fn test_nodes_run_after_their_dependencies() {
+        let scheduler = started_scheduler();
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        // a and b, then c once both finish, then d
+        let mut builder = TaskGraphBuilder::new();
+        let a = builder.add_task(record(&log, "a"));
+        let b = builder.add_task(record(&log, "b"));
+        let c = builder.add_task(record(&log, "c"));
+        let d = builder.add_task(record(&log, "d"));
+        builder.add_dependency(c, a).add_dependency(c, b).add_dependency(d, c);
+
+        let handle = scheduler.submit_graph(builder.build().unwrap()).unwrap();
+        let report = handle.wait_timeout(Duration::from_secs(5)).ok().expect("graph never finished");
+
+        assert!(report.all_completed());
+        let log = log.lock().unwrap();
+        assert_eq!(log.len(), 4);
+        assert_eq!(&log[2..], ["c", "d"]);
+
+        // A timeout past the end of `Instant` waits without a deadline
+        let mut builder = TaskGraphBuilder::new();
+        builder.add_task(|| {});
+        let handle = scheduler.submit_graph(builder.build().unwrap()).unwrap();
+        assert!(handle.wait_timeout(Duration::MAX).ok().expect("graph never finished").all_completed());
+
+        scheduler.shutdown();
+    }
+
+    #[test]
+    fn test_failure_skips_only_dependents() {
+        let scheduler = started_scheduler();
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        let mut builder = TaskGraphBuilder::new();
+        let fails = builder.add_fallible_task(|| Err::<(), _>("disk full"));
+        let dependent = builder.add_task(record(&log, "dependent"));
+        let transitive = builder.add_task(record(&log, "transitive"));
+        let independent = builder.add_task(record(&log, "independent"));
+        builder.add_dependency(dependent, fails).add_dependency(transitive, dependent);
+
+        let report = scheduler.submit_graph(builder.build().unwrap()).unwrap().wait();
+        assert_eq!(report.outcome(fails), &NodeOutcome::Failed(TaskFailure::Error("disk full".into())));
+        assert_eq!(report.outcome(dependent), &NodeOutcome::Skipped);
+        assert_eq!(report.outcome(transitive), &NodeOutcome::Skipped);
+        assert_eq!(report.outcome(independent), &NodeOutcome::Completed);
+        assert_eq!(*log.lock().unwrap(), vec!["independent"]);
+
+        scheduler.shutdown();
+    }
+
+    #[test]
+    fn test_cancel_graph_policy_stops_unstarted_nodes() {
+        let scheduler = started_scheduler();
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        let mut builder = TaskGraphBuilder::new();
+        builder.failure_policy(FailurePolicy::CancelGraph);
+        // The panic waits for `slow` to start, so only unstarted nodes are left to cancel
+        let (started_tx, started_rx) = mpsc::channel();
+        let panics = builder.add_task(move || {
+            let _ = started_rx.recv_timeout(Duration::from_secs(5));
+            panic!("expected graph panic")
+        });
+        let after_panic = builder.add_task(record(&log, "after panic"));
+        let slow = builder.add_task(move || {
+            let _ = started_tx.send(());
+            thread::sleep(Duration::from_millis(50));
+        });
+        let after_slow = builder.add_task(record(&log, "after slow"));
+        builder.add_dependency(after_panic, panics).add_dependency(after_slow, slow);
+
+        let report = scheduler.submit_graph(builder.build().unwrap()).unwrap().wait();
+        assert!(matches!(report.outcome(panics), NodeOutcome::Failed(TaskFailure::Panicked(_))));
+        assert_eq!(report.outcome(slow), &NodeOutcome::Completed);
+        assert_eq!(report.outcome(after_panic), &NodeOutcome::Cancelled);
+        assert_eq!(report.outcome(after_slow), &NodeOutcome::Cancelled);
+        assert!(log.lock().unwrap().is_empty());
+
+        scheduler.shutdown();
+    }
+
+    #[test]
+    fn test_build_rejects_cycles_and_unknown_nodes() {
+        let mut builder = TaskGraphBuilder::new();
+        let a = builder.add_task(|| {});
+        let b = builder.add_task(|| {});
+        let c = builder.add_task(|| {});
+        builder.add_dependency(b, a).add_dependency(c, b).add_dependency(a, c);
+        assert_eq!(builder.build().err(), Some(SchedulerError::DependencyCycle));
+
+        let mut builder = TaskGraphBuilder::new();
+        let a = builder.add_task(|| {});
+        builder.add_dependency(a, a);
+        assert_eq!(builder.build().err(), Some(SchedulerError::SelfDependency));
+
+        let mut other = TaskGraphBuilder::new();
+        other.add_task(|| {});
+        let foreign = other.add_task(|| {});
+        let mut builder = TaskGraphBuilder::new();
+        let a = builder.add_task(|| {});
+        builder.add_dependency(a, foreign);
+        assert_eq!(builder.build().err(), Some(SchedulerError::UnknownDependency));
+    }
+
+    #[test]
+    fn test_queued_nodes_are_dropped_at_shutdown() {
+        let config = SchedulerConfig {
+            enable_work_stealing: false,
+            ..test_config(1)
+        };
+        let mut scheduler = TaskScheduler::new(config);
+        scheduler.start();
+
+        let (started_tx, started_rx) = std::sync::mpsc::channel::<()>();
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        let mut builder = TaskGraphBuilder::new();
+        let blocker = builder.add_task(move || {
+            started_tx.send(()).unwrap();
+            let _ = release_rx.recv_timeout(Duration::from_secs(5));
+        });
+        let queued = builder.add_task(|| {});
+        let dependent = builder.add_task(|| {});
+        builder.add_dependency(dependent, queued);
+
+        let handle = scheduler.submit_graph(builder.build().unwrap()).unwrap();
+        started_rx.recv().unwrap();
+
+        let releaser = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            release_tx.send(()).unwrap();
+        });
+        scheduler.shutdown();
+        releaser.join().unwrap();
+
+        let report = handle.wait_timeout(Duration::from_secs(5)).ok().expect("graph never settled");
+        assert_eq!(report.outcome(blocker), &NodeOutcome::Completed);
+        assert_eq!(report.outcome(queued), &NodeOutcome::Dropped);
+        assert_eq!(report.outcome(dependent), &NodeOutcome::Skipped);
+    }
+}
@@ -0,0 +1,428 @@
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, Weak};
+use std::time::{Duration, Instant};
+
+use super::handle::{self, Completer};
+use super::{CancellationToken, JoinError, Priority, SchedulerError, SchedulerState, TaskExit, TaskHandle, TaskScheduler};
+
+#[derive(Default)]
+struct GroupCounts {
+    /// Tasks in this group and its child groups that have not run or been dropped
+    unfinished: usize,
+    /// Tasks in this group whose outcome no wait has taken yet
+    untaken: usize,
+}
+
+/// The part of a group its child groups and tasks see, whatever their result type
+struct GroupCore {
+    counts: Mutex<GroupCounts>,
+    changed: Condvar,
+    cancel_token: CancellationToken,
+    parent: Option<Arc<GroupCore>>,
+    children: Mutex<Vec<Weak<GroupCore>>>,
+}
+
+impl GroupCore {
+    fn new(parent: Option<Arc<GroupCore>>) -> Arc<Self> {
+        Arc::new(Self {
+            counts: Mutex::new(GroupCounts::default()),
+            changed: Condvar::new(),
+            cancel_token: CancellationToken::new(),
+            parent,
+            children: Mutex::new(Vec::new()),
+        })
+    }
+
+    fn counts(&self) -> MutexGuard<'_, GroupCounts> {
+        self.counts.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// This group and its ancestors, innermost first
+    fn lineage(self: &Arc<Self>) -> impl Iterator<Item = &Arc<GroupCore>> {
+        std::iter::successors(Some(self), |core| core.parent.as_ref())
+    }
+
+    fn task_added(self: &Arc<Self>) {
+        self.counts().untaken += 1;
+        for core in self.lineage() {
+            core.counts().unfinished += 1;
+        }
+    }
+
+    fn task_finished(self: &Arc<Self>) {
+        for core in self.lineage() {
+            let mut counts = core.counts();
+            counts.unfinished -= 1;
+            core.changed.notify_all();
+        }
+    }
+
+    fn cancel(&self) {
+        self.cancel_token.cancel();
+        let children = self.children.lock().unwrap_or_else(|e| e.into_inner());
+        for child in children.iter().filter_map(Weak::upgrade) {
+            child.cancel();
+        }
+    }
+}
+
+/// A task submitted to a group; the group counts it as finished once it is
+/// dropped, whether or not it ran, and after its handle has the outcome
+struct GroupJob<F, T> {
+    task: Option<F>,
+    completer: Option<Completer<T>>,
+    core: Arc<GroupCore>,
+}
+
+impl<F, T> GroupJob<F, T>
+where
+    F: FnOnce() -> T,
+{
+    fn run(mut self) -> TaskExit {
+        let (Some(task), Some(completer)) = (self.task.take(), self.completer.take()) else {
+            return TaskExit::Completed;
+        };
+
+        let outcome = std::panic::catch_unwind(std::panic::AssertUnwindSafe(task));
+        let exit = if outcome.is_ok() { TaskExit::Completed } else { TaskExit::Panicked };
+        completer.complete(outcome.map_err(JoinError::Panicked));
+        exit
+    }
+}
+
+impl<F, T> Drop for GroupJob<F, T> {
+    fn drop(&mut self) {
+        drop(self.task.take());
+        drop(self.completer.take());
+        self.core.task_finished();
+    }
+}
+
+/// Outcome of every task in a group, ordered by task id
+#[derive(Debug)]
+pub struct GroupResults<T> {
+    /// Values of the tasks that ran to completion
+    pub completed: Vec<(u64, T)>,
+    /// Tasks that panicked, were cancelled or were dropped without running
+    pub failed: Vec<(u64, JoinError)>,
+}
+
+impl<T> GroupResults<T> {
+    /// Whether every task completed
+    pub fn is_ok(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    /// The values in task id order, or the error of the first task that failed
+    pub fn into_result(self) -> Result<Vec<T>, JoinError> {
+        match self.failed.into_iter().next() {
+            Some((_, error)) => Err(error),
+            None => Ok(self.completed.into_iter().map(|(_, value)| value).collect()),
+        }
+    }
+}
+
+/// A set of tasks that can be waited on and cancelled together; see `TaskScheduler::group`
+///
+/// Clones refer to the same group, so a task can capture one and submit
+/// more tasks into it. Waiting blocks the calling thread, so a task waiting
+/// on a group holds its worker until the group is done.
+pub struct TaskGroup<T> {
+    core: Arc<GroupCore>,
+    handles: Arc<Mutex<Vec<TaskHandle<T>>>>,
+    state: Arc<SchedulerState>,
+}
+
+impl<T> Clone for TaskGroup<T> {
+    fn clone(&self) -> Self {
+        Self {
+            core: Arc::clone(&self.core),
+            handles: Arc::clone(&self.handles),
+            state: Arc::clone(&self.state),
+        }
+    }
+}
+
+impl<T: Send + 'static> TaskGroup<T> {
+    fn new(state: Arc<SchedulerState>, parent: Option<Arc<GroupCore>>) -> Self {
+        Self {
+            core: GroupCore::new(parent),
+            handles: Arc::new(Mutex::new(Vec::new())),
+            state,
+        }
+    }
+
+    pub fn submit<F>(&self, task: F) -> Result<u64, SchedulerError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+    {
+        self.submit_with_priority(Priority::NORMAL, task)
+    }
+
+    pub fn submit_with_priority<F>(&self, priority: Priority, task: F) -> Result<u64, SchedulerError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let (completer, handle) = handle::pair(self.core.cancel_token.clone());
+        self.core.task_added();
+        let job = GroupJob {
+            task: Some(task),
+            completer: Some(completer),
+            core: Arc::clone(&self.core),
+        };
+
+        // On failure the job has been dropped, which already counted it as finished
+        let result = self
+            .state
+            .enqueue(Box::new(move || job.run()), priority, self.core.cancel_token.clone());
+
+        let mut counts = self.core.counts();
+        match result {
+            Ok(task_id) => self.lock_handles().push(handle.with_id(task_id).driven_by(&self.state)),
+            Err(_) => counts.untaken -= 1,
+        }
+        self.core.changed.notify_all();
+        result
+    }
+
+    /// A group nested in this one, e.g. for a task to split its work into
+    ///
+    /// This group's waits also wait for the child's tasks, though their
+    /// results go to the child, and cancelling this group cancels the child.
+    pub fn child<U: Send + 'static>(&self) -> TaskGroup<U> {
+        let child = TaskGroup::new(Arc::clone(&self.state), Some(Arc::clone(&self.core)));
+        let mut children = self.core.children.lock().unwrap_or_else(|e| e.into_inner());
+        children.retain(|child| child.strong_count() > 0);
+        children.push(Arc::downgrade(&child.core));
+        drop(children);
+
+        if self.is_cancelled() {
+            child.cancel();
+        }
+        child
+    }
+
+    /// Cancel the group's tasks that have not started, and those of its child
+    /// groups; running tasks can check `is_cancelled` to stop early
+    pub fn cancel(&self) {
+        self.core.cancel();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.core.cancel_token.is_cancelled()
+    }
+
+    /// Wait for every task in the group and its child groups, including
+    /// tasks submitted while waiting, and collect the outcomes not already
+    /// taken by `wait_any`
+    pub fn wait_all(&self) -> GroupResults<T> {
+        self.wait_all_until(None).expect("waiting without a deadline cannot time out")
+    }
+
+    /// `wait_all` giving up after `timeout`; nothing is taken from the group
+    /// when it returns `None`. A timeout too large to add to the current
+    /// time waits like `wait_all`.
+    pub fn wait_all_timeout(&self, timeout: Duration) -> Option<GroupResults<T>> {
+        self.wait_all_until(Instant::now().checked_add(timeout))
+    }
+
+    /// Wait for the next task in this group to finish and take its outcome,
+    /// or return `None` once every outcome has been taken
+    pub fn wait_any(&self) -> Option<(u64, Result<T, JoinError>)> {
+        let mut counts = self.core.counts();
+        loop {
+            let mut handles = self.lock_handles();
+            if let Some(index) = handles.iter().position(TaskHandle::is_finished) {
+                let handle = handles.swap_remove(index);
+                counts.untaken -= 1;
+                drop(handles);
+                drop(counts);
+                return Some((handle.id(), handle.join()));
+            }
+            if counts.untaken == 0 {
+                return None;
+            }
+            drop(handles);
+            if self.state.simulation.is_some() {
+                drop(counts);
+                let progressed = TaskScheduler::drive_simulation(&self.state);
+                counts = self.core.counts();
+                if progressed {
+                    continue;
+                }
+            }
+            counts = self.core.changed.wait(counts).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    fn wait_all_until(&self, deadline: Option<Instant>) -> Option<GroupResults<T>> {
+        let mut counts = self.core.counts();
+        // A finished task's handle is stored once its `submit` call returns
+        while counts.unfinished > 0 || counts.untaken > self.lock_handles().len() {
+            // No worker threads run the group's tasks in simulation mode
+            if self.state.simulation.is_some() {
+                drop(counts);
+                let progressed = TaskScheduler::drive_simulation(&self.state);
+                counts = self.core.counts();
+                if progressed {
+                    continue;
+                }
+            }
+            counts = match deadline {
+                None => self.core.changed.wait(counts).unwrap_or_else(|e| e.into_inner()),
+                Some(deadline) => {
+                    let remaining = deadline.checked_duration_since(Instant::now())?;
+                    self.core.changed.wait_timeout(counts, remaining).unwrap_or_else(|e| e.into_inner()).0
+                }
+            };
+        }
+
+        let handles = std::mem::take(&mut *self.lock_handles());
+        counts.untaken -= handles.len();
+        drop(counts);
+
+        let mut handles = handles;
+        handles.sort_by_key(TaskHandle::id);
+        let mut results = GroupResults {
+            completed: Vec::new(),
+            failed: Vec::new(),
+        };
+        for handle in handles {
+            let task_id = handle.id();
+            match handle.join() {
+                Ok(value) => results.completed.push((task_id, value)),
+                Err(error) => results.failed.push((task_id, error)),
+            }
+        }
+        Some(results)
+    }
+
+    fn lock_handles(&self) -> MutexGuard<'_, Vec<TaskHandle<T>>> {
+        self.handles.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl TaskScheduler {
+    /// Create an empty group of tasks returning `T`
+    pub fn group<T: Send + 'static>(&self) -> TaskGroup<T> {
+        TaskGroup::new(Arc::clone(&self.state), None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task_scheduler::test_config;
+    use std::sync::mpsc;
+
+    fn started_scheduler(num_workers: usize) -> TaskScheduler {
+        let mut scheduler = TaskScheduler::new(test_config(num_workers));
+        scheduler.start();
+        scheduler
+    }
+
+    #[test]
+    fn test_wait_all_collects_values_and_panics() {
+        let scheduler = started_scheduler(2);
+        let group = scheduler.group();
+
+        let ids: Vec<u64> = (0..5u64).map(|i| group.submit(move || i * 10).unwrap()).collect();
+        let panicked = group.submit(|| panic!("expected test panic")).unwrap();
+
+        let results = group.wait_all();
+        assert!(!results.is_ok());
+        let expected: Vec<_> = ids.iter().zip([0, 10, 20, 30, 40]).map(|(&id, value)| (id, value)).collect();
+        assert_eq!(results.completed, expected);
+        assert_eq!(results.failed.len(), 1);
+        assert_eq!(results.failed[0].0, panicked);
+        assert_eq!(results.failed[0].1.panic_message(), Some("expected test panic"));
+
+        // Everything was taken, so the group is empty again
+        assert!(group.wait_all().into_result().unwrap().is_empty());
+        assert!(group.wait_any().is_none());
+        scheduler.shutdown();
+    }
+
+    #[test]
+    fn test_wait_any_and_timeout() {
+        let scheduler = started_scheduler(2);
+        let group = scheduler.group();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+
+        let slow = group.submit(move || release_rx.recv().map(|_| 1)).unwrap();
+        let fast = group.submit(|| Ok(2)).unwrap();
+
+        assert_eq!(group.wait_any().map(|(id, value)| (id, value.unwrap())), Some((fast, Ok(2))));
+        assert!(group.wait_all_timeout(Duration::from_millis(50)).is_none());
+
+        release_tx.send(()).unwrap();
+        let results = group.wait_all_timeout(Duration::from_secs(5)).expect("slow task never finished");
+        assert_eq!(results.completed, vec![(slow, Ok(1))]);
+
+        // A timeout past the end of `Instant` waits without a deadline
+        let last = group.submit(|| Ok(3)).unwrap();
+        let results = group.wait_all_timeout(Duration::MAX).expect("waiting without a deadline cannot time out");
+        assert_eq!(results.completed, vec![(last, Ok(3))]);
+        scheduler.shutdown();
+    }
+
+    #[test]
+    fn test_tasks_spawn_into_their_parent_group() {
+        let scheduler = started_scheduler(2);
+        let group = scheduler.group::<usize>();
+
+        for i in 0..4 {
+            let parent = group.clone();
+            group
+                .submit(move || {
+                    for j in 0..3 {
+                        parent.submit(move || i * 3 + j + 100).unwrap();
+                    }
+                    i
+                })
+                .unwrap();
+        }
+
+        let mut values: Vec<usize> = group.wait_all().into_result().unwrap();
+        values.sort();
+        let mut expected: Vec<usize> = (0..4).chain(100..112).collect();
+        expected.sort();
+        assert_eq!(values, expected);
+        scheduler.shutdown();
+    }
+
+    #[test]
+    fn test_cancelling_a_group_cancels_its_children() {
+        let scheduler = started_scheduler(1);
+        let group = scheduler.group::<()>();
+        let child = group.child::<u32>();
+
+        // Hold the only worker so nothing else starts before the cancel
+        let (started_tx, started_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let blocker = scheduler
+            .submit_with_result(move || {
+                started_tx.send(()).unwrap();
+                let _ = release_rx.recv_timeout(Duration::from_secs(10));
+            })
+            .unwrap();
+        started_rx.recv_timeout(Duration::from_secs(5)).expect("blocker never started");
+
+        group.submit(|| ()).unwrap();
+        child.submit(|| 1).unwrap();
+        group.cancel();
+        assert!(child.is_cancelled());
+        release_tx.send(()).unwrap();
+        blocker.join().unwrap();
+
+        // The parent waits for the child's task, whose outcome stays with the child
+        let results = group.wait_all();
+        assert!(results.completed.is_empty());
+        assert!(results.failed.iter().all(|(_, error)| error.is_cancelled()));
+        let child_results = child.wait_all_timeout(Duration::ZERO).expect("parent wait covers the child");
+        assert!(child_results.failed[0].1.is_cancelled());
+
+        let late = group.child::<()>();
+        assert!(late.is_cancelled());
+        scheduler.shutdown();
+    }
+}
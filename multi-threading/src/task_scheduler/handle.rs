@@ -0,0 +1,313 @@
+use std::any::Any;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use super::{CancellationToken, SchedulerState, TaskScheduler};
+
+/// Panic payload captured from a task, as produced by `catch_unwind`
+pub type PanicPayload = Box<dyn Any + Send + 'static>;
+
+/// Reason a task handle could not produce a value
+pub enum JoinError {
+    /// The task panicked; the payload is the value passed to `panic!`
+    Panicked(PanicPayload),
+    /// The task was dropped by the scheduler without ever running
+    Dropped,
+    /// The task was cancelled before it started
+    Cancelled,
+}
+
+impl JoinError {
+    /// Whether the task panicked
+    pub fn is_panic(&self) -> bool {
+        matches!(self, JoinError::Panicked(_))
+    }
+
+    /// Whether the task was cancelled before it started
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, JoinError::Cancelled)
+    }
+
+    /// Consume the error and return the panic payload, if the task panicked
+    pub fn into_panic(self) -> Option<PanicPayload> {
+        match self {
+            JoinError::Panicked(payload) => Some(payload),
+            _ => None,
+        }
+    }
+
+    /// Best-effort panic message for `panic!("literal")` and `panic!("{}", ..)` payloads
+    pub fn panic_message(&self) -> Option<&str> {
+        match self {
+            JoinError::Panicked(payload) => panic_message(payload.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Debug for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinError::Panicked(payload) => f
+                .debug_tuple("Panicked")
+                .field(&panic_message(payload.as_ref()).unwrap_or("<non-string payload>"))
+                .finish(),
+            JoinError::Dropped => f.write_str("Dropped"),
+            JoinError::Cancelled => f.write_str("Cancelled"),
+        }
+    }
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinError::Panicked(payload) => match panic_message(payload.as_ref()) {
+                Some(message) => write!(f, "task panicked: {}", message),
+                None => write!(f, "task panicked"),
+            },
+            JoinError::Dropped => write!(f, "task was dropped before it could run"),
+            JoinError::Cancelled => write!(f, "task was cancelled before it could run"),
+        }
+    }
+}
+
+impl std::error::Error for JoinError {}
+
+/// Extract the message from a panic payload if it is a string
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> Option<&str> {
+    if let Some(message) = payload.downcast_ref::<&'static str>() {
+        Some(message)
+    } else {
+        payload.downcast_ref::<String>().map(|message| message.as_str())
+    }
+}
+
+/// Result slot shared between a running task and its handle
+struct Shared<T> {
+    outcome: Mutex<Option<Result<T, JoinError>>>,
+    ready: Condvar,
+    /// Task awaiting the handle; only registered while `outcome` is locked
+    waker: Mutex<Option<Waker>>,
+}
+
+impl<T> Shared<T> {
+    fn set(&self, outcome: Result<T, JoinError>) {
+        let waker = {
+            let mut slot = self.outcome.lock().unwrap_or_else(|e| e.into_inner());
+            if slot.is_none() {
+                *slot = Some(outcome);
+            }
+            self.ready.notify_all();
+            self.waker.lock().unwrap_or_else(|e| e.into_inner()).take()
+        };
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// Write side of a task handle, moved into the task closure
+///
+/// If the closure is dropped without running (e.g. the scheduler shuts down),
+/// the handle observes `JoinError::Dropped` instead of blocking forever, or
+/// `JoinError::Cancelled` when the task's token was cancelled.
+pub(crate) struct Completer<T> {
+    shared: Option<Arc<Shared<T>>>,
+    token: CancellationToken,
+}
+
+impl<T> Completer<T> {
+    /// Store the task outcome and wake any joiner
+    pub(crate) fn complete(mut self, outcome: Result<T, JoinError>) {
+        if let Some(shared) = self.shared.take() {
+            shared.set(outcome);
+        }
+    }
+}
+
+impl<T> Drop for Completer<T> {
+    fn drop(&mut self) {
+        if let Some(shared) = self.shared.take() {
+            if self.token.is_cancelled() {
+                shared.set(Err(JoinError::Cancelled));
+            } else {
+                shared.set(Err(JoinError::Dropped));
+            }
+        }
+    }
+}
+
+/// Typed handle to a task submitted with `submit_with_result` or `spawn_future`
+///
+/// Besides the blocking `join` methods, the handle is a future resolving to
+/// the same outcome, so one task can await another.
+///
+/// In simulation mode, waiting on a handle runs the simulation on the
+/// waiting thread until the task finishes.
+pub struct TaskHandle<T> {
+    id: u64,
+    shared: Arc<Shared<T>>,
+    /// The scheduler to drive while waiting, in simulation mode
+    simulation: Option<Weak<SchedulerState>>,
+}
+
+/// Create a connected completer/handle pair; the handle id is assigned on submit
+pub(crate) fn pair<T>(token: CancellationToken) -> (Completer<T>, TaskHandle<T>) {
+    let shared = Arc::new(Shared {
+        outcome: Mutex::new(None),
+        ready: Condvar::new(),
+        waker: Mutex::new(None),
+    });
+
+    let completer = Completer {
+        shared: Some(Arc::clone(&shared)),
+        token,
+    };
+
+    let handle = TaskHandle {
+        id: 0,
+        shared,
+        simulation: None,
+    };
+    (completer, handle)
+}
+
+impl<T> TaskHandle<T> {
+    pub(crate) fn with_id(mut self, id: u64) -> Self {
+        self.id = id;
+        self
+    }
+
+    /// Run `state`'s simulation while waiting, if it has one
+    pub(super) fn driven_by(mut self, state: &Arc<SchedulerState>) -> Self {
+        if state.simulation.is_some() {
+            self.simulation = Some(Arc::downgrade(state));
+        }
+        self
+    }
+
+    /// Run the simulation on this thread until the task finishes, or
+    /// nothing in the simulation can make progress
+    fn drive_simulation(&self) {
+        let Some(state) = self.simulation.as_ref().and_then(Weak::upgrade) else {
+            return;
+        };
+        while !self.is_finished() && TaskScheduler::drive_simulation(&state) {}
+    }
+
+    /// Id of the underlying scheduled task
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Whether the task has finished (successfully, by panicking, or by being dropped)
+    pub fn is_finished(&self) -> bool {
+        self.shared
+            .outcome
+            .lock()
+            .map(|slot| slot.is_some())
+            .unwrap_or(true)
+    }
+
+    /// Block until the task finishes and return its value
+    pub fn join(self) -> Result<T, JoinError> {
+        self.drive_simulation();
+        let mut slot = self.shared.outcome.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            if let Some(outcome) = slot.take() {
+                return outcome;
+            }
+            slot = self.shared.ready.wait(slot).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Return the outcome if the task already finished, or hand the handle back
+    pub fn try_join(self) -> Result<Result<T, JoinError>, Self> {
+        let outcome = self
+            .shared
+            .outcome
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+
+        match outcome {
+            Some(outcome) => Ok(outcome),
+            None => Err(self),
+        }
+    }
+
+    /// Wait up to `timeout` for the task, handing the handle back if it is still running
+    ///
+    /// A timeout too large to add to the current time waits as long as `join`.
+    /// In simulation mode the simulation is run first, so the timeout only
+    /// bounds the wait for work outside it.
+    pub fn join_timeout(self, timeout: Duration) -> Result<Result<T, JoinError>, Self> {
+        let deadline = Instant::now().checked_add(timeout);
+        self.drive_simulation();
+        let outcome = {
+            let mut slot = self.shared.outcome.lock().unwrap_or_else(|e| e.into_inner());
+            loop {
+                if slot.is_some() {
+                    break slot.take();
+                }
+                slot = match deadline {
+                    Some(deadline) => {
+                        let now = Instant::now();
+                        if now >= deadline {
+                            break None;
+                        }
+                        self.shared
+                            .ready
+                            .wait_timeout(slot, deadline - now)
+                            .map(|(guard, _)| guard)
+                            .unwrap_or_else(|e| e.into_inner().0)
+                    }
+                    None => self.shared.ready.wait(slot).unwrap_or_else(|e| e.into_inner()),
+                };
+            }
+        };
+
+        match outcome {
+            Some(outcome) => Ok(outcome),
+            None => Err(self),
+        }
+    }
+}
+
+impl<T> Future for TaskHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slot = self.shared.outcome.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(outcome) = slot.take() {
+            return Poll::Ready(outcome);
+        }
+        *self.shared.waker.lock().unwrap_or_else(|e| e.into_inner()) = Some(cx.waker().clone());
+        drop(slot);
+
+        // Awaited from outside the simulation, e.g. by `block_on`: take a
+        // step of it and ask to be polled again. A simulated task awaiting
+        // the handle is woken by the step that finishes it.
+        if let Some(state) = self.simulation.as_ref().and_then(Weak::upgrade)
+            && !state.in_simulated_step()
+            && TaskScheduler::drive_simulation(&state)
+        {
+            cx.waker().wake_by_ref();
+        }
+        Poll::Pending
+    }
+}
+
+impl<T> fmt::Debug for TaskHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TaskHandle")
+            .field("id", &self.id)
+            .field("finished", &self.is_finished())
+            .finish()
+    }
+}
@@ -0,0 +1,291 @@
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::time::Duration;
+
+use super::{Priority, SchedulerEvent, SchedulerState, TaskExit, TaskFailure, TaskMetadata};
+
+/// A task about to run, passed to `SchedulerHooks::before_task`
+#[derive(Debug, Clone, Copy)]
+pub struct TaskStart {
+    pub task_id: u64,
+    /// `None` when the task runs on its submitter under `RejectionPolicy::CallerRuns`
+    pub worker_id: Option<usize>,
+    pub priority: Priority,
+    /// Time from submission until this run started
+    pub queue_wait: Duration,
+    /// Whether the worker stole the task from another worker's queue
+    pub stolen: bool,
+}
+
+/// A task that has just run, passed to `SchedulerHooks::after_task`
+#[derive(Debug, Clone, Copy)]
+pub struct TaskEnd {
+    pub task_id: u64,
+    pub worker_id: Option<usize>,
+    pub queue_wait: Duration,
+    pub execution_time: Duration,
+    pub panicked: bool,
+    /// `false` when the run asked to run again: a future that is still
+    /// pending, a failed attempt with retries left or a periodic task
+    pub finished: bool,
+}
+
+/// Observers of workers and tasks, e.g. to open tracing spans or carry
+/// per-task context such as request ids from the submitter to the worker
+///
+/// Every method does nothing by default. `before_task` and `after_task` are
+/// called on the thread running the task, straight before and after it, so
+/// thread-local state set in one is still there in the other. Hooks run on
+/// the scheduler's threads and should return quickly. A hook that panics
+/// is reported as `SchedulerEvent::HookPanicked` and otherwise ignored.
+pub trait SchedulerHooks: Send + Sync {
+    /// A task was accepted; called on the submitting thread, before any
+    /// worker can start the task
+    fn on_submit(&self, _task_id: u64) {}
+
+    /// Called on the new worker thread before it looks for work
+    fn on_worker_start(&self, _worker_id: usize) {}
+
+    /// Called on the worker thread as it exits, unless it panicked
+    fn on_worker_stop(&self, _worker_id: usize) {}
+
+    fn before_task(&self, _task: &TaskStart) {}
+
+    fn after_task(&self, _task: &TaskEnd) {}
+
+    /// Worker `thief` took task `task_id` from worker `victim`'s queue
+    fn on_steal(&self, _thief: usize, _victim: usize, _task_id: u64) {}
+
+    /// A `TaskWaiting` or `TaskOverrunning` event, whatever the execution
+    /// timeout action; it also goes to the event sink as usual
+    fn on_timeout_warning(&self, _warning: &SchedulerEvent) {}
+}
+
+impl SchedulerState {
+    /// Call one hook method on every observer; a panicking observer is
+    /// reported and skipped, so it cannot take the worker down with it
+    pub(super) fn notify_hooks(&self, hook: &'static str, f: impl Fn(&dyn SchedulerHooks)) {
+        for hooks in &self.hooks {
+            if catch_unwind(AssertUnwindSafe(|| f(hooks.as_ref()))).is_err() {
+                self.emit(SchedulerEvent::HookPanicked { hook });
+            }
+        }
+    }
+
+    pub(super) fn before_task(&self, metadata: &TaskMetadata, worker_id: Option<usize>, queue_wait: Duration, stolen: bool) {
+        if self.hooks.is_empty() {
+            return;
+        }
+
+        let start = TaskStart {
+            task_id: metadata.id,
+            worker_id,
+            priority: metadata.priority,
+            queue_wait,
+            stolen,
+        };
+        self.notify_hooks("before_task", |hooks| hooks.before_task(&start));
+    }
+
+    pub(super) fn after_task(
+        &self,
+        metadata: &TaskMetadata,
+        worker_id: Option<usize>,
+        queue_wait: Duration,
+        execution_time: Duration,
+        exit: &TaskExit,
+    ) {
+        if self.hooks.is_empty() {
+            return;
+        }
+
+        let end = TaskEnd {
+            task_id: metadata.id,
+            worker_id,
+            queue_wait,
+            execution_time,
+            panicked: matches!(
+                exit,
+                TaskExit::Panicked | TaskExit::Failed { failure: TaskFailure::Panicked(_), .. }
+            ),
+            finished: !matches!(exit, TaskExit::Retry { .. } | TaskExit::Recur { .. } | TaskExit::Pending),
+        };
+        self.notify_hooks("after_task", |hooks| hooks.after_task(&end));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task_scheduler::{ExecutionTimeoutAction, SchedulerConfig, TaskScheduler, test_config};
+    use std::cell::Cell;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Instant;
+
+    thread_local! {
+        static REQUEST_ID: Cell<Option<u32>> = const { Cell::new(None) };
+    }
+
+    /// Carries the submitter's request id to the worker, and logs the rest
+    #[derive(Default)]
+    struct Tracing {
+        request_ids: Mutex<HashMap<u64, u32>>,
+        workers: Mutex<Vec<String>>,
+        ends: Mutex<Vec<TaskEnd>>,
+        steals: Mutex<Vec<(usize, usize, u64)>>,
+        warnings: AtomicUsize,
+    }
+
+    impl SchedulerHooks for Tracing {
+        fn on_submit(&self, task_id: u64) {
+            if let Some(request_id) = REQUEST_ID.get() {
+                self.request_ids.lock().unwrap().insert(task_id, request_id);
+            }
+        }
+
+        fn on_worker_start(&self, worker_id: usize) {
+            self.workers.lock().unwrap().push(format!("start {}", worker_id));
+        }
+
+        fn on_worker_stop(&self, worker_id: usize) {
+            self.workers.lock().unwrap().push(format!("stop {}", worker_id));
+        }
+
+        fn before_task(&self, task: &TaskStart) {
+            let request_id = self.request_ids.lock().unwrap().get(&task.task_id).copied();
+            REQUEST_ID.set(request_id);
+        }
+
+        fn after_task(&self, task: &TaskEnd) {
+            REQUEST_ID.set(None);
+            if task.finished {
+                self.request_ids.lock().unwrap().remove(&task.task_id);
+            }
+            self.ends.lock().unwrap().push(*task);
+        }
+
+        fn on_steal(&self, thief: usize, victim: usize, task_id: u64) {
+            self.steals.lock().unwrap().push((thief, victim, task_id));
+        }
+
+        fn on_timeout_warning(&self, warning: &SchedulerEvent) {
+            assert!(matches!(warning, SchedulerEvent::TaskOverrunning { .. }));
+            self.warnings.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn traced_scheduler(config: SchedulerConfig) -> (TaskScheduler, Arc<Tracing>) {
+        let tracing = Arc::new(Tracing::default());
+        let config = SchedulerConfig {
+            hooks: vec![Arc::clone(&tracing) as Arc<dyn SchedulerHooks>],
+            ..config
+        };
+
+        let mut scheduler = TaskScheduler::new(config);
+        scheduler.start();
+        (scheduler, tracing)
+    }
+
+    #[test]
+    fn test_hooks_carry_request_ids_to_the_worker() {
+        let (scheduler, tracing) = traced_scheduler(test_config(2));
+
+        let handles: Vec<_> = (0..4)
+            .map(|request_id| {
+                REQUEST_ID.set(Some(request_id));
+                scheduler.submit_with_result(|| REQUEST_ID.get()).unwrap()
+            })
+            .collect();
+        REQUEST_ID.set(None);
+        let panicked = scheduler.submit_with_result(|| panic!("expected test panic")).unwrap();
+
+        let request_ids: Vec<_> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+        assert_eq!(request_ids, vec![Some(0), Some(1), Some(2), Some(3)]);
+        assert!(panicked.join().is_err());
+        scheduler.shutdown_drain();
+
+        let ends = tracing.ends.lock().unwrap();
+        assert_eq!(ends.len(), 5);
+        assert!(ends.iter().all(|end| end.finished && end.worker_id.is_some()));
+        assert_eq!(ends.iter().filter(|end| end.panicked).count(), 1);
+        assert!(tracing.request_ids.lock().unwrap().is_empty());
+
+        let mut workers = tracing.workers.lock().unwrap().clone();
+        workers.sort();
+        assert_eq!(workers, ["start 0", "start 1", "stop 0", "stop 1"]);
+    }
+
+    #[test]
+    fn test_steals_and_timeout_warnings_reach_the_hooks() {
+        let (scheduler, tracing) = traced_scheduler(SchedulerConfig {
+            execution_timeout_ms: 20,
+            execution_timeout_action: ExecutionTimeoutAction::Callback(Arc::new(|_| {})),
+            ..test_config(2)
+        });
+        let scheduler = Arc::new(scheduler);
+
+        // Tasks spawned by a task land on its worker's queue, so the other
+        // worker can only run them by stealing; the spawner waits for that
+        let scheduler_clone = Arc::clone(&scheduler);
+        let tracing_clone = Arc::clone(&tracing);
+        scheduler
+            .submit_with_result(move || {
+                for _ in 0..4 {
+                    scheduler_clone.submit(|| thread::sleep(Duration::from_millis(5))).unwrap();
+                }
+                let deadline = Instant::now() + Duration::from_secs(5);
+                while tracing_clone.steals.lock().unwrap().is_empty() && Instant::now() < deadline {
+                    thread::sleep(Duration::from_millis(5));
+                }
+                thread::sleep(Duration::from_millis(100));
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+
+        let steals = tracing.steals.lock().unwrap().clone();
+        assert!(!steals.is_empty());
+        assert!(steals.iter().all(|&(thief, victim, _)| thief != victim));
+        assert!(tracing.warnings.load(Ordering::SeqCst) >= 1);
+
+        // The handle resolves just before `after_task` runs for the spawner
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let spawner_ended = || {
+            let ends = tracing.ends.lock().unwrap();
+            ends.iter().any(|end| end.execution_time >= Duration::from_millis(100))
+        };
+        while !spawner_ended() {
+            assert!(Instant::now() < deadline, "after_task never saw the spawner");
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_panicking_hook_does_not_kill_the_worker() {
+        struct Exploding;
+
+        impl SchedulerHooks for Exploding {
+            fn after_task(&self, _task: &TaskEnd) {
+                panic!("expected test panic");
+            }
+        }
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink_events = Arc::clone(&events);
+        let mut scheduler = TaskScheduler::new(SchedulerConfig {
+            hooks: vec![Arc::new(Exploding)],
+            event_sink: Arc::new(move |event: &SchedulerEvent| sink_events.lock().unwrap().push(event.to_string())),
+            ..test_config(1)
+        });
+        scheduler.start();
+
+        for _ in 0..3 {
+            scheduler.submit(|| {}).unwrap();
+        }
+        let report = scheduler.shutdown_timeout(Duration::from_secs(5));
+        assert_eq!((report.completed, report.dropped, report.worker_panics), (3, 0, 0));
+        assert_eq!(events.lock().unwrap().iter().filter(|event| *event == "Hook after_task panicked").count(), 3);
+    }
+}
@@ -0,0 +1,553 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+
+use super::{
+    CancellationToken, Priority, SchedulerError, SchedulerState, TaskExit, TaskFailure, TaskScheduler,
+};
+
+/// Runs a journaled job from its payload; on `Err` the job is marked done
+/// and recorded as a dead letter, so it is not replayed
+pub type JobHandler = Arc<dyn Fn(&[u8]) -> Result<(), String> + Send + Sync>;
+
+/// Completions after which the journal is rewritten without them, once
+/// they also outnumber the jobs still pending
+const COMPACT_AFTER: usize = 1024;
+
+/// The job types a journal can run, by name
+#[derive(Clone, Default)]
+pub struct JobRegistry {
+    handlers: HashMap<String, JobHandler>,
+}
+
+impl JobRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run jobs submitted as `name` with `handler`, replacing any earlier one
+    pub fn register<F>(mut self, name: impl Into<String>, handler: F) -> Self
+    where
+        F: Fn(&[u8]) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.handlers.insert(name.into(), Arc::new(handler));
+        self
+    }
+
+    fn handler(&self, name: &str) -> Option<JobHandler> {
+        self.handlers.get(name).cloned()
+    }
+}
+
+/// A job submitted but not yet marked done
+#[derive(Clone)]
+struct PendingJob {
+    name: String,
+    payload: Vec<u8>,
+}
+
+/// One line of the journal file
+enum Record {
+    Submitted { seq: u64, job: PendingJob },
+    Done { seq: u64 },
+}
+
+impl Record {
+    /// `S <seq> <hex name> <hex payload>` or `D <seq>`
+    fn encode(&self) -> String {
+        match self {
+            Record::Submitted { seq, job } => {
+                format!("S {} {} {}\n", seq, hex_encode(job.name.as_bytes()), hex_encode(&job.payload))
+            }
+            Record::Done { seq } => format!("D {}\n", seq),
+        }
+    }
+
+    /// `None` for a malformed line, such as one torn by a crash mid-write
+    fn decode(line: &str) -> Option<Self> {
+        let mut fields = line.split(' ');
+        let kind = fields.next()?;
+        let seq = fields.next()?.parse().ok()?;
+        let record = match kind {
+            "S" => Record::Submitted {
+                seq,
+                job: PendingJob {
+                    name: String::from_utf8(hex_decode(fields.next()?)?).ok()?,
+                    payload: hex_decode(fields.next()?)?,
+                },
+            },
+            "D" => Record::Done { seq },
+            _ => return None,
+        };
+        fields.next().is_none().then_some(record)
+    }
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn hex_decode(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// The journal file and the jobs it still holds
+struct JournalFile {
+    file: File,
+    pending: BTreeMap<u64, PendingJob>,
+    next_seq: u64,
+    completed_since_compaction: usize,
+}
+
+/// Append-only record of submitted and finished jobs, kept so that jobs a
+/// crash interrupted can run again on the next start
+pub(super) struct Journal {
+    path: PathBuf,
+    registry: JobRegistry,
+    file: Mutex<JournalFile>,
+}
+
+impl Journal {
+    /// Read the jobs left pending at `path`, then compact it
+    fn open(path: &Path, registry: JobRegistry) -> io::Result<Self> {
+        let mut pending = BTreeMap::new();
+        let mut next_seq = 1;
+        match File::open(path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    match Record::decode(&line?) {
+                        Some(Record::Submitted { seq, job }) => {
+                            next_seq = next_seq.max(seq + 1);
+                            pending.insert(seq, job);
+                        }
+                        Some(Record::Done { seq }) => {
+                            pending.remove(&seq);
+                        }
+                        None => {}
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        let file = Self::rewrite(path, &pending)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            registry,
+            file: Mutex::new(JournalFile {
+                file,
+                pending,
+                next_seq,
+                completed_since_compaction: 0,
+            }),
+        })
+    }
+
+    /// Replace the file at `path` with one holding only `pending`, through a
+    /// rename so a crash leaves either the old journal or the new one
+    fn rewrite(path: &Path, pending: &BTreeMap<u64, PendingJob>) -> io::Result<File> {
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+
+        let mut temp = File::create(&temp_path)?;
+        for (&seq, job) in pending {
+            let record = Record::Submitted { seq, job: job.clone() };
+            temp.write_all(record.encode().as_bytes())?;
+        }
+        temp.sync_all()?;
+        fs::rename(&temp_path, path)?;
+
+        OpenOptions::new().append(true).open(path)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, JournalFile> {
+        self.file.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Record a new job durably, returning its sequence number
+    fn submit(&self, name: &str, payload: &[u8]) -> io::Result<u64> {
+        let mut journal = self.lock();
+        let seq = journal.next_seq;
+        let job = PendingJob {
+            name: name.to_string(),
+            payload: payload.to_vec(),
+        };
+
+        journal.file.write_all(Record::Submitted { seq, job: job.clone() }.encode().as_bytes())?;
+        // Losing a submit would lose the job, so it is synced; losing a
+        // done record only runs a finished job again
+        journal.file.sync_data()?;
+
+        journal.next_seq += 1;
+        journal.pending.insert(seq, job);
+        Ok(seq)
+    }
+
+    /// Mark a job done, so it is not run again
+    fn complete(&self, seq: u64) -> io::Result<()> {
+        let mut journal = self.lock();
+        if journal.pending.remove(&seq).is_none() {
+            return Ok(());
+        }
+        journal.file.write_all(Record::Done { seq }.encode().as_bytes())?;
+
+        journal.completed_since_compaction += 1;
+        if journal.completed_since_compaction >= COMPACT_AFTER
+            && journal.completed_since_compaction > journal.pending.len()
+        {
+            self.compact_locked(&mut journal)?;
+        }
+        Ok(())
+    }
+
+    fn compact_locked(&self, journal: &mut JournalFile) -> io::Result<()> {
+        journal.file = Self::rewrite(&self.path, &journal.pending)?;
+        journal.completed_since_compaction = 0;
+        Ok(())
+    }
+
+    fn pending(&self) -> Vec<(u64, String, Vec<u8>)> {
+        let journal = self.lock();
+        journal
+            .pending
+            .iter()
+            .map(|(&seq, job)| (seq, job.name.clone(), job.payload.clone()))
+            .collect()
+    }
+}
+
+/// A journaled job on its way through the queues; marks itself done once
+/// its handler returns, or when it is cancelled before running
+struct JournaledJob {
+    seq: u64,
+    handler: JobHandler,
+    payload: Vec<u8>,
+    journal: Arc<Journal>,
+    state: Weak<SchedulerState>,
+    cancel_token: CancellationToken,
+    ran: bool,
+}
+
+impl JournaledJob {
+    fn run(mut self) -> TaskExit {
+        self.ran = true;
+        let outcome = (self.handler)(&self.payload);
+        // A failure would most likely fail again, so replaying it on every
+        // start would never end; it goes to the dead letters instead
+        self.complete();
+        match outcome {
+            Ok(()) => TaskExit::Completed,
+            Err(message) => TaskExit::Failed {
+                attempts: 1,
+                failure: TaskFailure::Error(message),
+            },
+        }
+    }
+
+    fn complete(&self) {
+        if let Err(e) = self.journal.complete(self.seq)
+            && let Some(state) = self.state.upgrade()
+        {
+            state.record_error(None, SchedulerError::Journal { kind: e.kind() });
+        }
+    }
+}
+
+impl Drop for JournaledJob {
+    fn drop(&mut self) {
+        // A job dropped by shutdown stays in the journal to run on the next start
+        if !self.ran && self.cancel_token.is_cancelled() {
+            self.complete();
+        }
+    }
+}
+
+impl TaskScheduler {
+    /// Keep jobs submitted with `submit_job` in a journal at `path`, and queue
+    /// the jobs it holds from an earlier run that never finished
+    ///
+    /// Returns how many jobs were queued again. A replayed job with no
+    /// handler in `registry` stays in the journal for a later run, and is
+    /// reported as `SchedulerError::UnknownJob`.
+    pub fn open_journal(&mut self, path: impl AsRef<Path>, registry: JobRegistry) -> Result<usize, SchedulerError> {
+        let journal = Journal::open(path.as_ref(), registry).map_err(|e| SchedulerError::Journal { kind: e.kind() })?;
+        let journal = Arc::new(journal);
+        self.journal = Some(Arc::clone(&journal));
+
+        let mut replayed = 0;
+        for (seq, name, payload) in journal.pending() {
+            let Some(handler) = journal.registry.handler(&name) else {
+                self.state.record_error(None, SchedulerError::UnknownJob);
+                continue;
+            };
+            self.enqueue_journaled(&journal, seq, handler, payload)?;
+            replayed += 1;
+        }
+        Ok(replayed)
+    }
+
+    /// Submit a job by its registered name; it is in the journal before this
+    /// returns, and runs again after a crash until its handler has returned
+    ///
+    /// A handler that returns `Err` is not run again: the job is marked done
+    /// and recorded as a dead letter under the returned id. A job the
+    /// scheduler refuses, e.g. with `QueueFull`, is marked done as well.
+    pub fn submit_job(&self, name: &str, payload: impl Into<Vec<u8>>) -> Result<u64, SchedulerError> {
+        let Some(journal) = &self.journal else {
+            return Err(SchedulerError::NoJournal);
+        };
+        let handler = journal.registry.handler(name).ok_or(SchedulerError::UnknownJob)?;
+        self.state.accepting()?;
+
+        let payload = payload.into();
+        let seq = journal.submit(name, &payload).map_err(|e| SchedulerError::Journal { kind: e.kind() })?;
+        self.enqueue_journaled(journal, seq, handler, payload).inspect_err(|_| {
+            // The caller is told the job was refused, so it must not be replayed
+            if let Err(e) = journal.complete(seq) {
+                self.state.record_error(None, SchedulerError::Journal { kind: e.kind() });
+            }
+        })
+    }
+
+    /// Rewrite the journal without the jobs that have finished
+    pub fn compact_journal(&self) -> Result<(), SchedulerError> {
+        let Some(journal) = &self.journal else {
+            return Ok(());
+        };
+        let mut file = journal.lock();
+        journal.compact_locked(&mut file).map_err(|e| SchedulerError::Journal { kind: e.kind() })
+    }
+
+    fn enqueue_journaled(
+        &self,
+        journal: &Arc<Journal>,
+        seq: u64,
+        handler: JobHandler,
+        payload: Vec<u8>,
+    ) -> Result<u64, SchedulerError> {
+        let cancel_token = CancellationToken::new();
+        let job = JournaledJob {
+            seq,
+            handler,
+            payload,
+            journal: Arc::clone(journal),
+            state: Arc::downgrade(&self.state),
+            cancel_token: cancel_token.clone(),
+            ran: false,
+        };
+        // A refused job is dropped uncancelled, so a replayed one stays in
+        // the journal; `submit_job` marks its own done
+        self.enqueue(Box::new(move || job.run()), Priority::NORMAL, cancel_token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task_scheduler::{SchedulerConfig, test_config};
+    use std::process::{Command, Stdio};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    /// Set for the child process of the crash test, to the directory it works in
+    const CRASH_CHILD_DIR: &str = "TASK_SCHEDULER_JOURNAL_CHILD";
+    const CRASH_JOBS: usize = 20;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("journal-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Not started yet, so a journal can be opened before any worker runs
+    fn unstarted_scheduler(num_workers: usize) -> TaskScheduler {
+        TaskScheduler::new(test_config(num_workers))
+    }
+
+    /// Appends each payload as a line to `done`, slowly enough to be killed mid-run
+    fn recording_registry(done: PathBuf) -> JobRegistry {
+        JobRegistry::new().register("record", move |payload: &[u8]| {
+            let mut file = OpenOptions::new().create(true).append(true).open(&done).map_err(|e| e.to_string())?;
+            // One write, so a kill cannot leave half a line for the next run to extend
+            file.write_all(&[payload, b"\n"].concat()).map_err(|e| e.to_string())?;
+            thread::sleep(Duration::from_millis(50));
+            Ok(())
+        })
+    }
+
+    fn done_lines(done: &Path) -> Vec<String> {
+        fs::read_to_string(done)
+            .map(|text| text.lines().map(str::to_string).collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn test_unfinished_jobs_are_replayed_and_compacted_away() {
+        let dir = temp_dir("replay");
+        let path = dir.join("jobs.journal");
+        let runs = Arc::new(AtomicUsize::new(0));
+        let runs_clone = Arc::clone(&runs);
+        let registry = JobRegistry::new()
+            .register("count", move |payload: &[u8]| {
+                runs_clone.fetch_add(usize::from(payload[0]), Ordering::SeqCst);
+                Ok(())
+            })
+            .register("fail", |_: &[u8]| Err("not yet".to_string()));
+
+        // Never started, so shutdown drops the jobs without running them
+        let mut scheduler = unstarted_scheduler(1);
+        assert_eq!(scheduler.open_journal(&path, registry.clone()).unwrap(), 0);
+        scheduler.submit_job("count", [1]).unwrap();
+        let cancelled = scheduler.submit_job("count", [10]).unwrap();
+        scheduler.submit_job("fail", []).unwrap();
+        assert_eq!(scheduler.submit_job("missing", []), Err(SchedulerError::UnknownJob));
+        assert!(scheduler.cancel(cancelled));
+        assert_eq!(scheduler.shutdown().dropped, 2);
+
+        let mut scheduler = unstarted_scheduler(1);
+        assert_eq!(scheduler.open_journal(&path, registry.clone()).unwrap(), 2);
+        scheduler.start();
+        let report = scheduler.shutdown_drain();
+        assert_eq!((report.completed, report.failed), (1, 1));
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        // The failed job is a dead letter rather than replayed, and
+        // compaction leaves nothing behind
+        let mut scheduler = unstarted_scheduler(1);
+        assert_eq!(scheduler.open_journal(&path, registry).unwrap(), 0);
+        scheduler.compact_journal().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 0);
+        scheduler.shutdown();
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed_jobs_become_dead_letters_instead_of_replaying() {
+        let dir = temp_dir("failed");
+        let path = dir.join("jobs.journal");
+        let registry = JobRegistry::new().register("fail", |_: &[u8]| Err("bad payload".to_string()));
+
+        let mut scheduler = unstarted_scheduler(1);
+        scheduler.open_journal(&path, registry.clone()).unwrap();
+        scheduler.start();
+        let task_id = scheduler.submit_job("fail", []).unwrap();
+        assert!(scheduler.wait_until_idle(None));
+        let dead_letters = scheduler.dead_letters();
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0].task_id, task_id);
+        assert_eq!(dead_letters[0].failure, TaskFailure::Error("bad payload".into()));
+        scheduler.shutdown();
+
+        let mut scheduler = unstarted_scheduler(1);
+        assert_eq!(scheduler.open_journal(&path, registry).unwrap(), 0);
+        scheduler.shutdown();
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_refused_jobs_are_not_replayed() {
+        let dir = temp_dir("refused");
+        let path = dir.join("jobs.journal");
+        let registry = JobRegistry::new().register("noop", |_: &[u8]| Ok(()));
+
+        let mut scheduler = TaskScheduler::new(SchedulerConfig {
+            queue_capacity: 1,
+            ..test_config(1)
+        });
+        scheduler.open_journal(&path, registry.clone()).unwrap();
+        scheduler.submit(|| {}).unwrap();
+        assert_eq!(scheduler.submit_job("noop", []), Err(SchedulerError::QueueFull));
+        scheduler.shutdown();
+
+        let mut scheduler = unstarted_scheduler(1);
+        assert_eq!(scheduler.open_journal(&path, registry).unwrap(), 0);
+        scheduler.shutdown();
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_torn_records_are_ignored() {
+        let dir = temp_dir("torn");
+        let path = dir.join("jobs.journal");
+        let job = PendingJob {
+            name: "count".to_string(),
+            payload: vec![7],
+        };
+        let mut contents = Record::Submitted { seq: 4, job }.encode();
+        contents.push_str("D 9\nS 5 6");
+        fs::write(&path, contents).unwrap();
+
+        let journal = Journal::open(&path, JobRegistry::new()).unwrap();
+        assert_eq!(journal.pending(), vec![(4, "count".to_string(), vec![7])]);
+        assert_eq!(journal.submit("count", &[]).unwrap(), 5);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_jobs_survive_a_killed_process() {
+        if let Ok(dir) = std::env::var(CRASH_CHILD_DIR) {
+            let dir = PathBuf::from(dir);
+            let mut scheduler = unstarted_scheduler(1);
+            scheduler.open_journal(dir.join("jobs.journal"), recording_registry(dir.join("done"))).unwrap();
+            scheduler.start();
+            for job in 0..CRASH_JOBS {
+                scheduler.submit_job("record", job.to_string()).unwrap();
+            }
+            // Killed by the parent long before this returns
+            scheduler.shutdown_drain();
+            return;
+        }
+
+        let dir = temp_dir("crash");
+        let test_name = format!("{}::test_jobs_survive_a_killed_process", module_path!());
+        let test_name = test_name.split_once("::").map_or(test_name.as_str(), |(_, path)| path);
+        let mut child = Command::new(std::env::current_exe().unwrap())
+            .args([test_name, "--exact", "--test-threads=1"])
+            .env(CRASH_CHILD_DIR, &dir)
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        let done = dir.join("done");
+        let deadline = Instant::now() + Duration::from_secs(20);
+        while done_lines(&done).len() < 5 {
+            assert!(Instant::now() < deadline, "child never got going");
+            thread::sleep(Duration::from_millis(10));
+        }
+        child.kill().unwrap();
+        child.wait().unwrap();
+        let before_crash = done_lines(&done).len();
+        assert!(before_crash < CRASH_JOBS);
+
+        let mut scheduler = unstarted_scheduler(2);
+        let replayed = scheduler.open_journal(dir.join("jobs.journal"), recording_registry(done.clone())).unwrap();
+        scheduler.start();
+        assert_eq!(scheduler.shutdown_drain().completed, replayed);
+
+        // Every job ran at least once; a job killed after its handler ran,
+        // but before it was marked done, runs twice
+        let mut lines = done_lines(&done);
+        assert!(replayed >= CRASH_JOBS - before_crash);
+        assert!(lines.len() <= CRASH_JOBS + 1);
+        lines.sort_by_key(|line| line.parse::<usize>().unwrap());
+        lines.dedup();
+        assert_eq!(lines, (0..CRASH_JOBS).map(|job| job.to_string()).collect::<Vec<_>>());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,377 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use super::{CancellationToken, Priority, ScheduledTask, SchedulerError, SchedulerState, TaskMetadata, TaskScheduler, TaskTags};
+
+/// Limits on how many tasks of one class run at once and how often they start
+///
+/// A task over its class's limits stays queued, without holding a worker,
+/// until the class has room again. Tasks the caller runs under
+/// `RejectionPolicy::CallerRuns` are not limited.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ClassLimit {
+    /// Most tasks of the class running at once (0 = unlimited)
+    pub max_concurrent: usize,
+    /// Tasks of the class started per second, on average (0 = unlimited)
+    pub per_second: f64,
+    /// Tasks that may start back to back once the class has been quiet for
+    /// a while (at least 1)
+    pub burst: u32,
+}
+
+impl ClassLimit {
+    fn is_rate_limited(&self) -> bool {
+        self.per_second > 0.0
+    }
+
+    /// Size of the token bucket
+    fn capacity(&self) -> f64 {
+        f64::from(self.burst.max(1))
+    }
+}
+
+/// Running tasks, rate tokens and held tasks of one class
+struct ClassState {
+    running: usize,
+    tokens: f64,
+    refilled_at: Instant,
+    /// Tasks that were over the limits when a worker took them, oldest first
+    held: VecDeque<ScheduledTask>,
+}
+
+impl ClassState {
+    fn refill(&mut self, limit: &ClassLimit, now: Instant) {
+        if limit.is_rate_limited() {
+            let elapsed = now.saturating_duration_since(self.refilled_at).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * limit.per_second).min(limit.capacity());
+        }
+        self.refilled_at = now;
+    }
+
+    /// Tasks that could start now without breaking the limits
+    fn room(&self, limit: &ClassLimit) -> usize {
+        let concurrent = match limit.max_concurrent {
+            0 => usize::MAX,
+            max => max.saturating_sub(self.running),
+        };
+        let rate = if limit.is_rate_limited() { self.tokens as usize } else { usize::MAX };
+        concurrent.min(rate)
+    }
+
+    /// When a token next arrives, if that is all a held task waits for
+    fn next_token(&self, limit: &ClassLimit) -> Option<Instant> {
+        let waits_for_token = !self.held.is_empty()
+            && limit.is_rate_limited()
+            && self.tokens < 1.0
+            && (limit.max_concurrent == 0 || self.running < limit.max_concurrent);
+        // Rounded up, so the token has surely arrived by then
+        let nanos = ((1.0 - self.tokens) / limit.per_second * 1e9).ceil().max(1.0);
+        waits_for_token.then(|| self.refilled_at + Duration::from_nanos(nanos as u64))
+    }
+}
+
+struct ClassGate {
+    limit: ClassLimit,
+    state: Mutex<ClassState>,
+}
+
+impl ClassGate {
+    fn lock(&self) -> MutexGuard<'_, ClassState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The classes configured in `SchedulerConfig::class_limits`, indexed by
+/// `TaskTags::class`
+pub(super) struct TaskClasses {
+    ids: HashMap<String, u16>,
+    gates: Vec<ClassGate>,
+    /// Tasks held across all classes, so workers skip the class locks while there are none
+    held: AtomicUsize,
+}
+
+impl TaskClasses {
+    pub(super) fn new(limits: &HashMap<String, ClassLimit>, now: Instant) -> Self {
+        // Sorted so that simulation runs visit classes in the same order
+        let mut names: Vec<&String> = limits.keys().collect();
+        names.sort();
+
+        let gates = names
+            .iter()
+            .map(|name| {
+                let limit = limits[*name];
+                ClassGate {
+                    limit,
+                    state: Mutex::new(ClassState {
+                        running: 0,
+                        tokens: limit.capacity(),
+                        refilled_at: now,
+                        held: VecDeque::new(),
+                    }),
+                }
+            })
+            .collect();
+        let ids = names.into_iter().zip(0..).map(|(name, id)| (name.clone(), id)).collect();
+
+        Self {
+            ids,
+            gates,
+            held: AtomicUsize::new(0),
+        }
+    }
+
+    fn id(&self, name: &str) -> Option<u16> {
+        self.ids.get(name).copied()
+    }
+
+    /// Take a held task out of its class, for cancellation
+    pub(super) fn remove(&self, task_id: u64) -> Option<ScheduledTask> {
+        if self.held.load(Ordering::SeqCst) == 0 {
+            return None;
+        }
+        self.gates.iter().find_map(|gate| {
+            let mut state = gate.lock();
+            let index = state.held.iter().position(|task| task.metadata.id == task_id)?;
+            self.held.fetch_sub(1, Ordering::SeqCst);
+            state.held.remove(index)
+        })
+    }
+
+    /// Take every held task, for shutdown
+    pub(super) fn drain(&self) -> Vec<ScheduledTask> {
+        let mut drained = Vec::new();
+        for gate in &self.gates {
+            drained.extend(gate.lock().held.drain(..));
+        }
+        self.held.fetch_sub(drained.len(), Ordering::SeqCst);
+        drained
+    }
+
+    /// When the next held task that only waits for a rate token gets one
+    pub(super) fn next_token(&self) -> Option<Instant> {
+        if self.held.load(Ordering::SeqCst) == 0 {
+            return None;
+        }
+        self.gates.iter().filter_map(|gate| gate.lock().next_token(&gate.limit)).min()
+    }
+}
+
+impl SchedulerState {
+    /// Let a task a worker has taken start, counting it against its class,
+    /// or hold it back if the class is at its limits
+    ///
+    /// Returns `None` if the task was held, or dropped because it was cancelled.
+    pub(super) fn admit(&self, scheduled_task: ScheduledTask) -> Option<ScheduledTask> {
+        let Some(class) = scheduled_task.metadata.tags.class else {
+            return Some(scheduled_task);
+        };
+        if scheduled_task.cancel_token.is_cancelled() {
+            drop(scheduled_task);
+            self.task_finished();
+            return None;
+        }
+
+        let gate = &self.classes.gates[class as usize];
+        let mut state = gate.lock();
+        state.refill(&gate.limit, self.now());
+        if state.room(&gate.limit) == 0 {
+            state.held.push_back(scheduled_task);
+            self.classes.held.fetch_add(1, Ordering::SeqCst);
+            return None;
+        }
+
+        state.running += 1;
+        if gate.limit.is_rate_limited() {
+            state.tokens -= 1.0;
+        }
+        Some(scheduled_task)
+    }
+
+    /// Give back the place a task that `admit` let start held in its class
+    pub(super) fn leave_class(&self, metadata: &TaskMetadata) {
+        let Some(class) = metadata.tags.class else {
+            return;
+        };
+
+        let gate = &self.classes.gates[class as usize];
+        let mut state = gate.lock();
+        state.running -= 1;
+        drop(state);
+        self.release_held_class(gate);
+    }
+
+    /// Move held tasks back to the injector as far as their classes have room
+    pub(super) fn release_held(&self) {
+        // Checked without the locks, so this is nearly free while nothing is held
+        if self.classes.held.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+        for gate in &self.classes.gates {
+            self.release_held_class(gate);
+        }
+    }
+
+    fn release_held_class(&self, gate: &ClassGate) {
+        let mut state = gate.lock();
+        if state.held.is_empty() {
+            return;
+        }
+        state.refill(&gate.limit, self.now());
+        let room = state.room(&gate.limit).min(state.held.len());
+        let released: Vec<ScheduledTask> = state.held.drain(..room).collect();
+        drop(state);
+
+        if released.is_empty() {
+            return;
+        }
+        // Still checked against the limits when a worker takes them, so a
+        // task released alongside a newer one may be held again
+        self.classes.held.fetch_sub(released.len(), Ordering::SeqCst);
+        for scheduled_task in released {
+            self.injector.push(scheduled_task);
+        }
+        self.notify_work(false);
+    }
+}
+
+impl TaskScheduler {
+    /// Submit a task in one of the classes of `SchedulerConfig::class_limits`
+    ///
+    /// While the class is at its concurrency cap or out of rate tokens, the
+    /// task waits in a queue any worker picks it up from once the class has
+    /// room, and workers run other tasks meanwhile. Fails with
+    /// `SchedulerError::UnknownClass` if no limits are configured for `class`.
+    pub fn submit_with_class<F>(&self, class: &str, task: F) -> Result<u64, SchedulerError>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let class = self.state.classes.id(class).ok_or(SchedulerError::UnknownClass)?;
+        let tags = TaskTags {
+            class: Some(class),
+            ..TaskTags::default()
+        };
+        self.state
+            .enqueue_tagged(Self::job(task), Priority::NORMAL, tags, CancellationToken::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task_scheduler::{SchedulerConfig, test_config};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    fn config_with_class(name: &str, limit: ClassLimit, config: SchedulerConfig) -> SchedulerConfig {
+        SchedulerConfig {
+            class_limits: HashMap::from([(name.to_string(), limit)]),
+            ..config
+        }
+    }
+
+    #[test]
+    fn test_concurrency_cap_leaves_workers_free() {
+        let limit = ClassLimit {
+            max_concurrent: 1,
+            ..ClassLimit::default()
+        };
+        let mut scheduler = TaskScheduler::new(config_with_class("disk", limit, test_config(3)));
+        scheduler.start();
+
+        let running = Arc::new(AtomicUsize::new(0));
+        let most_running = Arc::new(AtomicUsize::new(0));
+        let finished = Arc::new(AtomicUsize::new(0));
+        for _ in 0..6 {
+            let running = Arc::clone(&running);
+            let most_running = Arc::clone(&most_running);
+            let finished = Arc::clone(&finished);
+            scheduler
+                .submit_with_class("disk", move || {
+                    let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    most_running.fetch_max(now_running, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(30));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    finished.fetch_add(1, Ordering::SeqCst);
+                })
+                .unwrap();
+        }
+
+        // Other work is not stuck behind the held tasks
+        let handle = scheduler.submit_with_result(|| 7).unwrap();
+        assert_eq!(handle.join().unwrap(), 7);
+        assert!(finished.load(Ordering::SeqCst) < 6);
+
+        assert!(scheduler.wait_until_idle(Some(Instant::now() + Duration::from_secs(5))));
+        assert_eq!(finished.load(Ordering::SeqCst), 6);
+        assert_eq!(most_running.load(Ordering::SeqCst), 1);
+        scheduler.shutdown();
+    }
+
+    #[test]
+    fn test_rate_limit_spaces_starts_in_virtual_time() {
+        let limit = ClassLimit {
+            per_second: 10.0,
+            burst: 2,
+            ..ClassLimit::default()
+        };
+        let mut scheduler = TaskScheduler::new(config_with_class("api", limit, SchedulerConfig {
+            simulation_seed: Some(3),
+            ..test_config(2)
+        }));
+        scheduler.start();
+        let scheduler = Arc::new(scheduler);
+
+        let started = Arc::new(Mutex::new(Vec::new()));
+        for _ in 0..5 {
+            let started = Arc::clone(&started);
+            let clock = Arc::clone(&scheduler);
+            scheduler
+                .submit_with_class("api", move || started.lock().unwrap().push(clock.now()))
+                .unwrap();
+        }
+        let begin = scheduler.now();
+        assert!(scheduler.run_until_idle());
+
+        // Two at once from the full bucket, then one per token
+        let offsets: Vec<Duration> = started.lock().unwrap().iter().map(|at| *at - begin).collect();
+        assert_eq!(offsets.len(), 5);
+        assert_eq!(offsets[1], Duration::ZERO);
+        for (i, offset) in offsets.iter().enumerate().skip(2) {
+            let expected = Duration::from_millis(100) * (i as u32 - 1);
+            assert!(offset.abs_diff(expected) < Duration::from_millis(1), "start {} at {:?}", i, offset);
+        }
+    }
+
+    #[test]
+    fn test_held_task_can_be_cancelled() {
+        let limit = ClassLimit {
+            per_second: 1.0,
+            burst: 1,
+            ..ClassLimit::default()
+        };
+        let mut scheduler = TaskScheduler::new(config_with_class("api", limit, SchedulerConfig {
+            simulation_seed: Some(1),
+            ..test_config(1)
+        }));
+        scheduler.start();
+
+        assert!(matches!(scheduler.submit_with_class("nope", || {}), Err(SchedulerError::UnknownClass)));
+
+        let ran = Arc::new(Mutex::new(Vec::new()));
+        let ids: Vec<u64> = (0..3)
+            .map(|i| {
+                let ran = Arc::clone(&ran);
+                scheduler.submit_with_class("api", move || ran.lock().unwrap().push(i)).unwrap()
+            })
+            .collect();
+
+        assert!(scheduler.step());
+        // The rest are held waiting for a token, so nothing else runs yet
+        assert!(!scheduler.step());
+        assert!(scheduler.cancel(ids[1]));
+        assert!(scheduler.run_until_idle());
+        assert_eq!(*ran.lock().unwrap(), vec![0, 2]);
+    }
+}
@@ -0,0 +1,260 @@
+use std::mem::MaybeUninit;
+use std::sync::Mutex;
+use std::sync::atomic::Ordering;
+use std::thread;
+
+use super::TaskScheduler;
+
+/// Input a parallel helper can cut in two and hand to separate tasks
+trait Producer: Send + Sized {
+    fn len(&self) -> usize;
+    fn split_at(self, mid: usize) -> (Self, Self);
+}
+
+impl<T: Sync> Producer for &[T] {
+    fn len(&self) -> usize {
+        <[T]>::len(self)
+    }
+
+    fn split_at(self, mid: usize) -> (Self, Self) {
+        <[T]>::split_at(self, mid)
+    }
+}
+
+impl<T: Send> Producer for &mut [T] {
+    fn len(&self) -> usize {
+        <[T]>::len(self)
+    }
+
+    fn split_at(self, mid: usize) -> (Self, Self) {
+        self.split_at_mut(mid)
+    }
+}
+
+/// Two producers of the same length split in step, e.g. inputs and their output slots
+impl<A: Producer, B: Producer> Producer for (A, B) {
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn split_at(self, mid: usize) -> (Self, Self) {
+        let (a_left, a_right) = self.0.split_at(mid);
+        let (b_left, b_right) = self.1.split_at(mid);
+        ((a_left, b_left), (a_right, b_right))
+    }
+}
+
+impl TaskScheduler {
+    /// Run two closures, potentially in parallel, and return both results
+    ///
+    /// `b` is pushed onto the current worker's queue, or the injector from
+    /// outside the pool, while `a` runs on the calling thread. If no idle
+    /// worker steals `b` in the meantime, the caller runs it itself. A panic
+    /// in either closure is resumed once both have finished.
+    pub fn join<A, B, RA, RB>(&self, a: A, b: B) -> (RA, RB)
+    where
+        A: FnOnce() -> RA + Send,
+        B: FnOnce() -> RB + Send,
+        RA: Send,
+        RB: Send,
+    {
+        let b = Mutex::new(Some(b));
+        let result_b = Mutex::new(None);
+
+        let result_a = self.scope(|s| {
+            // Only fails while shutting down, in which case `b` runs below
+            let _ = s.spawn(|| {
+                let b = b.lock().unwrap_or_else(|e| e.into_inner()).take();
+                if let Some(b) = b {
+                    *result_b.lock().unwrap_or_else(|e| e.into_inner()) = Some(b());
+                }
+            });
+            a()
+        });
+
+        let result_b = result_b
+            .into_inner()
+            .unwrap_or_else(|e| e.into_inner())
+            .or_else(|| b.into_inner().unwrap_or_else(|e| e.into_inner()).map(|b| b()));
+        match result_b {
+            Some(result_b) => (result_a, result_b),
+            None => unreachable!("`b` either ran as a task or is still in its slot"),
+        }
+    }
+
+    /// Apply `f` to every item in parallel, keeping the results in order
+    pub fn par_map<T, U, F>(&self, items: &[T], f: F) -> Vec<U>
+    where
+        T: Sync,
+        U: Send,
+        F: Fn(&T) -> U + Sync,
+    {
+        let mut output = Vec::with_capacity(items.len());
+        let slots: &mut [MaybeUninit<U>] = &mut output.spare_capacity_mut()[..items.len()];
+
+        self.bridge(
+            (items, slots),
+            self.initial_splits(),
+            &|(items, slots): (&[T], &mut [MaybeUninit<U>])| {
+                for (item, slot) in items.iter().zip(slots) {
+                    slot.write(f(item));
+                }
+            },
+            &|(), ()| (),
+        );
+
+        // SAFETY: `bridge` returned without panicking, so every leaf ran and
+        // wrote each of the first `items.len()` slots exactly once
+        unsafe { output.set_len(items.len()) };
+        output
+    }
+
+    /// Call `f` on every item in parallel
+    pub fn par_for_each<T, F>(&self, items: &[T], f: F)
+    where
+        T: Sync,
+        F: Fn(&T) + Sync,
+    {
+        self.bridge(items, self.initial_splits(), &|items: &[T]| items.iter().for_each(&f), &|(), ()| ());
+    }
+
+    /// Map every item in parallel and combine the results with `op`
+    ///
+    /// `identity` must be neutral for `op`, and `op` associative, since items
+    /// are combined in an unspecified grouping (though always in order).
+    pub fn par_reduce<T, R, Id, M, Op>(&self, items: &[T], identity: Id, map: M, op: Op) -> R
+    where
+        T: Sync,
+        R: Send,
+        Id: Fn() -> R + Sync,
+        M: Fn(&T) -> R + Sync,
+        Op: Fn(R, R) -> R + Sync,
+    {
+        self.bridge(
+            items,
+            self.initial_splits(),
+            &|items: &[T]| items.iter().fold(identity(), |acc, item| op(acc, map(item))),
+            &op,
+        )
+    }
+
+    /// How many times to halve the input up front: enough pieces to give
+    /// every worker some, without splitting cheap work into tiny tasks
+    fn initial_splits(&self) -> usize {
+        self.state.workers.load(Ordering::SeqCst).max(1)
+    }
+
+    /// Split `producer` in halves through `join` while `splits` lasts, run
+    /// `leaf` on the pieces and `combine` their results in order
+    fn bridge<P, R>(
+        &self,
+        producer: P,
+        splits: usize,
+        leaf: &(dyn Fn(P) -> R + Sync),
+        combine: &(dyn Fn(R, R) -> R + Sync),
+    ) -> R
+    where
+        P: Producer,
+        R: Send,
+    {
+        let len = producer.len();
+        if len <= 1 || splits == 0 {
+            return leaf(producer);
+        }
+
+        let (left, right) = producer.split_at(len / 2);
+        let splits = splits / 2;
+        let origin = thread::current().id();
+        let (left, right) = self.join(
+            || self.bridge(left, splits, leaf, combine),
+            || {
+                // A stolen half means workers are idle, so split it up again
+                // to keep them all busy
+                let splits = if thread::current().id() == origin {
+                    splits
+                } else {
+                    splits.max(self.initial_splits())
+                };
+                self.bridge(right, splits, leaf, combine)
+            },
+        );
+        combine(left, right)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task_scheduler::test_config;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicU64;
+
+    fn started_scheduler(num_workers: usize) -> TaskScheduler {
+        let mut scheduler = TaskScheduler::new(test_config(num_workers));
+        scheduler.start();
+        scheduler
+    }
+
+    fn fib(scheduler: &TaskScheduler, n: u64) -> u64 {
+        if n < 2 {
+            return n;
+        }
+        let (a, b) = scheduler.join(|| fib(scheduler, n - 1), || fib(scheduler, n - 2));
+        a + b
+    }
+
+    #[test]
+    fn test_join_returns_both_results_inside_and_outside_the_pool() {
+        for num_workers in [1, 4] {
+            let scheduler = Arc::new(started_scheduler(num_workers));
+            assert_eq!(scheduler.join(|| "a", || 2), ("a", 2));
+            assert_eq!(fib(&scheduler, 15), 610);
+
+            // Recursing from a task keeps the halves on the worker's own queue
+            let scheduler_clone = Arc::clone(&scheduler);
+            let handle = scheduler.submit_with_result(move || fib(&scheduler_clone, 15)).unwrap();
+            assert_eq!(handle.join().unwrap(), 610);
+        }
+    }
+
+    #[test]
+    fn test_parallel_slice_helpers() {
+        let scheduler = started_scheduler(4);
+        let items: Vec<u64> = (1..=10_000).collect();
+
+        let squares = scheduler.par_map(&items, |x| x * x);
+        assert_eq!(squares, items.iter().map(|x| x * x).collect::<Vec<_>>());
+
+        let sum = AtomicU64::new(0);
+        scheduler.par_for_each(&items, |x| {
+            sum.fetch_add(*x, Ordering::Relaxed);
+        });
+        assert_eq!(sum.load(Ordering::Relaxed), 50_005_000);
+
+        let sum_of_squares = scheduler.par_reduce(&items, || 0, |x| x * x, |a, b| a + b);
+        assert_eq!(sum_of_squares, squares.iter().sum::<u64>());
+
+        // Concatenation checks that pieces are combined in order
+        let words = ["fork", "-", "join"];
+        let joined = scheduler.par_reduce(&words, String::new, |word| word.to_string(), |a, b| a + &b);
+        assert_eq!(joined, "fork-join");
+
+        let empty: [u64; 0] = [];
+        assert!(scheduler.par_map(&empty, |x| x + 1).is_empty());
+        assert_eq!(scheduler.par_reduce(&empty, || 7, |x| *x, |a, b| a + b), 7);
+    }
+
+    #[test]
+    fn test_panic_in_parallel_helper_reaches_the_caller() {
+        let scheduler = started_scheduler(2);
+        let items: Vec<u32> = (0..100).collect();
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            scheduler.par_for_each(&items, |x| assert_ne!(*x, 63, "bad item"));
+        }));
+        assert!(result.is_err());
+
+        // The pool is still usable afterwards
+        assert_eq!(scheduler.par_map(&items, |x| x + 1)[99], 100);
+    }
+}
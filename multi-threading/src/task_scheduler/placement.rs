@@ -0,0 +1,237 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::{CancellationToken, Priority, ScheduledTask, SchedulerError, SchedulerState, TaskScheduler, TaskTags};
+
+/// Chooses the worker a submitted task is queued for
+///
+/// Selected with `SchedulerConfig::placement`. Without one, submissions go
+/// to the shared injector that every worker takes from. Tasks spawned by a
+/// running task stay on its worker unless they were submitted with a key.
+pub trait PlacementStrategy: Send + Sync {
+    /// The worker whose inbox the task joins, or `None` for the shared injector
+    fn place(&self, context: &PlacementContext<'_>) -> Option<usize>;
+}
+
+/// What a placement strategy sees of a task and the pool
+pub struct PlacementContext<'a> {
+    key: Option<u32>,
+    workers: Vec<usize>,
+    state: &'a SchedulerState,
+}
+
+impl PlacementContext<'_> {
+    /// Hash of the key given to `submit_with_key`, if any
+    pub fn key(&self) -> Option<u32> {
+        self.key
+    }
+
+    /// Ids of the workers currently running, in slot order
+    pub fn workers(&self) -> &[usize] {
+        &self.workers
+    }
+
+    /// Tasks queued for `worker`
+    pub fn load(&self, worker: usize) -> usize {
+        self.state.slots[worker].queued()
+    }
+
+    /// A random number, from the seeded generator in simulation mode
+    pub fn random(&self) -> u64 {
+        self.state.random()
+    }
+}
+
+/// Deal tasks out to the workers in turn
+#[derive(Debug, Default)]
+pub struct RoundRobin {
+    next: AtomicUsize,
+}
+
+impl PlacementStrategy for RoundRobin {
+    fn place(&self, context: &PlacementContext<'_>) -> Option<usize> {
+        let workers = context.workers();
+        if workers.is_empty() {
+            return None;
+        }
+        Some(workers[self.next.fetch_add(1, Ordering::Relaxed) % workers.len()])
+    }
+}
+
+/// Queue each task for the worker with the fewest tasks queued
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LeastLoaded;
+
+impl PlacementStrategy for LeastLoaded {
+    fn place(&self, context: &PlacementContext<'_>) -> Option<usize> {
+        context.workers().iter().copied().min_by_key(|&worker| context.load(worker))
+    }
+}
+
+/// Queue tasks with the same key for the same worker, so related tasks
+/// share its caches; tasks without a key go to the shared injector
+///
+/// Keys map to workers by position, so they move when the pool grows or shrinks.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KeyAffinity;
+
+impl PlacementStrategy for KeyAffinity {
+    fn place(&self, context: &PlacementContext<'_>) -> Option<usize> {
+        let key = context.key()?;
+        let workers = context.workers();
+        (!workers.is_empty()).then(|| workers[key as usize % workers.len()])
+    }
+}
+
+/// Pick two workers at random and queue for the less loaded one, which
+/// balances nearly as well as `LeastLoaded` while reading only two queues
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PowerOfTwoChoices;
+
+impl PlacementStrategy for PowerOfTwoChoices {
+    fn place(&self, context: &PlacementContext<'_>) -> Option<usize> {
+        let workers = context.workers();
+        if workers.is_empty() {
+            return None;
+        }
+        let first = workers[(context.random() % workers.len() as u64) as usize];
+        let second = workers[(context.random() % workers.len() as u64) as usize];
+        Some(if context.load(second) < context.load(first) { second } else { first })
+    }
+}
+
+impl SchedulerState {
+    /// Queue a task that is not going on the current worker's own queue:
+    /// in the inbox of the worker the placement strategy picks, or the injector
+    pub(super) fn push_shared(&self, scheduled_task: ScheduledTask) {
+        let target = self.placement.as_ref().and_then(|placement| {
+            let context = PlacementContext {
+                key: scheduled_task.metadata.tags.key,
+                workers: (0..self.slots.len()).filter(|&worker| self.slots[worker].is_occupied()).collect(),
+                state: self,
+            };
+            placement.place(&context).filter(|&worker| worker < self.slots.len())
+        });
+
+        let Some(worker_id) = target else {
+            self.injector.push(scheduled_task);
+            self.notify_work(true);
+            return;
+        };
+
+        let slot = &self.slots[worker_id];
+        let task_id = scheduled_task.metadata.id;
+        slot.inbox.push(scheduled_task);
+        // A worker vacates its slot before emptying its inbox, so if it
+        // left meanwhile, either it took the task or we see the slot vacant
+        if !slot.is_occupied()
+            && let Some(scheduled_task) = slot.inbox.remove(task_id)
+        {
+            self.injector.push(scheduled_task);
+        }
+        // Any worker can take the task by stealing; otherwise only its owner can
+        self.notify_work(self.work_stealing);
+    }
+
+    /// Move whatever was placed for a worker that has left to the injector
+    pub(super) fn vacate_inbox(&self, worker_id: usize) {
+        let orphans = self.slots[worker_id].inbox.drain();
+        if orphans.is_empty() {
+            return;
+        }
+        for scheduled_task in orphans {
+            self.injector.push(scheduled_task);
+        }
+        self.notify_work(false);
+    }
+}
+
+impl TaskScheduler {
+    /// Submit a task with a key the placement strategy can use, e.g. so
+    /// `KeyAffinity` runs tasks with equal keys on the same worker
+    ///
+    /// Without a placement strategy the key is ignored.
+    pub fn submit_with_key<K, F>(&self, key: &K, task: F) -> Result<u64, SchedulerError>
+    where
+        K: Hash + ?Sized,
+        F: FnOnce() + Send + 'static,
+    {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let hash = hasher.finish();
+        let tags = TaskTags {
+            key: Some((hash ^ (hash >> 32)) as u32),
+            ..TaskTags::default()
+        };
+        self.state
+            .enqueue_tagged(Self::job(task), Priority::NORMAL, tags, CancellationToken::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task_scheduler::{SchedulerConfig, test_config};
+    use std::sync::Arc;
+
+    fn placed_scheduler(placement: Arc<dyn PlacementStrategy>) -> TaskScheduler {
+        let mut scheduler = TaskScheduler::new(SchedulerConfig {
+            simulation_seed: Some(2),
+            placement: Some(placement),
+            ..test_config(4)
+        });
+        scheduler.start();
+        scheduler
+    }
+
+    fn inbox_depths(scheduler: &TaskScheduler) -> Vec<usize> {
+        scheduler.state.slots.iter().map(|slot| slot.inbox.len()).collect()
+    }
+
+    #[test]
+    fn test_round_robin_and_least_loaded_spread_tasks() {
+        let scheduler = placed_scheduler(Arc::new(RoundRobin::default()));
+        for _ in 0..8 {
+            scheduler.submit(|| {}).unwrap();
+        }
+        assert_eq!(inbox_depths(&scheduler), vec![2, 2, 2, 2, 0, 0, 0, 0]);
+        assert!(scheduler.run_until_idle());
+
+        let scheduler = placed_scheduler(Arc::new(LeastLoaded));
+        for _ in 0..8 {
+            scheduler.submit(|| {}).unwrap();
+        }
+        assert_eq!(inbox_depths(&scheduler), vec![2, 2, 2, 2, 0, 0, 0, 0]);
+        assert!(scheduler.run_until_idle());
+    }
+
+    #[test]
+    fn test_key_affinity_keeps_a_key_on_one_worker() {
+        let scheduler = placed_scheduler(Arc::new(KeyAffinity));
+        for _ in 0..5 {
+            scheduler.submit_with_key("user-42", || {}).unwrap();
+        }
+        let depths = inbox_depths(&scheduler);
+        assert_eq!(depths.iter().filter(|&&depth| depth > 0).count(), 1);
+        assert_eq!(depths.iter().sum::<usize>(), 5);
+
+        // Tasks without a key are shared
+        scheduler.submit(|| {}).unwrap();
+        assert_eq!(scheduler.state.injector.len(), 1);
+        assert!(scheduler.run_until_idle());
+        assert_eq!(scheduler.stats().completed, 6);
+    }
+
+    #[test]
+    fn test_power_of_two_choices_and_cancel_in_inbox() {
+        let scheduler = placed_scheduler(Arc::new(PowerOfTwoChoices));
+        let ids: Vec<u64> = (0..40).map(|_| scheduler.submit(|| {}).unwrap()).collect();
+        let depths = inbox_depths(&scheduler);
+        assert_eq!(depths.iter().sum::<usize>(), 40);
+        assert!(depths[..4].iter().all(|&depth| (6..=14).contains(&depth)), "{:?}", depths);
+
+        assert!(scheduler.cancel(ids[7]));
+        assert!(scheduler.run_until_idle());
+        assert_eq!(scheduler.stats().completed, 39);
+    }
+}
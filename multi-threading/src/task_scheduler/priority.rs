@@ -0,0 +1,54 @@
+use std::fmt;
+
+/// Scheduling priority of a task; higher levels run first
+///
+/// Levels are numeric in `0..Priority::LEVELS`, with named constants for
+/// the common cases. Values above `Priority::MAX` are clamped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Priority(u8);
+
+impl Priority {
+    /// Number of distinct priority levels
+    pub const LEVELS: usize = 8;
+
+    pub const LOW: Priority = Priority(0);
+    pub const NORMAL: Priority = Priority(3);
+    pub const HIGH: Priority = Priority(6);
+    pub const MAX: Priority = Priority(Self::LEVELS as u8 - 1);
+
+    /// Create a priority from a numeric level, clamping to `Priority::MAX`
+    pub fn new(level: u8) -> Self {
+        Priority(level.min(Self::MAX.0))
+    }
+
+    /// Numeric level of this priority
+    pub fn level(self) -> u8 {
+        self.0
+    }
+
+    /// The next level up, saturating at `Priority::MAX`
+    pub fn promoted(self) -> Self {
+        Priority::new(self.0.saturating_add(1))
+    }
+
+    pub(crate) fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Priority::NORMAL
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Priority::LOW => write!(f, "low"),
+            Priority::NORMAL => write!(f, "normal"),
+            Priority::HIGH => write!(f, "high"),
+            Priority(level) => write!(f, "p{}", level),
+        }
+    }
+}
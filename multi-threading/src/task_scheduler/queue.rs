@@ -0,0 +1,661 @@
+use std::cmp::{self, Reverse};
+use std::collections::{BinaryHeap, VecDeque};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use super::deque::{self, Steal};
+use super::{Priority, ScheduledTask};
+
+/// Most tasks a worker moves from the injector to its own queue in one go
+const INJECTOR_BATCH: usize = 16;
+
+/// One priority level of the injector: a FIFO per tenant, served by
+/// deficit round robin so a tenant with a long backlog cannot starve the others
+///
+/// Every task costs one unit, so each tenant with queued tasks takes up to
+/// its weight in tasks per turn.
+struct FairLane {
+    /// Indexed by `TaskMetadata::lane`
+    queues: Vec<VecDeque<ScheduledTask>>,
+    /// Tenants with queued tasks, in turn order; the front one is being served
+    turns: VecDeque<usize>,
+    /// Tasks the front tenant may still take in its current turn
+    deficit: u32,
+    len: usize,
+}
+
+impl FairLane {
+    fn new(tenants: usize) -> Self {
+        Self {
+            queues: (0..tenants).map(|_| VecDeque::new()).collect(),
+            turns: VecDeque::new(),
+            deficit: 0,
+            len: 0,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn push(&mut self, task: ScheduledTask) {
+        let queue = &mut self.queues[task.metadata.lane()];
+        if queue.is_empty() {
+            self.turns.push_back(task.metadata.lane());
+        }
+        queue.push_back(task);
+        self.len += 1;
+    }
+
+    fn pop(&mut self, weights: &[u32]) -> Option<ScheduledTask> {
+        let tenant = *self.turns.front()?;
+        if self.deficit == 0 {
+            self.deficit = weights[tenant].max(1);
+        }
+        self.deficit -= 1;
+        let task = self.take_front(tenant);
+        if self.deficit == 0 && !self.queues[tenant].is_empty() {
+            self.turns.rotate_left(1);
+        }
+        task
+    }
+
+    /// Take up to `count` tasks, in the order `pop` would return them
+    fn pop_many(&mut self, weights: &[u32], count: usize) -> Vec<ScheduledTask> {
+        // A lone tenant has nobody to take turns with
+        if let [tenant] = *self.turns.make_contiguous() {
+            let count = count.min(self.queues[tenant].len());
+            let tasks: Vec<ScheduledTask> = self.queues[tenant].drain(..count).collect();
+            self.len -= count;
+            if self.queues[tenant].is_empty() {
+                self.turns.clear();
+                self.deficit = 0;
+            }
+            return tasks;
+        }
+        std::iter::from_fn(|| self.pop(weights)).take(count).collect()
+    }
+
+    /// Take the oldest task of `tenant`, dropping it from the turns once it
+    /// has no more
+    fn take_front(&mut self, tenant: usize) -> Option<ScheduledTask> {
+        let task = self.queues[tenant].pop_front()?;
+        self.len -= 1;
+        if self.queues[tenant].is_empty() {
+            if self.turns.front() == Some(&tenant) {
+                self.deficit = 0;
+            }
+            self.turns.retain(|&turn| turn != tenant);
+        }
+        Some(task)
+    }
+
+    fn remove(&mut self, task_id: u64) -> Option<ScheduledTask> {
+        let tenant = self
+            .queues
+            .iter()
+            .position(|queue| queue.iter().any(|task| task.metadata.id == task_id))?;
+        let index = self.queues[tenant].iter().position(|task| task.metadata.id == task_id)?;
+        if index == 0 {
+            return self.take_front(tenant);
+        }
+        self.len -= 1;
+        self.queues[tenant].remove(index)
+    }
+
+    /// Tasks in the order `pop` would return them
+    fn in_order(&self, weights: &[u32]) -> Vec<&ScheduledTask> {
+        let mut next: Vec<usize> = vec![0; self.queues.len()];
+        let mut turns = self.turns.clone();
+        let mut deficit = self.deficit;
+        let mut ordered = Vec::with_capacity(self.len);
+        while let Some(&tenant) = turns.front() {
+            if deficit == 0 {
+                deficit = weights[tenant].max(1);
+            }
+            deficit -= 1;
+            ordered.push(&self.queues[tenant][next[tenant]]);
+            next[tenant] += 1;
+            if next[tenant] == self.queues[tenant].len() {
+                turns.pop_front();
+                deficit = 0;
+            } else if deficit == 0 {
+                turns.rotate_left(1);
+            }
+        }
+        ordered
+    }
+
+    fn drain(&mut self, weights: &[u32]) -> Vec<ScheduledTask> {
+        self.pop_many(weights, self.len)
+    }
+}
+
+/// Global queue for tasks submitted from outside the worker threads, with
+/// one lane per priority level, shared fairly between tenants
+pub(super) struct Injector {
+    levels: Mutex<Vec<FairLane>>,
+    /// Weight of each tenant lane; lane 0 holds tasks without a tenant
+    weights: Vec<u32>,
+    /// Bit per non-empty level, so workers can check for work without the lock
+    occupied: AtomicUsize,
+}
+
+impl Injector {
+    pub(super) fn new(weights: Vec<u32>) -> Self {
+        Self {
+            levels: Mutex::new((0..Priority::LEVELS).map(|_| FairLane::new(weights.len())).collect()),
+            weights,
+            occupied: AtomicUsize::new(0),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<FairLane>> {
+        // Lanes are only ever pushed to and popped from, so a panic while
+        // holding the lock cannot leave them inconsistent
+        self.levels.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Recompute the occupancy bits; called with the lock held after every change
+    fn publish(&self, levels: &[FairLane]) {
+        let occupied = levels
+            .iter()
+            .enumerate()
+            .filter(|(_, lane)| !lane.is_empty())
+            .fold(0, |bits, (level, _)| bits | 1 << level);
+        self.occupied.store(occupied, Ordering::SeqCst);
+    }
+
+    pub(super) fn push(&self, task: ScheduledTask) {
+        let mut levels = self.lock();
+        levels[task.metadata.priority.index()].push(task);
+        self.publish(&levels);
+    }
+
+    /// Take the most urgent task, plus a fair share of the tasks queued
+    /// behind it at the same level for the worker to keep locally, but no
+    /// more than the `room` its queue has left
+    pub(super) fn pop_batch(&self, num_workers: usize, room: usize) -> Option<(ScheduledTask, Vec<ScheduledTask>)> {
+        let mut levels = self.lock();
+        let lane = levels.iter_mut().rev().find(|lane| !lane.is_empty())?;
+
+        let share = (lane.len / num_workers.max(1)).min(INJECTOR_BATCH);
+        let first = lane.pop(&self.weights)?;
+        let batch = lane.pop_many(&self.weights, share.saturating_sub(1).min(room));
+
+        self.publish(&levels);
+        Some((first, batch))
+    }
+
+    /// Take the most urgent task on its own
+    pub(super) fn pop(&self) -> Option<ScheduledTask> {
+        self.pop_batch(usize::MAX, 0).map(|(task, _)| task)
+    }
+
+    /// Take up to `count` tasks from the most urgent level, in run order
+    pub(super) fn pop_up_to(&self, count: usize) -> Vec<ScheduledTask> {
+        let mut levels = self.lock();
+        let Some(lane) = levels.iter_mut().rev().find(|lane| !lane.is_empty()) else {
+            return Vec::new();
+        };
+        let tasks = lane.pop_many(&self.weights, count);
+        self.publish(&levels);
+        tasks
+    }
+
+    /// Priority of the task `pop` or `pop_batch` would return next
+    pub(super) fn top_priority(&self) -> Option<Priority> {
+        let occupied = self.occupied.load(Ordering::SeqCst);
+        (occupied != 0).then(|| Priority::new((usize::BITS - 1 - occupied.leading_zeros()) as u8))
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.occupied.load(Ordering::SeqCst) == 0
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.lock().iter().map(|lane| lane.len).sum()
+    }
+
+    /// Queued tasks in each tenant lane
+    pub(super) fn lane_lens(&self) -> Vec<usize> {
+        let levels = self.lock();
+        (0..self.weights.len())
+            .map(|tenant| levels.iter().map(|lane| lane.queues[tenant].len()).sum())
+            .collect()
+    }
+
+    /// Remove a queued task by id
+    pub(super) fn remove(&self, task_id: u64) -> Option<ScheduledTask> {
+        let mut levels = self.lock();
+        let removed = levels.iter_mut().find_map(|lane| lane.remove(task_id));
+        self.publish(&levels);
+        removed
+    }
+
+    /// Remove the oldest task of the tenant with the most queued at the
+    /// lowest non-empty level, the one least likely to be missed
+    pub(super) fn evict(&self) -> Option<ScheduledTask> {
+        let mut levels = self.lock();
+        let evicted = levels.iter_mut().find(|lane| !lane.is_empty()).and_then(|lane| {
+            let tenant = (0..lane.queues.len()).max_by_key(|&tenant| lane.queues[tenant].len())?;
+            lane.take_front(tenant)
+        });
+        self.publish(&levels);
+        evicted
+    }
+
+    /// Move tasks that have waited `interval` at their level up one level
+    pub(super) fn promote_aged(&self, now: Instant, interval: Duration) -> usize {
+        let mut levels = self.lock();
+        let mut promoted = 0;
+
+        // Walk from the top so a task is promoted at most once per pass
+        for level in (0..Priority::LEVELS - 1).rev() {
+            for tenant in 0..self.weights.len() {
+                while let Some(task) = levels[level].queues[tenant].front() {
+                    if now.duration_since(task.metadata.enqueued_at) < interval {
+                        break;
+                    }
+
+                    let mut task = levels[level].take_front(tenant).unwrap();
+                    task.metadata.priority = task.metadata.priority.promoted();
+                    task.metadata.enqueued_at = now;
+                    levels[level + 1].push(task);
+                    promoted += 1;
+                }
+            }
+        }
+
+        self.publish(&levels);
+        promoted
+    }
+
+    /// Id, run-order position and wait time of every task queued longer than `timeout`
+    ///
+    /// Returns nothing if the lock is contended, rather than stalling submitters.
+    pub(super) fn overdue(&self, now: Instant, timeout: Duration) -> Vec<(u64, usize, Duration)> {
+        let Ok(levels) = self.levels.try_lock() else {
+            return Vec::new();
+        };
+
+        levels
+            .iter()
+            .rev()
+            .flat_map(|lane| lane.in_order(&self.weights))
+            .enumerate()
+            .filter_map(|(position, task)| {
+                let age = now.duration_since(task.metadata.submitted_at);
+                (age > timeout).then_some((task.metadata.id, position, age))
+            })
+            .collect()
+    }
+
+    /// How long the longest-waiting task at the front of a lane has been queued
+    pub(super) fn oldest_wait(&self, now: Instant) -> Option<Duration> {
+        self.lock()
+            .iter()
+            .flat_map(|lane| lane.queues.iter().filter_map(|queue| queue.front()))
+            .map(|task| now.saturating_duration_since(task.metadata.submitted_at))
+            .max()
+    }
+
+    /// Remove every queued task, in the order workers would have run them
+    pub(super) fn drain(&self) -> Vec<ScheduledTask> {
+        let mut levels = self.lock();
+        let drained = levels.iter_mut().rev().flat_map(|lane| lane.drain(&self.weights)).collect();
+        self.publish(&levels);
+        drained
+    }
+}
+
+/// Tasks that may not run before a given instant, such as retries waiting
+/// out their backoff
+pub(super) struct DelayQueue {
+    tasks: Mutex<BinaryHeap<Reverse<Delayed>>>,
+    /// Nanoseconds after `epoch` at which the earliest task is due, or
+    /// `u64::MAX` when empty, so workers can check without the lock
+    next_due: AtomicU64,
+    epoch: Instant,
+}
+
+struct Delayed {
+    due: Instant,
+    task: ScheduledTask,
+}
+
+impl PartialEq for Delayed {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == cmp::Ordering::Equal
+    }
+}
+
+impl Eq for Delayed {}
+
+impl PartialOrd for Delayed {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Delayed {
+    /// Earliest due first, then in submission order
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.due
+            .cmp(&other.due)
+            .then(self.task.metadata.id.cmp(&other.task.metadata.id))
+    }
+}
+
+impl DelayQueue {
+    pub(super) fn new() -> Self {
+        Self {
+            tasks: Mutex::new(BinaryHeap::new()),
+            next_due: AtomicU64::new(u64::MAX),
+            epoch: Instant::now(),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BinaryHeap<Reverse<Delayed>>> {
+        self.tasks.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Update `next_due`; called with the lock held after every change
+    fn publish(&self, tasks: &BinaryHeap<Reverse<Delayed>>) {
+        let next_due = tasks.peek().map_or(u64::MAX, |Reverse(delayed)| {
+            let nanos = delayed.due.saturating_duration_since(self.epoch).as_nanos();
+            u64::try_from(nanos).unwrap_or(u64::MAX - 1)
+        });
+        self.next_due.store(next_due, Ordering::SeqCst);
+    }
+
+    pub(super) fn push(&self, task: ScheduledTask, due: Instant) {
+        let mut tasks = self.lock();
+        tasks.push(Reverse(Delayed { due, task }));
+        self.publish(&tasks);
+    }
+
+    /// When the earliest task is due, without taking the lock
+    pub(super) fn next_due(&self) -> Option<Instant> {
+        match self.next_due.load(Ordering::SeqCst) {
+            u64::MAX => None,
+            nanos => Some(self.epoch + Duration::from_nanos(nanos)),
+        }
+    }
+
+    /// Remove every task due at or before `now`, earliest first
+    pub(super) fn pop_due(&self, now: Instant) -> Vec<ScheduledTask> {
+        let mut tasks = self.lock();
+        let mut due = Vec::new();
+        while tasks.peek().is_some_and(|Reverse(delayed)| delayed.due <= now) {
+            due.push(tasks.pop().unwrap().0.task);
+        }
+        self.publish(&tasks);
+        due
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub(super) fn remove(&self, task_id: u64) -> Option<ScheduledTask> {
+        let mut tasks = self.lock();
+        let mut delayed = std::mem::take(&mut *tasks).into_vec();
+        let removed = delayed
+            .iter()
+            .position(|Reverse(delayed)| delayed.task.metadata.id == task_id)
+            .map(|index| delayed.swap_remove(index).0.task);
+        *tasks = BinaryHeap::from(delayed);
+        self.publish(&tasks);
+        removed
+    }
+
+    /// Remove every task, earliest due first
+    pub(super) fn drain(&self) -> Vec<ScheduledTask> {
+        let mut tasks = self.lock();
+        let drained = std::iter::from_fn(|| tasks.pop().map(|Reverse(delayed)| delayed.task)).collect();
+        self.publish(&tasks);
+        drained
+    }
+}
+
+/// A worker's own queue: one lock-free deque per priority level
+///
+/// Only the worker thread occupying the slot pushes and pops; other workers
+/// steal through the matching `WorkerStealer`.
+pub(super) struct WorkerQueue {
+    levels: Vec<deque::Worker<ScheduledTask>>,
+}
+
+/// Thief side of a `WorkerQueue`, shared with the other workers
+pub(super) struct WorkerStealer {
+    levels: Vec<deque::Stealer<ScheduledTask>>,
+}
+
+impl WorkerQueue {
+    pub(super) fn new() -> (Self, WorkerStealer) {
+        let levels: Vec<_> = (0..Priority::LEVELS).map(|_| deque::Worker::new()).collect();
+        let stealer = WorkerStealer {
+            levels: levels.iter().map(|level| level.stealer()).collect(),
+        };
+        (Self { levels }, stealer)
+    }
+
+    pub(super) fn push(&self, task: ScheduledTask) {
+        self.levels[task.metadata.priority.index()].push(task);
+    }
+
+    /// Pop the newest task at the highest non-empty level
+    pub(super) fn pop(&self) -> Option<ScheduledTask> {
+        // Skip empty levels without paying for a pop's fence
+        self.levels
+            .iter()
+            .rev()
+            .filter(|level| level.len() > 0)
+            .find_map(|level| level.pop())
+    }
+
+    pub(super) fn top_priority(&self) -> Option<Priority> {
+        self.levels
+            .iter()
+            .rposition(|level| level.len() > 0)
+            .map(|level| Priority::new(level as u8))
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.levels.iter().map(|level| level.len()).sum()
+    }
+}
+
+impl WorkerStealer {
+    /// Steal the oldest task at the highest non-empty level
+    pub(super) fn steal(&self) -> Option<ScheduledTask> {
+        for level in self.levels.iter().rev() {
+            loop {
+                match level.steal() {
+                    Steal::Success(task) => return Some(task),
+                    Steal::Empty => break,
+                    Steal::Retry => std::hint::spin_loop(),
+                }
+            }
+        }
+        None
+    }
+
+    /// Priority of the task `steal` would return next
+    pub(super) fn top_priority(&self) -> Option<Priority> {
+        self.levels
+            .iter()
+            .rposition(|level| !level.is_empty())
+            .map(|level| Priority::new(level as u8))
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.levels.iter().all(|level| level.is_empty())
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.levels.iter().map(|level| level.len()).sum()
+    }
+
+    /// Steal until the task with `task_id` turns up, returning it along with
+    /// everything stolen on the way
+    pub(super) fn steal_until(&self, task_id: u64) -> (Option<ScheduledTask>, Vec<ScheduledTask>) {
+        let mut others = Vec::new();
+        while let Some(task) = self.steal() {
+            if task.metadata.id == task_id {
+                return (Some(task), others);
+            }
+            others.push(task);
+        }
+        (None, others)
+    }
+
+    /// Steal everything left, e.g. once the owner has exited
+    pub(super) fn drain(&self) -> Vec<ScheduledTask> {
+        std::iter::from_fn(|| self.steal()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task_scheduler::{CancellationToken, TaskMetadata, TaskScheduler, TaskTags};
+
+    fn scheduled(id: u64, priority: Priority) -> ScheduledTask {
+        let now = Instant::now();
+        ScheduledTask {
+            task: TaskScheduler::job(|| {}),
+            metadata: TaskMetadata {
+                id,
+                submitted_at: now,
+                priority,
+                enqueued_at: now,
+                recurring: false,
+                tags: TaskTags::default(),
+            },
+            cancel_token: CancellationToken::new(),
+        }
+    }
+
+    fn ids(tasks: impl IntoIterator<Item = ScheduledTask>) -> Vec<u64> {
+        tasks.into_iter().map(|task| task.metadata.id).collect()
+    }
+
+    #[test]
+    fn test_injector_drains_by_priority() {
+        let injector = Injector::new(vec![1]);
+        injector.push(scheduled(1, Priority::LOW));
+        injector.push(scheduled(2, Priority::NORMAL));
+        injector.push(scheduled(3, Priority::HIGH));
+        injector.push(scheduled(4, Priority::HIGH));
+        injector.push(scheduled(5, Priority::NORMAL));
+        assert_eq!(injector.top_priority(), Some(Priority::HIGH));
+
+        let (first, batch) = injector.pop_batch(4, usize::MAX).unwrap();
+        assert_eq!(first.metadata.id, 3);
+        assert!(batch.is_empty());
+
+        assert_eq!(injector.remove(5).unwrap().metadata.id, 5);
+        assert_eq!(ids(injector.drain()), vec![4, 2, 1]);
+        assert!(injector.is_empty());
+        assert_eq!(injector.top_priority(), None);
+    }
+
+    #[test]
+    fn test_injector_batches_a_fair_share() {
+        let injector = Injector::new(vec![1]);
+        for id in 0..40 {
+            injector.push(scheduled(id, Priority::NORMAL));
+        }
+
+        // 40 tasks over 4 workers: take the first plus 9 more, in order
+        let (first, batch) = injector.pop_batch(4, usize::MAX).unwrap();
+        assert_eq!(first.metadata.id, 0);
+        assert_eq!(ids(batch), (1..10).collect::<Vec<_>>());
+
+        // Never more than the batch cap, however few workers there are
+        let (_, batch) = injector.pop_batch(1, usize::MAX).unwrap();
+        assert_eq!(batch.len(), INJECTOR_BATCH - 1);
+
+        // Nor more than the worker's queue has room for
+        let (_, batch) = injector.pop_batch(1, 3).unwrap();
+        assert_eq!(batch.len(), 3);
+    }
+
+    #[test]
+    fn test_injector_aging_promotes_waiting_tasks() {
+        let injector = Injector::new(vec![1]);
+        injector.push(scheduled(1, Priority::LOW));
+
+        let later = Instant::now() + Duration::from_millis(20);
+        assert_eq!(injector.promote_aged(later, Duration::from_millis(10)), 1);
+        assert_eq!(injector.top_priority(), Some(Priority::new(1)));
+        assert_eq!(injector.overdue(later, Duration::from_millis(10)).len(), 1);
+        assert!(injector.overdue(later, Duration::from_secs(1)).is_empty());
+    }
+
+    #[test]
+    fn test_injector_shares_levels_between_tenants_by_weight() {
+        let tenant_task = |id, tenant| {
+            let mut task = scheduled(id, Priority::NORMAL);
+            task.metadata.tags.tenant = tenant;
+            task
+        };
+        // No tenant, then tenants 0 and 1 with weights 2 and 1
+        let injector = Injector::new(vec![1, 2, 1]);
+        for id in 0..6 {
+            injector.push(tenant_task(id, Some(0)));
+        }
+        for id in 10..13 {
+            injector.push(tenant_task(id, Some(1)));
+        }
+        injector.push(tenant_task(20, None));
+
+        let order = vec![0, 1, 10, 20, 2, 3, 11, 4, 5, 12];
+        let overdue = injector.overdue(Instant::now() + Duration::from_secs(1), Duration::ZERO);
+        assert_eq!(overdue.iter().map(|(id, _, _)| *id).collect::<Vec<_>>(), order);
+        assert_eq!(injector.lane_lens(), vec![1, 6, 3]);
+
+        // The busiest tenant loses its oldest task first
+        assert_eq!(injector.evict().unwrap().metadata.id, 0);
+        assert_eq!(ids(injector.drain()), vec![1, 2, 10, 20, 3, 4, 11, 5, 12]);
+    }
+
+    #[test]
+    fn test_delay_queue_releases_due_tasks_in_order() {
+        let delayed = DelayQueue::new();
+        let now = Instant::now();
+        delayed.push(scheduled(1, Priority::NORMAL), now + Duration::from_millis(30));
+        delayed.push(scheduled(2, Priority::NORMAL), now + Duration::from_millis(10));
+        delayed.push(scheduled(3, Priority::NORMAL), now + Duration::from_millis(10));
+        delayed.push(scheduled(4, Priority::NORMAL), now + Duration::from_secs(60));
+        assert_eq!(delayed.next_due(), Some(now + Duration::from_millis(10)));
+
+        assert!(delayed.pop_due(now).is_empty());
+        assert_eq!(ids(delayed.pop_due(now + Duration::from_millis(30))), vec![2, 3, 1]);
+
+        assert_eq!(delayed.remove(4).unwrap().metadata.id, 4);
+        assert!(delayed.remove(4).is_none());
+        assert_eq!(delayed.next_due(), None);
+        assert_eq!(delayed.len(), 0);
+    }
+
+    #[test]
+    fn test_worker_queue_runs_urgent_work_first_and_steals_oldest() {
+        let (queue, stealer) = WorkerQueue::new();
+        queue.push(scheduled(1, Priority::LOW));
+        queue.push(scheduled(2, Priority::HIGH));
+        queue.push(scheduled(3, Priority::HIGH));
+        queue.push(scheduled(4, Priority::NORMAL));
+        assert_eq!(stealer.top_priority(), Some(Priority::HIGH));
+
+        // Thieves take the most urgent work too, from the old end of its level
+        assert_eq!(stealer.steal().unwrap().metadata.id, 2);
+
+        assert_eq!(queue.top_priority(), Some(Priority::HIGH));
+        assert_eq!(ids(std::iter::from_fn(|| queue.pop())), vec![3, 4, 1]);
+        assert!(stealer.is_empty());
+    }
+}
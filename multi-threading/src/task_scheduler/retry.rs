@@ -0,0 +1,388 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::handle::panic_message;
+use super::{CancellationToken, Job, Priority, SchedulerError, TaskExit, TaskScheduler};
+
+/// How long to wait before each retry
+#[derive(Debug, Clone)]
+pub enum Backoff {
+    Fixed(Duration),
+    /// Starts at `initial` and doubles after every failed attempt, up to `max`
+    Exponential { initial: Duration, max: Duration },
+}
+
+/// Why an attempt at running a task failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TaskFailure {
+    /// The task returned `Err`; holds the error's `Display` output
+    Error(String),
+    /// The task panicked; holds the panic message, if it was a string
+    Panicked(String),
+}
+
+impl fmt::Display for TaskFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TaskFailure::Error(message) => write!(f, "error: {}", message),
+            TaskFailure::Panicked(message) => write!(f, "panicked: {}", message),
+        }
+    }
+}
+
+/// Decides whether a failure is worth retrying
+pub type RetryPredicate = Arc<dyn Fn(&TaskFailure) -> bool + Send + Sync>;
+
+/// When and how often a failing task submitted with `submit_with_retry` runs again
+#[derive(Clone)]
+pub struct RetryPolicy {
+    /// Attempts in total, including the first
+    pub max_attempts: u32,
+    pub backoff: Backoff,
+    /// Fraction of each delay that is randomly shaved off, from 0.0 to 1.0,
+    /// so tasks that failed together do not retry in lockstep
+    pub jitter: f64,
+    /// Only failures this returns `true` for are retried; `None` retries every failure
+    pub retry_on: Option<RetryPredicate>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            backoff: Backoff::Exponential {
+                initial: Duration::from_millis(100),
+                max: Duration::from_secs(10),
+            },
+            jitter: 0.2,
+            retry_on: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before the attempt following attempt number `attempt` (starting
+    /// at 1), before jitter shaves a random part of it off
+    pub fn delay(&self, attempt: u32) -> Duration {
+        match self.backoff {
+            Backoff::Fixed(delay) => delay,
+            Backoff::Exponential { initial, max } => {
+                let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+                initial.saturating_mul(factor).min(max)
+            }
+        }
+    }
+
+    fn should_retry(&self, attempt: u32, failure: &TaskFailure) -> bool {
+        attempt < self.max_attempts && self.retry_on.as_ref().is_none_or(|retry_on| retry_on(failure))
+    }
+}
+
+/// `delay` less the fraction `sample` (spread over all of `u64`) of its
+/// `jitter` fraction
+pub(super) fn jittered(delay: Duration, jitter: f64, sample: u64) -> Duration {
+    let unit = (sample >> 11) as f64 / (1u64 << 53) as f64;
+    let kept = 1.0 - jitter.clamp(0.0, 1.0) * unit;
+    // Not `mul_f64`, which panics when a huge backoff does not round-trip
+    Duration::try_from_secs_f64(delay.as_secs_f64() * kept).unwrap_or(delay)
+}
+
+/// A task that failed permanently, kept until taken with `take_dead_letters`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeadLetter {
+    pub task_id: u64,
+    pub attempts: u32,
+    /// The failure of the last attempt
+    pub failure: TaskFailure,
+}
+
+impl TaskScheduler {
+    /// Submit a fallible task that is retried according to `policy` when it
+    /// returns `Err` or panics
+    ///
+    /// Retries keep the task's id and wait out their backoff without holding
+    /// a worker. Once the policy gives up, the task is recorded as a dead letter.
+    pub fn submit_with_retry<F, E>(&self, policy: RetryPolicy, task: F) -> Result<u64, SchedulerError>
+    where
+        F: FnMut() -> Result<(), E> + Send + 'static,
+        E: fmt::Display,
+    {
+        self.enqueue(retry_job(task, policy, 1), Priority::NORMAL, CancellationToken::new())
+    }
+
+    /// Tasks that failed permanently, oldest first
+    pub fn dead_letters(&self) -> Vec<DeadLetter> {
+        self.state
+            .dead_letters
+            .lock()
+            .map(|dead_letters| dead_letters.clone())
+            .unwrap_or_default()
+    }
+
+    /// Remove and return the recorded dead letters
+    pub fn take_dead_letters(&self) -> Vec<DeadLetter> {
+        self.state
+            .dead_letters
+            .lock()
+            .map(|mut dead_letters| std::mem::take(&mut *dead_letters))
+            .unwrap_or_default()
+    }
+}
+
+/// Wrap attempt number `attempt` of a fallible task as a job whose exit says
+/// whether to run it again
+fn retry_job<F, E>(mut task: F, policy: RetryPolicy, attempt: u32) -> Job
+where
+    F: FnMut() -> Result<(), E> + Send + 'static,
+    E: fmt::Display,
+{
+    Box::new(move || {
+        let failure = match std::panic::catch_unwind(std::panic::AssertUnwindSafe(&mut task)) {
+            Ok(Ok(())) => return TaskExit::Completed,
+            Ok(Err(error)) => TaskFailure::Error(error.to_string()),
+            Err(payload) => TaskFailure::Panicked(
+                panic_message(payload.as_ref()).unwrap_or("<non-string payload>").to_string(),
+            ),
+        };
+
+        if policy.should_retry(attempt, &failure) {
+            TaskExit::Retry {
+                delay: policy.delay(attempt),
+                jitter: policy.jitter,
+                job: retry_job(task, policy, attempt + 1),
+            }
+        } else {
+            TaskExit::Failed { attempts: attempt, failure }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task_scheduler::{SchedulerConfig, test_config};
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::thread;
+    use std::time::Instant;
+
+    fn started_scheduler() -> TaskScheduler {
+        let mut scheduler = TaskScheduler::new(test_config(2));
+        scheduler.start();
+        scheduler
+    }
+
+    fn fixed(max_attempts: u32, delay_ms: u64) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            backoff: Backoff::Fixed(Duration::from_millis(delay_ms)),
+            jitter: 0.0,
+            retry_on: None,
+        }
+    }
+
+    /// Wait until nothing is queued, running or waiting for a retry
+    fn wait_for_idle(scheduler: &TaskScheduler) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while scheduler.stats().in_flight > 0 {
+            assert!(Instant::now() < deadline, "tasks never finished");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn test_backoff_delays() {
+        let fixed = RetryPolicy {
+            backoff: Backoff::Fixed(Duration::from_millis(30)),
+            jitter: 0.0,
+            ..RetryPolicy::default()
+        };
+        assert_eq!(fixed.delay(1), Duration::from_millis(30));
+        assert_eq!(fixed.delay(5), Duration::from_millis(30));
+
+        let exponential = RetryPolicy {
+            backoff: Backoff::Exponential {
+                initial: Duration::from_millis(10),
+                max: Duration::from_millis(50),
+            },
+            jitter: 0.0,
+            ..RetryPolicy::default()
+        };
+        let delays: Vec<_> = (1..=4).map(|attempt| exponential.delay(attempt)).collect();
+        assert_eq!(delays, [10, 20, 40, 50].map(Duration::from_millis));
+        assert_eq!(exponential.delay(u32::MAX), Duration::from_millis(50));
+
+        let delay = exponential.delay(2);
+        assert_eq!(jittered(delay, 0.5, 0), Duration::from_millis(20));
+        let near = |sample: u64, expected_ms: u64| {
+            jittered(delay, 0.5, sample).abs_diff(Duration::from_millis(expected_ms)) < Duration::from_micros(1)
+        };
+        assert!(near(u64::MAX / 2 + 1, 15));
+        assert!(near(u64::MAX, 10));
+    }
+
+    #[test]
+    fn test_retry_on_filters_failures() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            retry_on: Some(Arc::new(|failure| matches!(failure, TaskFailure::Error(_)))),
+            ..RetryPolicy::default()
+        };
+
+        assert!(policy.should_retry(1, &TaskFailure::Error("busy".into())));
+        assert!(!policy.should_retry(3, &TaskFailure::Error("busy".into())));
+        assert!(!policy.should_retry(1, &TaskFailure::Panicked("bug".into())));
+    }
+
+    #[test]
+    fn test_failing_task_is_retried_until_it_succeeds() {
+        let scheduler = started_scheduler();
+        let attempts = Arc::new(AtomicU32::new(0));
+        let attempts_clone = Arc::clone(&attempts);
+
+        let started = Instant::now();
+        scheduler
+            .submit_with_retry(fixed(3, 20), move || {
+                match attempts_clone.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err("not yet"),
+                    1 => panic!("still not"),
+                    _ => Ok(()),
+                }
+            })
+            .unwrap();
+
+        wait_for_idle(&scheduler);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        assert!(started.elapsed() >= Duration::from_millis(40), "retries skipped their backoff");
+        assert!(scheduler.dead_letters().is_empty());
+
+        let report = scheduler.shutdown_drain();
+        assert_eq!((report.completed, report.panicked, report.failed), (1, 0, 0));
+    }
+
+    #[test]
+    fn test_exhausted_tasks_become_dead_letters() {
+        let scheduler = started_scheduler();
+
+        let always_fails = scheduler
+            .submit_with_retry(fixed(2, 5), || Err::<(), _>("connection refused"))
+            .unwrap();
+        let only_errors_retried = RetryPolicy {
+            retry_on: Some(Arc::new(|failure| matches!(failure, TaskFailure::Error(_)))),
+            ..fixed(5, 5)
+        };
+        let panics = scheduler
+            .submit_with_retry(only_errors_retried, || -> Result<(), String> { panic!("bad input") })
+            .unwrap();
+
+        wait_for_idle(&scheduler);
+        let mut dead_letters = scheduler.take_dead_letters();
+        dead_letters.sort_by_key(|dead_letter| dead_letter.task_id);
+        assert_eq!(
+            dead_letters,
+            vec![
+                DeadLetter {
+                    task_id: always_fails,
+                    attempts: 2,
+                    failure: TaskFailure::Error("connection refused".to_string()),
+                },
+                DeadLetter {
+                    task_id: panics,
+                    attempts: 1,
+                    failure: TaskFailure::Panicked("bad input".to_string()),
+                },
+            ]
+        );
+        assert!(scheduler.dead_letters().is_empty());
+
+        let report = scheduler.shutdown_drain();
+        assert_eq!((report.completed, report.panicked, report.failed), (0, 1, 1));
+    }
+
+    #[test]
+    fn test_cancel_removes_task_waiting_for_retry() {
+        let scheduler = started_scheduler();
+        let attempts = Arc::new(AtomicU32::new(0));
+        let attempts_clone = Arc::clone(&attempts);
+
+        let task_id = scheduler
+            .submit_with_retry(fixed(3, 60_000), move || {
+                attempts_clone.fetch_add(1, Ordering::SeqCst);
+                Err("retry much later")
+            })
+            .unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while scheduler.stats().delayed_depth == 0 {
+            assert!(Instant::now() < deadline, "task never waited for a retry");
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        assert!(scheduler.cancel(task_id));
+        wait_for_idle(&scheduler);
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+        assert!(scheduler.dead_letters().is_empty());
+
+        let report = scheduler.shutdown_drain();
+        assert_eq!(report.dropped, 0);
+    }
+
+    #[test]
+    fn test_jitter_replays_from_the_simulation_seed() {
+        /// Virtual times since the start at which the attempts ran
+        fn attempt_times(seed: u64) -> Vec<Duration> {
+            let mut scheduler = TaskScheduler::new(SchedulerConfig {
+                simulation_seed: Some(seed),
+                ..test_config(1)
+            });
+            scheduler.start();
+            let scheduler = Arc::new(scheduler);
+            let start = scheduler.now();
+            let times = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+            let policy = RetryPolicy {
+                jitter: 0.9,
+                ..fixed(4, 1000)
+            };
+            let clock = Arc::clone(&scheduler);
+            let recorded = Arc::clone(&times);
+            scheduler
+                .submit_with_retry(policy, move || {
+                    recorded.lock().unwrap().push(clock.now() - start);
+                    Err("again")
+                })
+                .unwrap();
+            assert!(scheduler.run_until_idle());
+            drop(scheduler);
+            Arc::try_unwrap(times).unwrap().into_inner().unwrap()
+        }
+
+        let times = attempt_times(7);
+        assert_eq!(times.len(), 4);
+        assert_eq!(times, attempt_times(7));
+        assert_ne!(times, attempt_times(8));
+    }
+
+    #[test]
+    fn test_huge_backoff_waits_instead_of_panicking() {
+        let policy = RetryPolicy {
+            max_attempts: 2,
+            backoff: Backoff::Fixed(Duration::MAX),
+            jitter: 0.5,
+            retry_on: None,
+        };
+        assert!(jittered(policy.delay(1), 0.5, u64::MAX) >= Duration::MAX / 2);
+
+        let scheduler = started_scheduler();
+        let task_id = scheduler.submit_with_retry(policy, || Err("retry never")).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while scheduler.stats().delayed_depth == 0 {
+            assert!(Instant::now() < deadline, "task never waited for a retry");
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        assert!(scheduler.cancel(task_id));
+        let report = scheduler.shutdown_drain();
+        assert_eq!((report.worker_panics, report.dropped), (0, 0));
+    }
+}
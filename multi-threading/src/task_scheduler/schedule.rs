@@ -0,0 +1,277 @@
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use super::simulation::Clock;
+use super::{CancellationToken, Job, Priority, SchedulerError, SchedulerState, TaskExit, TaskScheduler};
+
+/// When the next run of a periodic task is due
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cadence {
+    /// `interval` after the previous run was due, skipping runs missed
+    /// entirely while the previous one overran
+    FixedRate,
+    /// `interval` after the previous run finished
+    FixedDelay,
+}
+
+impl Cadence {
+    fn next_due(self, interval: Duration, due: Instant, finished: Instant) -> Instant {
+        match self {
+            Cadence::FixedRate => {
+                let behind = finished.saturating_duration_since(due).as_nanos() / interval.as_nanos();
+                let periods = u32::try_from(behind + 1).unwrap_or(u32::MAX);
+                due + interval.saturating_mul(periods)
+            }
+            Cadence::FixedDelay => finished + interval,
+        }
+    }
+}
+
+impl SchedulerState {
+    /// Accept a job that becomes runnable at `due`
+    ///
+    /// Only one-shot jobs are counted as outstanding while they wait; the
+    /// next run of a periodic job is counted once it is due.
+    fn enqueue_at(&self, task: Job, due: Instant, recurring: bool) -> Result<u64, SchedulerError> {
+        if !recurring && !self.try_reserve() {
+            self.rejected_tasks.fetch_add(1, Ordering::SeqCst);
+            return Err(SchedulerError::QueueFull);
+        }
+
+        let mut scheduled_task = match self.new_task(task, Priority::NORMAL, CancellationToken::new()) {
+            Ok(scheduled_task) => scheduled_task,
+            Err(e) => {
+                if !recurring {
+                    self.task_finished();
+                }
+                return Err(e);
+            }
+        };
+        scheduled_task.metadata.recurring = recurring;
+        let task_id = scheduled_task.metadata.id;
+        self.delayed.push(scheduled_task, due);
+
+        // A parked worker may be sleeping past the new due time
+        self.notify_work(true);
+        Ok(task_id)
+    }
+}
+
+impl TaskScheduler {
+    /// Submit a task that runs once `delay` has passed
+    ///
+    /// A delay too large to add to the current time waits until cancelled.
+    pub fn schedule_after<F>(&self, delay: Duration, task: F) -> Result<u64, SchedulerError>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.schedule_at(self.state.due_after(delay), task)
+    }
+
+    /// Submit a task that runs once `at` is reached, or straight away if it already has
+    pub fn schedule_at<F>(&self, at: Instant, task: F) -> Result<u64, SchedulerError>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.state.enqueue_at(Self::job(task), at, false)
+    }
+
+    /// Run a task every `interval`, first after one `interval` has passed
+    ///
+    /// Runs are due at fixed multiples of `interval`; a run that starts late
+    /// does not shift the ones after it, and runs missed entirely while an
+    /// earlier one overran are skipped rather than run back to back. The
+    /// returned id stays valid for `cancel` across runs.
+    pub fn schedule_every<F>(&self, interval: Duration, task: F) -> Result<u64, SchedulerError>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        self.schedule_periodic(Cadence::FixedRate, interval, task)
+    }
+
+    /// Run a task repeatedly, waiting `delay` after each run finishes before the next
+    ///
+    /// The returned id stays valid for `cancel` across runs.
+    pub fn schedule_with_fixed_delay<F>(&self, delay: Duration, task: F) -> Result<u64, SchedulerError>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        self.schedule_periodic(Cadence::FixedDelay, delay, task)
+    }
+
+    fn schedule_periodic<F>(&self, cadence: Cadence, interval: Duration, task: F) -> Result<u64, SchedulerError>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        if interval.is_zero() {
+            return Err(SchedulerError::ZeroInterval);
+        }
+
+        let due = self.state.due_after(interval);
+        let job = periodic_job(task, cadence, interval, due, self.state.clock.clone());
+        self.state.enqueue_at(job, due, true)
+    }
+}
+
+/// Wrap the run of a periodic task due at `due` as a job whose exit carries the next run
+///
+/// A panic ends the schedule: it unwinds past the `Recur` exit to the worker.
+fn periodic_job<F>(mut task: F, cadence: Cadence, interval: Duration, due: Instant, clock: Clock) -> Job
+where
+    F: FnMut() + Send + 'static,
+{
+    Box::new(move || {
+        task();
+        let next_due = cadence.next_due(interval, due, clock.now());
+        TaskExit::Recur {
+            due: next_due,
+            job: periodic_job(task, cadence, interval, next_due, clock),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task_scheduler::test_config;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    fn started_scheduler() -> TaskScheduler {
+        let mut scheduler = TaskScheduler::new(test_config(2));
+        scheduler.start();
+        scheduler
+    }
+
+    /// Poll `condition` until it holds, failing the test after five seconds
+    fn wait_for(what: &str, condition: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !condition() {
+            assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn test_next_due_by_cadence() {
+        let interval = Duration::from_millis(10);
+        let due = Instant::now();
+        let ms = Duration::from_millis;
+
+        // On time, late within the period, and late past two whole periods
+        assert_eq!(Cadence::FixedRate.next_due(interval, due, due + ms(3)), due + ms(10));
+        assert_eq!(Cadence::FixedRate.next_due(interval, due, due + ms(10)), due + ms(20));
+        assert_eq!(Cadence::FixedRate.next_due(interval, due, due + ms(25)), due + ms(30));
+
+        assert_eq!(Cadence::FixedDelay.next_due(interval, due, due + ms(3)), due + ms(13));
+        assert_eq!(Cadence::FixedDelay.next_due(interval, due, due + ms(25)), due + ms(35));
+    }
+
+    #[test]
+    fn test_delayed_tasks_run_in_due_order() {
+        let scheduler = started_scheduler();
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        let started = Instant::now();
+        for delay_ms in [60, 20, 40] {
+            let order = Arc::clone(&order);
+            scheduler
+                .schedule_after(Duration::from_millis(delay_ms), move || {
+                    order.lock().unwrap().push(delay_ms);
+                })
+                .unwrap();
+        }
+        let order_clone = Arc::clone(&order);
+        scheduler
+            .schedule_at(started - Duration::from_millis(1), move || order_clone.lock().unwrap().push(0))
+            .unwrap();
+        assert!(scheduler.stats().in_flight >= 3);
+
+        let report = scheduler.shutdown_drain();
+        assert!(started.elapsed() >= Duration::from_millis(60), "delayed tasks ran early");
+        assert_eq!(*order.lock().unwrap(), [0, 20, 40, 60]);
+        assert_eq!((report.completed, report.dropped), (4, 0));
+    }
+
+    #[test]
+    fn test_delay_past_the_end_of_instant_waits_until_cancelled() {
+        let scheduler = started_scheduler();
+
+        let task_id = scheduler.schedule_after(Duration::MAX, || panic!("ran a task due at the end of time")).unwrap();
+        scheduler.schedule_every(Duration::MAX, || panic!("ran a task due at the end of time")).unwrap();
+        assert_eq!(scheduler.stats().delayed_depth, 2);
+
+        assert!(scheduler.cancel(task_id));
+        let (report, pending) = scheduler.shutdown_now();
+        assert_eq!((report.completed, report.panicked), (0, 0));
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn test_fixed_rate_task_keeps_running_until_cancelled() {
+        let scheduler = started_scheduler();
+        let runs = Arc::new(AtomicUsize::new(0));
+        let runs_clone = Arc::clone(&runs);
+
+        let task_id = scheduler
+            .schedule_every(Duration::from_millis(5), move || {
+                runs_clone.fetch_add(1, Ordering::SeqCst);
+            })
+            .unwrap();
+
+        wait_for("three runs", || runs.load(Ordering::SeqCst) >= 3);
+        scheduler.cancel(task_id);
+        wait_for("the schedule to end", || {
+            let stats = scheduler.stats();
+            stats.delayed_depth == 0 && stats.in_flight == 0
+        });
+
+        let after_cancel = runs.load(Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(runs.load(Ordering::SeqCst), after_cancel);
+        assert!(!scheduler.cancel(task_id));
+    }
+
+    #[test]
+    fn test_fixed_delay_waits_after_each_run() {
+        let scheduler = started_scheduler();
+        let starts = Arc::new(Mutex::new(Vec::new()));
+        let starts_clone = Arc::clone(&starts);
+
+        scheduler
+            .schedule_with_fixed_delay(Duration::from_millis(10), move || {
+                starts_clone.lock().unwrap().push(Instant::now());
+                thread::sleep(Duration::from_millis(10));
+            })
+            .unwrap();
+
+        wait_for("three runs", || starts.lock().unwrap().len() >= 3);
+        let starts = starts.lock().unwrap().clone();
+        for pair in starts.windows(2) {
+            assert!(pair[1] - pair[0] >= Duration::from_millis(20), "next run started too soon");
+        }
+    }
+
+    #[test]
+    fn test_shutdown_drain_does_not_wait_for_periodic_tasks() {
+        let scheduler = started_scheduler();
+        let runs = Arc::new(AtomicUsize::new(0));
+        let runs_clone = Arc::clone(&runs);
+
+        scheduler
+            .schedule_every(Duration::from_millis(2), move || {
+                runs_clone.fetch_add(1, Ordering::SeqCst);
+            })
+            .unwrap();
+        assert_eq!(
+            scheduler.schedule_every(Duration::ZERO, || {}),
+            Err(SchedulerError::ZeroInterval)
+        );
+        wait_for("a run", || runs.load(Ordering::SeqCst) >= 1);
+
+        let report = scheduler.shutdown_drain();
+        assert_eq!(report.dropped, 0);
+        assert_eq!(report.completed, runs.load(Ordering::SeqCst));
+    }
+}
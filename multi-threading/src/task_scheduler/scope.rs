@@ -0,0 +1,256 @@
+use std::marker::PhantomData;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+
+use super::{
+    CancellationToken, LOCAL_QUEUE, PARK_TIMEOUT, PanicPayload, Priority, SchedulerError, TaskExit, TaskScheduler,
+};
+
+/// Tasks spawned in a scope that have not yet run or been dropped
+#[derive(Default)]
+struct ScopeData {
+    pending: AtomicUsize,
+    lock: Mutex<()>,
+    done: Condvar,
+    /// Payload of the first task in the scope to panic
+    panic: Mutex<Option<PanicPayload>>,
+}
+
+impl ScopeData {
+    fn task_finished(&self) {
+        if self.pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+            let _guard = self.lock.lock();
+            self.done.notify_all();
+        }
+    }
+}
+
+/// Spawns tasks that may borrow from outside the scope; see `TaskScheduler::scope`
+pub struct Scope<'scope, 'env: 'scope> {
+    scheduler: &'scope TaskScheduler,
+    data: Arc<ScopeData>,
+    /// Invariant over both lifetimes, as in `std::thread::Scope`
+    scope: PhantomData<&'scope mut &'scope ()>,
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+/// A task spawned in a scope; the scope counts it as finished once it is
+/// dropped, whether or not it ran
+struct ScopedJob<'scope> {
+    task: Option<Box<dyn FnOnce() + Send + 'scope>>,
+    data: Arc<ScopeData>,
+}
+
+impl ScopedJob<'_> {
+    fn run(mut self) -> TaskExit {
+        let Some(task) = self.task.take() else {
+            return TaskExit::Completed;
+        };
+
+        match std::panic::catch_unwind(std::panic::AssertUnwindSafe(task)) {
+            Ok(()) => TaskExit::Completed,
+            Err(payload) => {
+                let mut panic = self.data.panic.lock().unwrap_or_else(|e| e.into_inner());
+                panic.get_or_insert(payload);
+                TaskExit::Panicked
+            }
+        }
+    }
+}
+
+impl Drop for ScopedJob<'_> {
+    fn drop(&mut self) {
+        // The closure and its borrows must be gone before the scope can return
+        drop(self.task.take());
+        self.data.task_finished();
+    }
+}
+
+impl<'scope> Scope<'scope, '_> {
+    /// Submit a task that may borrow anything that outlives the scope
+    pub fn spawn<F>(&'scope self, task: F) -> Result<u64, SchedulerError>
+    where
+        F: FnOnce() + Send + 'scope,
+    {
+        self.data.pending.fetch_add(1, Ordering::SeqCst);
+        let job = ScopedJob {
+            task: Some(Box::new(task)),
+            data: Arc::clone(&self.data),
+        };
+
+        // SAFETY: `TaskScheduler::scope` does not return until `pending`
+        // drops to zero, which happens only once this job has been dropped,
+        // so the closure never outlives the borrows it captures. The
+        // scheduler cannot hand the job back to the caller either: the
+        // shutdown methods that return queued tasks take the scheduler by
+        // value, which the scope's borrow rules out.
+        let job: ScopedJob<'static> = unsafe { std::mem::transmute::<ScopedJob<'scope>, ScopedJob<'static>>(job) };
+
+        self.scheduler
+            .enqueue(Box::new(move || job.run()), Priority::NORMAL, CancellationToken::new())
+    }
+}
+
+impl TaskScheduler {
+    /// Run `f` with a `Scope` whose tasks may borrow from the caller's stack
+    ///
+    /// Every task spawned in the scope has finished by the time `scope`
+    /// returns. If `f` or any of the tasks panicked, the panic is resumed
+    /// here once they have; a panic in `f` takes precedence. Called from a
+    /// task, the worker runs queued tasks while it waits rather than blocking.
+    pub fn scope<'env, F, T>(&self, f: F) -> T
+    where
+        F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> T,
+    {
+        let scope = Scope {
+            scheduler: self,
+            data: Arc::new(ScopeData::default()),
+            scope: PhantomData,
+            env: PhantomData,
+        };
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f(&scope)));
+        self.wait_for_scope(&scope.data);
+
+        let panic = scope.data.panic.lock().unwrap_or_else(|e| e.into_inner()).take();
+        match (result, panic) {
+            (Err(payload), _) | (Ok(_), Some(payload)) => std::panic::resume_unwind(payload),
+            (Ok(value), None) => value,
+        }
+    }
+
+    fn wait_for_scope(&self, data: &ScopeData) {
+        while data.pending.load(Ordering::SeqCst) > 0 {
+            // No worker threads run the scope's tasks in simulation mode
+            if self.run_pending_task() || Self::drive_simulation(&self.state) {
+                continue;
+            }
+
+            let guard = data.lock.lock().unwrap_or_else(|e| e.into_inner());
+            if data.pending.load(Ordering::SeqCst) == 0 {
+                break;
+            }
+            // Time out to look for work again, in case this is a worker
+            let _ = data.done.wait_timeout(guard, PARK_TIMEOUT);
+        }
+    }
+
+    /// Run one queued task on the current thread if it is one of this scheduler's workers
+    ///
+    /// Lets a task that blocks on other tasks help with them instead of
+    /// holding its worker idle. Returns whether a task ran.
+    pub(super) fn run_pending_task(&self) -> bool {
+        let local = LOCAL_QUEUE.with(|local| match &*local.borrow() {
+            Some(local) if std::ptr::eq(local.state, Arc::as_ptr(&self.state)) => {
+                Some((local.worker_id, Rc::clone(&local.queue)))
+            }
+            _ => None,
+        });
+        let Some((worker_id, queue)) = local else {
+            return false;
+        };
+        let Some((scheduled_task, stolen)) = Self::find_work(worker_id, &queue, &self.state, &self.config) else {
+            return false;
+        };
+
+        // The waiting task is still running; keep it visible to `cancel` and
+        // the execution timeout once the nested one is done
+        let slot = &self.state.slots[worker_id];
+        let waiting = slot.running.lock().ok().and_then(|mut running| running.take());
+        Self::run_task(worker_id, &self.state, scheduled_task, stolen);
+        if let Ok(mut running) = slot.running.lock() {
+            *running = waiting;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task_scheduler::test_config;
+    use std::thread;
+    use std::time::Duration;
+
+    fn started_scheduler(num_workers: usize) -> TaskScheduler {
+        let mut scheduler = TaskScheduler::new(test_config(num_workers));
+        scheduler.start();
+        scheduler
+    }
+
+    #[test]
+    fn test_scoped_tasks_borrow_from_the_stack() {
+        let scheduler = started_scheduler(4);
+        let mut values: Vec<u64> = (0..1000).collect();
+        let total = AtomicUsize::new(0);
+
+        let returned = scheduler.scope(|s| {
+            for chunk in values.chunks_mut(100) {
+                let total = &total;
+                s.spawn(move || {
+                    for value in chunk.iter_mut() {
+                        *value *= 2;
+                    }
+                    thread::sleep(Duration::from_millis(5));
+                    total.fetch_add(chunk.len(), Ordering::SeqCst);
+                })
+                .unwrap();
+            }
+            "done"
+        });
+
+        assert_eq!(returned, "done");
+        assert_eq!(total.load(Ordering::SeqCst), 1000);
+        assert!(values.iter().enumerate().all(|(i, &value)| value == 2 * i as u64));
+
+        let report = scheduler.shutdown_drain();
+        assert_eq!(report.completed, 10);
+    }
+
+    #[test]
+    fn test_panic_in_scope_is_resumed_after_all_tasks_finish() {
+        let scheduler = started_scheduler(2);
+        let finished = AtomicUsize::new(0);
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            scheduler.scope(|s| {
+                s.spawn(|| panic!("scoped task failed")).unwrap();
+                for _ in 0..4 {
+                    s.spawn(|| {
+                        thread::sleep(Duration::from_millis(10));
+                        finished.fetch_add(1, Ordering::SeqCst);
+                    })
+                    .unwrap();
+                }
+            })
+        }));
+
+        let payload = result.unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"scoped task failed"));
+        assert_eq!(finished.load(Ordering::SeqCst), 4);
+
+        let report = scheduler.shutdown_drain();
+        assert_eq!((report.completed, report.panicked), (4, 1));
+    }
+
+    #[test]
+    fn test_nested_scope_on_a_single_worker_does_not_deadlock() {
+        let scheduler = Arc::new(started_scheduler(1));
+        let scheduler_clone = Arc::clone(&scheduler);
+
+        let handle = scheduler
+            .submit_with_result(move || {
+                let mut squares = [0u64; 8];
+                scheduler_clone.scope(|s| {
+                    for (i, square) in squares.iter_mut().enumerate() {
+                        s.spawn(move || *square = (i * i) as u64).unwrap();
+                    }
+                });
+                squares.iter().sum::<u64>()
+            })
+            .unwrap();
+
+        assert_eq!(handle.join().unwrap(), 140);
+    }
+}
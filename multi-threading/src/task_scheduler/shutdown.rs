@@ -0,0 +1,453 @@
+use std::fmt;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use super::{Priority, ScheduledTask, SchedulerError, Task, TaskScheduler};
+
+/// How often `stop` checks whether a replaced worker has exited before its deadline
+const REPLACED_WORKER_POLL: Duration = Duration::from_millis(1);
+
+/// Summary of what happened to the scheduler's tasks, returned by every shutdown mode
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// Tasks that ran to completion over the scheduler's lifetime
+    pub completed: usize,
+    /// Tasks that panicked over the scheduler's lifetime
+    pub panicked: usize,
+    /// Retried tasks whose last attempt returned `Err`
+    pub failed: usize,
+    /// Accepted tasks discarded without running
+    pub dropped: usize,
+    /// Whether `shutdown_timeout` reached its deadline before the queues drained
+    pub timed_out: bool,
+    /// Worker threads that panicked outside of task execution
+    pub worker_panics: usize,
+    pub supervisor_panicked: bool,
+    /// Distinct errors the scheduler reported over its lifetime, such as
+    /// poisoned locks and worker deaths, in the order they first occurred
+    pub errors: Vec<SchedulerError>,
+}
+
+impl fmt::Display for ShutdownReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "completed: {}, panicked: {}, dropped: {}",
+            self.completed, self.panicked, self.dropped
+        )?;
+        if self.failed > 0 {
+            write!(f, ", failed: {}", self.failed)?;
+        }
+        if self.timed_out {
+            write!(f, " (timed out)")?;
+        }
+        if self.worker_panics > 0 || self.supervisor_panicked {
+            write!(
+                f,
+                ", worker panics: {}, supervisor panicked: {}",
+                self.worker_panics, self.supervisor_panicked
+            )?;
+        }
+        for error in &self.errors {
+            write!(f, "\nerror: {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+/// A task that had not started when `shutdown_now` stopped the scheduler
+pub struct PendingTask {
+    pub id: u64,
+    pub priority: Priority,
+    /// The original closure; calling it runs the task on the current thread
+    pub task: Task,
+}
+
+impl From<ScheduledTask> for PendingTask {
+    fn from(scheduled_task: ScheduledTask) -> Self {
+        let job = scheduled_task.task;
+        Self {
+            id: scheduled_task.metadata.id,
+            priority: scheduled_task.metadata.priority,
+            task: Box::new(move || {
+                job();
+            }),
+        }
+    }
+}
+
+impl TaskScheduler {
+    /// Run every accepted task to completion, then stop the workers
+    pub fn shutdown_drain(mut self) -> ShutdownReport {
+        self.wait_until_idle(None);
+        let (mut report, unexecuted) = self.stop(None);
+        report.dropped += unexecuted.len();
+        report
+    }
+
+    /// Stop as soon as running tasks finish and hand back the tasks that never started
+    pub fn shutdown_now(mut self) -> (ShutdownReport, Vec<PendingTask>) {
+        let (report, unexecuted) = self.stop(None);
+        let pending = unexecuted.into_iter().map(PendingTask::from).collect();
+        (report, pending)
+    }
+
+    /// Drain like `shutdown_drain` until `timeout` elapses, then drop the remaining queued tasks
+    ///
+    /// Tasks already running when the deadline passes are still allowed to finish,
+    /// except on workers replaced as stuck: those are abandoned and their task
+    /// counted as dropped. A timeout too large to add to the current time drains
+    /// without a deadline.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> ShutdownReport {
+        let deadline = Instant::now().checked_add(timeout);
+        let drained = self.wait_until_idle(deadline);
+        let (mut report, unexecuted) = self.stop(deadline);
+        report.dropped += unexecuted.len();
+        report.timed_out = !drained;
+        report
+    }
+
+    /// Block until no accepted task is queued or running, or the deadline passes
+    pub(super) fn wait_until_idle(&self, deadline: Option<Instant>) -> bool {
+        // Nothing runs in simulation mode unless the caller steps it
+        if self.state.simulation.is_some() {
+            return self.run_until_idle();
+        }
+
+        // Nothing will ever drain the queues if the workers were never started
+        let has_workers = self.state.slots.iter().any(|slot| {
+            slot.thread
+                .lock()
+                .map(|thread| thread.is_some())
+                .unwrap_or(false)
+        });
+        if !has_workers {
+            return self.state.outstanding_tasks.load(Ordering::SeqCst) == 0;
+        }
+
+        let mut guard = self
+            .state
+            .outstanding_lock
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+
+        loop {
+            if self.state.outstanding_tasks.load(Ordering::SeqCst) == 0 {
+                return true;
+            }
+
+            guard = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return false;
+                    }
+                    self.state
+                        .outstanding_condvar
+                        .wait_timeout(guard, deadline - now)
+                        .map(|(guard, _)| guard)
+                        .unwrap_or_else(|e| e.into_inner().0)
+                }
+                None => self
+                    .state
+                    .outstanding_condvar
+                    .wait(guard)
+                    .unwrap_or_else(|e| e.into_inner()),
+            };
+        }
+    }
+
+    /// Signal shutdown, join every thread and take the tasks left in the queues
+    ///
+    /// Threads replaced as stuck are only waited for until `deadline`; the
+    /// task each is still running by then is counted in `report.dropped`.
+    pub(super) fn stop(&mut self, deadline: Option<Instant>) -> (ShutdownReport, Vec<ScheduledTask>) {
+        let mut report = ShutdownReport::default();
+
+        // Signal shutdown first
+        match self.state.shutdown.lock() {
+            Ok(mut shutdown) => *shutdown = true,
+            Err(poisoned) => *poisoned.into_inner() = true,
+        }
+        self.state.shutdown_condvar.notify_all();
+        self.stop_simulation();
+
+        // Wait for the supervisor first so it cannot spawn a replacement
+        // worker while we are joining them
+        if let Some(handle) = self.supervisor_handle.take() {
+            report.supervisor_panicked = handle.join().is_err();
+        }
+
+        // Wake every parked worker so it notices the shutdown flag
+        self.state.notify_work(false);
+
+        for slot in &self.state.slots {
+            let handle = match slot.thread.lock() {
+                Ok(mut thread) => thread.take(),
+                Err(poisoned) => poisoned.into_inner().take(),
+            };
+
+            if let Some(handle) = handle
+                && handle.join().is_err()
+            {
+                report.worker_panics += 1;
+            }
+        }
+
+        // Workers replaced as stuck retire once their task returns, handing
+        // their local tasks to the injector before it is drained below
+        let replaced = std::mem::take(&mut *self.state.replaced_threads.lock().unwrap_or_else(|e| e.into_inner()));
+        for (worker_id, handle) in replaced {
+            while !handle.is_finished() && deadline.is_some_and(|deadline| Instant::now() < deadline) {
+                std::thread::sleep(REPLACED_WORKER_POLL);
+            }
+
+            if deadline.is_none() || handle.is_finished() {
+                if handle.join().is_err() {
+                    report.worker_panics += 1;
+                }
+            } else if self.state.slots[worker_id]
+                .running
+                .lock()
+                .map(|running| running.is_some())
+                .unwrap_or(true)
+            {
+                // Left detached, still stuck on its task
+                report.dropped += 1;
+            }
+        }
+
+        // Exiting workers move their local tasks to the injector, but a
+        // worker that panicked may have left some behind in its slot
+        let mut unexecuted = self.state.injector.drain();
+        for slot in &self.state.slots {
+            unexecuted.extend(slot.stealer.drain());
+            unexecuted.extend(slot.inbox.drain());
+        }
+        // The next run of a periodic task was never counted as accepted
+        unexecuted.extend(self.state.delayed.drain().into_iter().filter(|task| !task.metadata.recurring));
+        unexecuted.extend(self.state.classes.drain());
+
+        report.completed = self.state.completed_tasks.load(Ordering::SeqCst);
+        report.panicked = self.state.panicked_tasks.load(Ordering::SeqCst);
+        report.failed = self.state.failed_tasks.load(Ordering::SeqCst);
+        report.errors = self.state.errors.lock().map(|errors| errors.clone()).unwrap_or_else(|e| e.into_inner().clone());
+
+        (report, unexecuted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::task_scheduler::{ExecutionTimeoutAction, JoinError, SchedulerConfig, test_config};
+    use super::*;
+    use std::sync::mpsc;
+    use std::sync::{Arc, atomic::{AtomicBool, AtomicUsize}};
+    use std::thread;
+
+    fn single_worker() -> TaskScheduler {
+        let config = SchedulerConfig {
+            enable_work_stealing: false,
+            ..test_config(1)
+        };
+
+        let mut scheduler = TaskScheduler::new(config);
+        scheduler.start();
+        scheduler
+    }
+
+    /// Occupy the worker until the returned sender is used or dropped
+    fn block_worker(scheduler: &TaskScheduler) -> mpsc::Sender<()> {
+        let (started_tx, started_rx) = mpsc::channel::<()>();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        scheduler
+            .submit(move || {
+                started_tx.send(()).unwrap();
+                let _ = release_rx.recv();
+            })
+            .unwrap();
+        started_rx.recv().unwrap();
+        release_tx
+    }
+
+    #[test]
+    fn test_shutdown_drain_runs_every_accepted_task() {
+        let config = SchedulerConfig {
+            enable_work_stealing: true,
+            ..test_config(2)
+        };
+
+        let mut scheduler = TaskScheduler::new(config);
+        scheduler.start();
+
+        let counter = Arc::new(AtomicUsize::new(0));
+        for _ in 0..20 {
+            let counter = Arc::clone(&counter);
+            scheduler
+                .submit(move || {
+                    thread::sleep(Duration::from_millis(5));
+                    counter.fetch_add(1, Ordering::SeqCst);
+                })
+                .unwrap();
+        }
+        scheduler.submit(|| panic!("expected test panic")).unwrap();
+
+        let report = scheduler.shutdown_drain();
+        assert_eq!(counter.load(Ordering::SeqCst), 20);
+        assert_eq!(report.completed, 20);
+        assert_eq!(report.panicked, 1);
+        assert_eq!(report.dropped, 0);
+        assert!(!report.timed_out);
+    }
+
+    #[test]
+    fn test_shutdown_now_returns_unstarted_tasks() {
+        let scheduler = single_worker();
+        let release = block_worker(&scheduler);
+
+        let counter = Arc::new(AtomicUsize::new(0));
+        let mut expected_ids = Vec::new();
+        for priority in [Priority::LOW, Priority::HIGH] {
+            let counter = Arc::clone(&counter);
+            let id = scheduler
+                .submit_with_priority(priority, move || {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                })
+                .unwrap();
+            expected_ids.push(id);
+        }
+        let handle = scheduler.submit_with_result(|| 5).unwrap();
+
+        // Let the blocker finish once shutdown is underway
+        let releaser = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            release.send(()).unwrap();
+        });
+
+        let (report, pending) = scheduler.shutdown_now();
+        releaser.join().unwrap();
+
+        assert_eq!(report.completed, 1);
+        assert_eq!(report.dropped, 0);
+
+        // Handed back in the order the worker would have run them
+        let ids: Vec<u64> = pending.iter().map(|task| task.id).collect();
+        assert_eq!(ids, vec![expected_ids[1], handle.id(), expected_ids[0]]);
+        assert_eq!(pending[0].priority, Priority::HIGH);
+
+        assert_eq!(counter.load(Ordering::SeqCst), 0);
+        for task in pending {
+            (task.task)();
+        }
+        assert_eq!(counter.load(Ordering::SeqCst), 2);
+        assert_eq!(handle.join().unwrap(), 5);
+    }
+
+    #[test]
+    fn test_shutdown_timeout_drops_work_left_at_deadline() {
+        let scheduler = single_worker();
+        let release = block_worker(&scheduler);
+
+        let queued: Vec<_> = (0..2)
+            .map(|i| scheduler.submit_with_result(move || i).unwrap())
+            .collect();
+
+        let releaser = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(150));
+            release.send(()).unwrap();
+        });
+
+        let report = scheduler.shutdown_timeout(Duration::from_millis(30));
+        releaser.join().unwrap();
+
+        assert!(report.timed_out);
+        assert_eq!(report.completed, 1);
+        assert_eq!(report.dropped, 2);
+        for handle in queued {
+            assert!(matches!(handle.join(), Err(JoinError::Dropped)));
+        }
+    }
+
+    #[test]
+    fn test_shutdown_timeout_finishes_early_when_drained() {
+        let scheduler = single_worker();
+        let handle = scheduler.submit_with_result(|| "quick").unwrap();
+
+        let started = Instant::now();
+        let report = scheduler.shutdown_timeout(Duration::from_secs(10));
+
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(!report.timed_out);
+        assert_eq!(report.completed, 1);
+        assert_eq!(handle.join().unwrap(), "quick");
+    }
+
+    #[test]
+    fn test_shutdown_timeout_past_the_end_of_instant_drains() {
+        let scheduler = single_worker();
+        scheduler.submit(|| thread::sleep(Duration::from_millis(20))).unwrap();
+
+        let report = scheduler.shutdown_timeout(Duration::MAX);
+        assert!(!report.timed_out);
+        assert_eq!((report.completed, report.dropped), (1, 0));
+    }
+
+    /// A single worker stuck until the returned sender is used, already
+    /// replaced by the supervisor; `finished` is set when the stuck task returns
+    fn replaced_worker(finished: &Arc<AtomicBool>) -> (TaskScheduler, mpsc::Sender<()>) {
+        let config = SchedulerConfig {
+            enable_work_stealing: false,
+            execution_timeout_ms: 20,
+            execution_timeout_action: ExecutionTimeoutAction::ReplaceWorker,
+            ..test_config(1)
+        };
+        let mut scheduler = TaskScheduler::new(config);
+        scheduler.start();
+
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let finished = Arc::clone(finished);
+        scheduler
+            .submit(move || {
+                let _ = release_rx.recv_timeout(Duration::from_secs(10));
+                finished.store(true, Ordering::SeqCst);
+            })
+            .unwrap();
+
+        // Only a replacement can run this while the first worker is stuck
+        let handle = scheduler.submit_with_result(|| ()).unwrap();
+        handle.join_timeout(Duration::from_secs(5)).expect("the stuck worker was never replaced").unwrap();
+        (scheduler, release_tx)
+    }
+
+    #[test]
+    fn test_shutdown_joins_replaced_workers() {
+        let finished = Arc::new(AtomicBool::new(false));
+        let (scheduler, release_tx) = replaced_worker(&finished);
+
+        let releaser = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            release_tx.send(()).unwrap();
+        });
+        let report = scheduler.shutdown();
+
+        assert!(finished.load(Ordering::SeqCst));
+        assert_eq!((report.completed, report.dropped, report.worker_panics), (2, 0, 0));
+        releaser.join().unwrap();
+    }
+
+    #[test]
+    fn test_shutdown_timeout_abandons_replaced_workers_at_deadline() {
+        let finished = Arc::new(AtomicBool::new(false));
+        let (scheduler, release_tx) = replaced_worker(&finished);
+
+        let started = Instant::now();
+        let report = scheduler.shutdown_timeout(Duration::from_millis(50));
+
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(report.timed_out);
+        assert!(!finished.load(Ordering::SeqCst));
+        // The stuck task is counted as dropped rather than forgotten
+        assert_eq!((report.completed, report.dropped), (1, 1));
+        release_tx.send(()).unwrap();
+    }
+}
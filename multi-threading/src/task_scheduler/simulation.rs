@@ -0,0 +1,602 @@
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use super::queue::WorkerQueue;
+use super::timer::{self, TimerHeap, VirtualTimers};
+use super::{FAR_FUTURE, LOCAL_QUEUE, LocalQueue, SchedulerConfig, SchedulerState, TaskScheduler};
+
+/// Where the scheduler reads the time: the system clock, or a virtual one
+/// that only moves when a simulation is advanced
+#[derive(Clone)]
+pub(super) enum Clock {
+    Real,
+    Virtual(Arc<VirtualClock>),
+}
+
+impl Clock {
+    pub(super) fn now(&self) -> Instant {
+        match self {
+            Clock::Real => Instant::now(),
+            Clock::Virtual(clock) => clock.now(),
+        }
+    }
+}
+
+pub(super) struct VirtualClock {
+    epoch: Instant,
+    /// Nanoseconds of virtual time since `epoch`
+    elapsed: AtomicU64,
+}
+
+impl VirtualClock {
+    fn now(&self) -> Instant {
+        self.epoch + Duration::from_nanos(self.elapsed.load(Ordering::SeqCst))
+    }
+
+    /// Move the clock forward to `at`; it never goes back
+    fn advance_to(&self, at: Instant) {
+        let nanos = at.saturating_duration_since(self.epoch).as_nanos();
+        self.elapsed.fetch_max(u64::try_from(nanos).unwrap_or(u64::MAX), Ordering::SeqCst);
+    }
+}
+
+/// SplitMix64, so a seed replays the same order whatever `rand` version is in use
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = (self.next() % (i as u64 + 1)) as usize;
+            items.swap(i, j);
+        }
+    }
+}
+
+/// A worker with no thread, stepped by the simulation
+struct SimulatedWorker {
+    id: usize,
+    /// `None` while the worker is taking a step
+    queue: Option<WorkerQueue>,
+}
+
+/// Workers, scheduling order and supervisor timing of a scheduler in simulation mode
+pub(super) struct Simulation {
+    clock: Arc<VirtualClock>,
+    rng: Mutex<SplitMix64>,
+    workers: Mutex<Vec<SimulatedWorker>>,
+    /// When the supervisor next checks on the virtual clock, and how often;
+    /// `None` when the configuration needs no supervisor
+    supervisor: Mutex<Option<(Instant, Duration)>>,
+    /// `sleep` futures polled by the simulated workers
+    timers: VirtualTimers,
+    /// The scheduler's configuration, for threads that only hold its state
+    /// and drive the simulation while they wait on it
+    config: SchedulerConfig,
+}
+
+impl Simulation {
+    /// The clock for a scheduler, and its simulation when it has a seed
+    pub(super) fn for_seed(config: &SchedulerConfig) -> (Clock, Option<Simulation>) {
+        let Some(seed) = config.simulation_seed else {
+            return (Clock::Real, None);
+        };
+
+        let clock = Arc::new(VirtualClock {
+            epoch: Instant::now(),
+            elapsed: AtomicU64::new(0),
+        });
+        let simulation = Simulation {
+            clock: Arc::clone(&clock),
+            rng: Mutex::new(SplitMix64(seed)),
+            workers: Mutex::new(Vec::new()),
+            supervisor: Mutex::new(None),
+            timers: VirtualTimers {
+                clock: Clock::Virtual(Arc::clone(&clock)),
+                heap: Arc::new(TimerHeap::default()),
+            },
+            config: config.clone(),
+        };
+        (Clock::Virtual(clock), Some(simulation))
+    }
+
+    fn workers(&self) -> MutexGuard<'_, Vec<SimulatedWorker>> {
+        self.workers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn take_queue(&self, worker_id: usize) -> Option<WorkerQueue> {
+        let mut workers = self.workers();
+        workers.iter_mut().find(|worker| worker.id == worker_id)?.queue.take()
+    }
+
+    fn return_queue(&self, worker_id: usize, queue: WorkerQueue) {
+        if let Some(worker) = self.workers().iter_mut().find(|worker| worker.id == worker_id) {
+            worker.queue = Some(queue);
+        }
+    }
+}
+
+impl SchedulerState {
+    /// The current time on the scheduler's clock
+    pub(super) fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    /// `delay` from now on the scheduler's clock, or `FAR_FUTURE` if that overflows
+    pub(super) fn due_after(&self, delay: Duration) -> Instant {
+        let now = self.now();
+        now.checked_add(delay).unwrap_or_else(|| now + FAR_FUTURE)
+    }
+
+    /// When the earliest `sleep` on the virtual clock ends, in simulation mode
+    pub(super) fn next_timer(&self) -> Option<Instant> {
+        self.simulation.as_ref()?.timers.heap.next_deadline()
+    }
+
+    /// Whether the current thread is taking a step of this scheduler's simulation
+    pub(super) fn in_simulated_step(&self) -> bool {
+        LOCAL_QUEUE.with(|local| local.borrow().as_ref().is_some_and(|local| std::ptr::eq(local.state, self)))
+    }
+
+    /// A random number, from the seeded generator in simulation mode so runs replay
+    pub(super) fn random(&self) -> u64 {
+        match &self.simulation {
+            Some(simulation) => simulation.rng.lock().unwrap_or_else(|e| e.into_inner()).next(),
+            None => rand::random(),
+        }
+    }
+}
+
+impl TaskScheduler {
+    /// The current time on the scheduler's clock, which is virtual in simulation mode
+    ///
+    /// Use it rather than `Instant::now()` to compute deadlines for `schedule_at`.
+    pub fn now(&self) -> Instant {
+        self.state.now()
+    }
+
+    /// Let the simulated supervisor start checking, as `start` would start its thread
+    pub(super) fn start_simulation(&self) {
+        if let Some(simulation) = &self.state.simulation
+            && self.config.needs_supervisor()
+        {
+            let interval = Self::check_interval(&self.config);
+            *simulation.supervisor.lock().unwrap_or_else(|e| e.into_inner()) = Some((self.now() + interval, interval));
+        }
+    }
+
+    /// Occupy a slot with a simulated worker instead of a thread
+    pub(super) fn start_simulated_worker(
+        worker_id: usize,
+        queue: WorkerQueue,
+        state: &SchedulerState,
+        config: &SchedulerConfig,
+        simulation: &Simulation,
+    ) {
+        if let Some(worker_init) = &config.worker_init {
+            worker_init(worker_id);
+        }
+        state.notify_hooks("on_worker_start", |hooks| hooks.on_worker_start(worker_id));
+        simulation.workers().push(SimulatedWorker {
+            id: worker_id,
+            queue: Some(queue),
+        });
+    }
+
+    /// Let one simulated worker, chosen by the seed, run one task
+    ///
+    /// Workers are tried in a seeded random order until one finds work, so
+    /// the same seed and submissions always run tasks in the same order.
+    /// Returns whether a task ran; always `false` outside simulation mode.
+    pub fn step(&self) -> bool {
+        Self::step_simulation(&self.state)
+    }
+
+    /// `step` for callers that only hold the scheduler's state
+    fn step_simulation(state: &Arc<SchedulerState>) -> bool {
+        let Some(simulation) = &state.simulation else {
+            return false;
+        };
+
+        let mut order: Vec<usize> = simulation
+            .workers()
+            .iter()
+            .filter(|worker| worker.queue.is_some())
+            .map(|worker| worker.id)
+            .collect();
+        simulation.rng.lock().unwrap_or_else(|e| e.into_inner()).shuffle(&mut order);
+
+        order.into_iter().any(|worker_id| Self::step_worker(state, worker_id, simulation))
+    }
+
+    /// One pass of `worker_loop` for a simulated worker; returns whether it ran a task
+    fn step_worker(state: &Arc<SchedulerState>, worker_id: usize, simulation: &Simulation) -> bool {
+        let Some(queue) = simulation.take_queue(worker_id) else {
+            return false;
+        };
+
+        let slot = &state.slots[worker_id];
+        let should_shutdown = state.shutdown.lock().map(|shutdown| *shutdown).unwrap_or(true);
+        if should_shutdown || slot.retiring.load(Ordering::SeqCst) {
+            Self::stop_simulated_worker(worker_id, queue, state, simulation);
+            return false;
+        }
+
+        let queue = Rc::new(queue);
+        let previous = LOCAL_QUEUE.with(|local| {
+            local.replace(Some(LocalQueue {
+                state: Arc::as_ptr(state),
+                worker_id,
+                queue: Rc::clone(&queue),
+            }))
+        });
+
+        let ran = match Self::find_work(worker_id, &queue, state, &simulation.config) {
+            Some((scheduled_task, stolen)) => {
+                Self::set_idle_since(slot, None);
+                let previous = timer::enter_virtual(Some(simulation.timers.clone()));
+                Self::run_task(worker_id, state, scheduled_task, stolen);
+                timer::enter_virtual(previous);
+                true
+            }
+            None => {
+                let idle = slot.idle_since.lock().map(|idle_since| idle_since.is_some()).unwrap_or(true);
+                if !idle {
+                    Self::set_idle_since(slot, Some(state.now()));
+                }
+                false
+            }
+        };
+
+        LOCAL_QUEUE.with(|local| local.replace(previous));
+        let Ok(queue) = Rc::try_unwrap(queue) else {
+            unreachable!("nothing keeps the local queue beyond the step");
+        };
+
+        if !ran && Self::take_poison_pill(state) {
+            Self::stop_simulated_worker(worker_id, queue, state, simulation);
+        } else {
+            simulation.return_queue(worker_id, queue);
+        }
+        ran
+    }
+
+    /// Vacate a simulated worker's slot, as a worker thread does on exit
+    fn stop_simulated_worker(worker_id: usize, queue: WorkerQueue, state: &SchedulerState, simulation: &Simulation) {
+        state.notify_hooks("on_worker_stop", |hooks| hooks.on_worker_stop(worker_id));
+        simulation.workers().retain(|worker| worker.id != worker_id);
+
+        while let Some(scheduled_task) = queue.pop() {
+            state.injector.push(scheduled_task);
+        }
+
+        let slot = &state.slots[worker_id];
+        Self::set_idle_since(slot, None);
+        slot.retiring.store(false, Ordering::SeqCst);
+        state.workers.fetch_sub(1, Ordering::SeqCst);
+        if let Ok(mut vacant) = slot.queue.lock() {
+            *vacant = Some(queue);
+        }
+        state.vacate_inbox(worker_id);
+    }
+
+    /// Stop every simulated worker that is not in the middle of a step
+    pub(super) fn stop_simulation(&self) {
+        let Some(simulation) = &self.state.simulation else {
+            return;
+        };
+
+        let idle: Vec<usize> = simulation.workers().iter().map(|worker| worker.id).collect();
+        for worker_id in idle {
+            if let Some(queue) = simulation.take_queue(worker_id) {
+                Self::stop_simulated_worker(worker_id, queue, &self.state, simulation);
+            }
+        }
+    }
+
+    /// Let `duration` pass: the virtual clock moves forward, running the
+    /// supervisor's checks as it goes, or the calling thread sleeps when
+    /// the scheduler is not simulated
+    ///
+    /// A simulated task may call this to take up virtual time while it
+    /// runs, e.g. to overrun the execution timeout.
+    pub fn advance(&self, duration: Duration) {
+        Self::advance_simulation(&self.state, duration)
+    }
+
+    /// `advance` for callers that only hold the scheduler's state
+    fn advance_simulation(state: &Arc<SchedulerState>, duration: Duration) {
+        let Some(simulation) = &state.simulation else {
+            std::thread::sleep(duration);
+            return;
+        };
+
+        let target = state.now() + duration;
+        loop {
+            let mut supervisor = simulation.supervisor.lock().unwrap_or_else(|e| e.into_inner());
+            let Some((check_at, interval)) = *supervisor else {
+                break;
+            };
+            if check_at > target {
+                break;
+            }
+            *supervisor = Some((check_at + interval, interval));
+            drop(supervisor);
+
+            simulation.clock.advance_to(check_at);
+            Self::supervise(state, &simulation.config, check_at);
+        }
+
+        simulation.clock.advance_to(target);
+        simulation.timers.heap.wake_due(target);
+        state.release_due();
+    }
+
+    /// Run one simulated task, or skip the virtual clock ahead to the next
+    /// thing that falls due; returns `false` when neither is possible, or
+    /// outside simulation mode
+    ///
+    /// Lets a thread that blocks on simulated tasks, such as `TaskHandle::join`
+    /// or `scope`, run them itself instead of waiting for workers that do
+    /// not exist.
+    pub(super) fn drive_simulation(state: &Arc<SchedulerState>) -> bool {
+        if state.simulation.is_none() {
+            return false;
+        }
+        if Self::step_simulation(state) {
+            return true;
+        }
+        match state.next_wake() {
+            Some(due) => {
+                Self::advance_simulation(state, due.saturating_duration_since(state.now()));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Run tasks until none is queued or running, skipping the virtual clock
+    /// ahead to delayed tasks, retries, rate-limited tasks and `sleep` futures
+    /// as they fall due
+    ///
+    /// Returns whether every accepted task has finished; it has not when the
+    /// remaining ones wait on something outside the simulation, such as a
+    /// future woken by another thread. Outside simulation mode this blocks
+    /// until the worker threads have finished every accepted task.
+    pub fn run_until_idle(&self) -> bool {
+        if self.state.simulation.is_none() {
+            return self.wait_until_idle(None);
+        }
+
+        loop {
+            if self.state.outstanding_tasks.load(Ordering::SeqCst) == 0 {
+                return true;
+            }
+            if !Self::drive_simulation(&self.state) {
+                return false;
+            }
+        }
+    }
+
+    /// Run tasks for `duration` of virtual time, including periodic ones,
+    /// then leave the clock `duration` ahead; outside simulation mode, sleep
+    pub fn run_for(&self, duration: Duration) {
+        if self.state.simulation.is_none() {
+            std::thread::sleep(duration);
+            return;
+        }
+
+        let deadline = self.now() + duration;
+        loop {
+            if self.step() {
+                continue;
+            }
+            match self.state.next_wake() {
+                Some(due) if due <= deadline => self.advance(due.saturating_duration_since(self.now())),
+                _ => break,
+            }
+        }
+        self.advance(deadline.saturating_duration_since(self.now()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task_scheduler::{ExecutionTimeoutAction, SchedulerEvent, test_config};
+    use std::sync::atomic::AtomicUsize;
+
+    fn simulated_scheduler(seed: u64, config: SchedulerConfig) -> TaskScheduler {
+        let config = SchedulerConfig {
+            simulation_seed: Some(seed),
+            ..config
+        };
+
+        let mut scheduler = TaskScheduler::new(config);
+        scheduler.start();
+        scheduler
+    }
+
+    /// The order in which tasks spawned by a few parents ran under `seed`
+    fn run_order(seed: u64) -> Vec<u32> {
+        let scheduler = Arc::new(simulated_scheduler(seed, test_config(3)));
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        for parent in 0..3u32 {
+            let order = Arc::clone(&order);
+            let spawner = Arc::clone(&scheduler);
+            scheduler
+                .submit(move || {
+                    for child in 0..4 {
+                        let order = Arc::clone(&order);
+                        spawner.submit(move || order.lock().unwrap().push(parent * 10 + child)).unwrap();
+                    }
+                })
+                .unwrap();
+        }
+
+        assert!(scheduler.run_until_idle());
+        let order = order.lock().unwrap().clone();
+        let Ok(scheduler) = Arc::try_unwrap(scheduler) else {
+            panic!("tasks still hold the scheduler");
+        };
+        assert_eq!(scheduler.shutdown().completed, 15);
+        order
+    }
+
+    #[test]
+    fn test_a_seed_replays_the_same_interleaving() {
+        let first = run_order(7);
+        assert_eq!(first.len(), 12);
+        assert_eq!(run_order(7), first);
+
+        // Some other seed schedules the workers differently
+        assert!((0..20).any(|seed| run_order(seed) != first));
+    }
+
+    #[test]
+    fn test_waiting_on_simulated_tasks_runs_the_simulation() {
+        let scheduler = Arc::new(simulated_scheduler(11, test_config(3)));
+
+        let handle = scheduler.submit_with_result(|| 6 * 7).unwrap();
+        assert_eq!(handle.join().unwrap(), 42);
+
+        // Also from inside a simulated task, where the waiting worker cannot step itself
+        let nested = Arc::clone(&scheduler);
+        let handle = scheduler
+            .submit_with_result(move || {
+                let inner = nested.submit_with_result(|| 1).unwrap();
+                let (a, b) = nested.join(|| 2, || 3);
+                inner.join().unwrap() + a + b
+            })
+            .unwrap();
+        assert_eq!(handle.join().unwrap(), 6);
+
+        let items: Vec<u64> = (0..100).collect();
+        assert_eq!(scheduler.par_map(&items, |item| item * 2), (0..200).step_by(2).collect::<Vec<_>>());
+
+        let group = scheduler.group();
+        for i in 0..5u64 {
+            group.submit(move || i + 1).unwrap();
+        }
+        assert_eq!(group.wait_all().into_result().unwrap(), [1, 2, 3, 4, 5]);
+
+        // A sleep on the virtual clock is skipped over rather than waited out
+        let started = Instant::now();
+        let handle = scheduler
+            .spawn_future(async {
+                crate::task_scheduler::sleep(Duration::from_secs(3600)).await;
+                "woke"
+            })
+            .unwrap();
+        assert_eq!(crate::task_scheduler::block_on(handle).unwrap(), "woke");
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let Ok(scheduler) = Arc::try_unwrap(scheduler) else {
+            panic!("tasks still hold the scheduler");
+        };
+        assert_eq!(scheduler.shutdown().dropped, 0);
+    }
+
+    #[test]
+    fn test_delayed_tasks_run_on_the_virtual_clock() {
+        let scheduler = simulated_scheduler(1, test_config(2));
+        let started = Instant::now();
+        let start = scheduler.now();
+
+        let ran_at = Arc::new(Mutex::new(Vec::new()));
+        for delay_secs in [3605, 65] {
+            let ran_at = Arc::clone(&ran_at);
+            let clock = scheduler.state.clock.clone();
+            scheduler
+                .schedule_after(Duration::from_secs(delay_secs), move || ran_at.lock().unwrap().push(clock.now()))
+                .unwrap();
+        }
+        let runs = Arc::new(AtomicUsize::new(0));
+        let runs_clone = Arc::clone(&runs);
+        scheduler
+            .schedule_every(Duration::from_secs(10), move || {
+                runs_clone.fetch_add(1, Ordering::SeqCst);
+            })
+            .unwrap();
+
+        assert!(scheduler.run_until_idle());
+        let ran_at: Vec<Duration> = ran_at.lock().unwrap().iter().map(|at| *at - start).collect();
+        assert_eq!(ran_at, [Duration::from_secs(65), Duration::from_secs(3605)]);
+        assert_eq!(runs.load(Ordering::SeqCst), 360);
+
+        scheduler.run_for(Duration::from_secs(100));
+        assert_eq!(runs.load(Ordering::SeqCst), 370);
+        assert_eq!(scheduler.now() - start, Duration::from_secs(3705));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        scheduler.shutdown();
+    }
+
+    #[test]
+    fn test_sleeping_futures_wake_on_the_virtual_clock() {
+        let scheduler = simulated_scheduler(5, test_config(2));
+        let started = Instant::now();
+        let start = scheduler.now();
+
+        let clock = scheduler.state.clock.clone();
+        let handles: Vec<_> = [3600, 60]
+            .into_iter()
+            .map(|secs| {
+                let clock = clock.clone();
+                scheduler
+                    .spawn_future(async move {
+                        crate::task_scheduler::sleep(Duration::from_secs(secs)).await;
+                        clock.now()
+                    })
+                    .unwrap()
+            })
+            .collect();
+
+        assert!(scheduler.run_until_idle());
+        let woke_at: Vec<Duration> = handles.into_iter().map(|handle| handle.join().unwrap() - start).collect();
+        assert_eq!(woke_at, [Duration::from_secs(3600), Duration::from_secs(60)]);
+        assert_eq!(scheduler.now() - start, Duration::from_secs(3600));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(scheduler.shutdown().completed, 2);
+    }
+
+    #[test]
+    fn test_supervisor_checks_follow_the_virtual_clock() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let events_clone = Arc::clone(&events);
+        let scheduler = Arc::new(simulated_scheduler(3, SchedulerConfig {
+            timeout_seconds: 1,
+            enable_work_stealing: false,
+            execution_timeout_ms: 2000,
+            execution_timeout_action: ExecutionTimeoutAction::Log,
+            event_sink: Arc::new(move |event: &SchedulerEvent| events_clone.lock().unwrap().push(event.clone())),
+            ..test_config(1)
+        }));
+
+        // The only worker takes five virtual seconds over its task while
+        // another waits in the injector
+        let scheduler_clone = Arc::clone(&scheduler);
+        let slow = scheduler.submit(move || scheduler_clone.advance(Duration::from_secs(5))).unwrap();
+        let queued = scheduler.submit(|| {}).unwrap();
+        assert!(scheduler.run_until_idle());
+
+        let events = events.lock().unwrap();
+        assert!(events.iter().any(|event| matches!(
+            event,
+            SchedulerEvent::TaskWaiting { task_id, .. } if *task_id == queued
+        )));
+        assert!(events.iter().any(|event| matches!(
+            event,
+            SchedulerEvent::TaskOverrunning { task, .. } if task.task_id == slow && task.running_for > Duration::from_secs(2)
+        )));
+    }
+}
@@ -0,0 +1,444 @@
+use std::fmt;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use super::{SchedulerState, TaskScheduler, TenantStats};
+
+/// Sub-buckets per power of two; 8 keeps every bucket within 12.5% of its values
+const SUB_BUCKET_BITS: u32 = 3;
+const SUB_BUCKETS: u64 = 1 << SUB_BUCKET_BITS;
+const HISTOGRAM_BUCKETS: usize = (64 - SUB_BUCKET_BITS as usize + 1) * SUB_BUCKETS as usize;
+
+/// Lock-free log-linear histogram of durations, recorded in nanoseconds
+pub(super) struct Histogram {
+    buckets: Vec<AtomicU64>,
+    max_ns: AtomicU64,
+}
+
+impl Histogram {
+    pub(super) fn new() -> Self {
+        Self {
+            buckets: (0..HISTOGRAM_BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            max_ns: AtomicU64::new(0),
+        }
+    }
+
+    pub(super) fn record(&self, value: Duration) {
+        let nanos = u64::try_from(value.as_nanos()).unwrap_or(u64::MAX);
+        self.buckets[Self::bucket(nanos)].fetch_add(1, Ordering::Relaxed);
+        // A new maximum is rare, so avoid the read-modify-write on every record
+        if nanos > self.max_ns.load(Ordering::Relaxed) {
+            self.max_ns.fetch_max(nanos, Ordering::Relaxed);
+        }
+    }
+
+    /// Values below `2 * SUB_BUCKETS` get a bucket each; above that, each
+    /// power of two is split into `SUB_BUCKETS` equal parts
+    fn bucket(nanos: u64) -> usize {
+        if nanos < SUB_BUCKETS * 2 {
+            return nanos as usize;
+        }
+        let shift = u64::BITS - 1 - nanos.leading_zeros() - SUB_BUCKET_BITS;
+        (shift as u64 * SUB_BUCKETS + (nanos >> shift)) as usize
+    }
+
+    /// Largest value that lands in `bucket`
+    fn bucket_upper_bound(bucket: usize) -> u64 {
+        let bucket = bucket as u64;
+        if bucket < SUB_BUCKETS * 2 {
+            return bucket;
+        }
+        let shift = bucket / SUB_BUCKETS - 1;
+        let mantissa = bucket % SUB_BUCKETS + SUB_BUCKETS;
+        (mantissa << shift) + ((1 << shift) - 1)
+    }
+
+    /// Percentiles over everything recorded so far
+    pub(super) fn summary(&self) -> LatencySummary {
+        let counts: Vec<u64> = self.buckets.iter().map(|bucket| bucket.load(Ordering::Relaxed)).collect();
+        let count: u64 = counts.iter().sum();
+        let max_ns = self.max_ns.load(Ordering::Relaxed);
+
+        let percentile = |quantile: f64| {
+            if count == 0 {
+                return Duration::ZERO;
+            }
+            let rank = ((quantile * count as f64).ceil() as u64).max(1);
+            let mut seen = 0;
+            let bucket = counts
+                .iter()
+                .position(|&bucket_count| {
+                    seen += bucket_count;
+                    seen >= rank
+                })
+                .unwrap_or(HISTOGRAM_BUCKETS - 1);
+            // Buckets are coarse, so never report more than was actually seen
+            Duration::from_nanos(Self::bucket_upper_bound(bucket).min(max_ns))
+        };
+
+        LatencySummary {
+            count,
+            p50: percentile(0.50),
+            p95: percentile(0.95),
+            p99: percentile(0.99),
+            max: Duration::from_nanos(max_ns),
+        }
+    }
+}
+
+/// Counters a worker slot accumulates over the scheduler's lifetime
+#[derive(Default)]
+pub(super) struct WorkerCounters {
+    pub(super) executed: AtomicUsize,
+    pub(super) panicked: AtomicUsize,
+    /// Tasks other workers stole from this slot's queue
+    pub(super) stolen_from: AtomicUsize,
+    /// Tasks the worker in this slot stole from other queues
+    pub(super) stolen_by: AtomicUsize,
+    pub(super) idle_ns: AtomicU64,
+    /// Deepest queue the supervisor has sampled
+    pub(super) peak_queue_depth: AtomicUsize,
+}
+
+impl WorkerCounters {
+    pub(super) fn add_idle(&self, idle: Duration) {
+        let nanos = u64::try_from(idle.as_nanos()).unwrap_or(u64::MAX);
+        self.idle_ns.fetch_add(nanos, Ordering::Relaxed);
+    }
+}
+
+/// Percentiles of a latency distribution
+///
+/// Values are accurate to within 12.5%; `max` is exact.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencySummary {
+    pub count: u64,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+/// Counters for one worker slot
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WorkerStats {
+    pub worker_id: usize,
+    /// Whether a thread currently occupies the slot
+    pub active: bool,
+    /// Tasks run, including those that panicked
+    pub executed: usize,
+    pub panicked: usize,
+    /// Tasks other workers stole from this worker's queue
+    pub stolen_from: usize,
+    /// Tasks this worker stole from other workers' queues
+    pub stolen_by: usize,
+    /// Time spent parked waiting for work
+    pub idle_time: Duration,
+    /// Tasks waiting in the worker's own queue and inbox
+    pub queue_depth: usize,
+    /// Deepest queue seen by the supervisor's periodic sampling
+    pub peak_queue_depth: usize,
+}
+
+/// Point-in-time snapshot of the scheduler, returned by `TaskScheduler::stats`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SchedulerStats {
+    /// Tasks accepted over the scheduler's lifetime
+    pub submitted: u64,
+    pub completed: usize,
+    pub panicked: usize,
+    /// Retried tasks whose last attempt returned `Err`
+    pub failed: usize,
+    /// Tasks refused or evicted because the queues were at capacity
+    pub rejected: usize,
+    /// Accepted tasks that are queued, running, waiting to be retried or
+    /// waiting for their scheduled time; periodic tasks count only while due or running
+    pub in_flight: usize,
+    pub injector_depth: usize,
+    /// Tasks waiting for their scheduled time or a retry backoff
+    pub delayed_depth: usize,
+    /// Deepest injector seen by the supervisor's periodic sampling
+    pub peak_injector_depth: usize,
+    /// Slots that are occupied or have run tasks, in slot order
+    pub workers: Vec<WorkerStats>,
+    /// Configured tenants, by name
+    pub tenants: Vec<TenantStats>,
+    /// Time from submission until a worker started the task
+    pub queue_wait: LatencySummary,
+    pub execution_time: LatencySummary,
+}
+
+impl SchedulerStats {
+    /// Serialize as a single JSON object, with durations in microseconds
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        let _ = write!(
+            json,
+            "{{\"submitted\":{},\"completed\":{},\"panicked\":{},\"failed\":{},\"rejected\":{},\
+             \"in_flight\":{},\"injector_depth\":{},\"delayed_depth\":{},\
+             \"peak_injector_depth\":{},\"queue_wait\":{},\"execution_time\":{},\"tenants\":[",
+            self.submitted,
+            self.completed,
+            self.panicked,
+            self.failed,
+            self.rejected,
+            self.in_flight,
+            self.injector_depth,
+            self.delayed_depth,
+            self.peak_injector_depth,
+            self.queue_wait.to_json(),
+            self.execution_time.to_json(),
+        );
+        for (i, tenant) in self.tenants.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let _ = write!(
+                json,
+                "{{\"tenant\":{},\"weight\":{},\"submitted\":{},\"executed\":{},\"queued\":{},\
+                 \"queue_wait\":{},\"execution_time\":{}}}",
+                json_string(&tenant.tenant),
+                tenant.weight,
+                tenant.submitted,
+                tenant.executed,
+                tenant.queued,
+                tenant.queue_wait.to_json(),
+                tenant.execution_time.to_json(),
+            );
+        }
+        json.push_str("],\"workers\":[");
+        for (i, worker) in self.workers.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let _ = write!(
+                json,
+                "{{\"worker_id\":{},\"active\":{},\"executed\":{},\"panicked\":{},\
+                 \"stolen_from\":{},\"stolen_by\":{},\"idle_time_us\":{},\
+                 \"queue_depth\":{},\"peak_queue_depth\":{}}}",
+                worker.worker_id,
+                worker.active,
+                worker.executed,
+                worker.panicked,
+                worker.stolen_from,
+                worker.stolen_by,
+                worker.idle_time.as_micros(),
+                worker.queue_depth,
+                worker.peak_queue_depth,
+            );
+        }
+        json.push_str("]}");
+        json
+    }
+}
+
+/// Quote and escape a string for JSON
+fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl LatencySummary {
+    fn to_json(self) -> String {
+        format!(
+            "{{\"count\":{},\"p50_us\":{},\"p95_us\":{},\"p99_us\":{},\"max_us\":{}}}",
+            self.count,
+            self.p50.as_micros(),
+            self.p95.as_micros(),
+            self.p99.as_micros(),
+            self.max.as_micros()
+        )
+    }
+}
+
+impl fmt::Display for LatencySummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "n={} p50={:?} p95={:?} p99={:?} max={:?}",
+            self.count, self.p50, self.p95, self.p99, self.max
+        )
+    }
+}
+
+impl fmt::Display for SchedulerStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "submitted: {}, completed: {}, panicked: {}, failed: {}, rejected: {}, in flight: {}, injector: {} (peak {}), delayed: {}",
+            self.submitted,
+            self.completed,
+            self.panicked,
+            self.failed,
+            self.rejected,
+            self.in_flight,
+            self.injector_depth,
+            self.peak_injector_depth,
+            self.delayed_depth
+        )?;
+        writeln!(f, "queue wait:     {}", self.queue_wait)?;
+        write!(f, "execution time: {}", self.execution_time)?;
+        for worker in &self.workers {
+            write!(
+                f,
+                "\nworker {}{}: executed {}, panicked {}, stolen from {}, stole {}, idle {:?}, queue {} (peak {})",
+                worker.worker_id,
+                if worker.active { "" } else { " (exited)" },
+                worker.executed,
+                worker.panicked,
+                worker.stolen_from,
+                worker.stolen_by,
+                worker.idle_time,
+                worker.queue_depth,
+                worker.peak_queue_depth
+            )?;
+        }
+        for tenant in &self.tenants {
+            write!(
+                f,
+                "\ntenant {} (weight {}): submitted {}, executed {}, queued {}, queue wait {}",
+                tenant.tenant, tenant.weight, tenant.submitted, tenant.executed, tenant.queued, tenant.queue_wait
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl SchedulerState {
+    /// Record current queue depths as peaks; called by the supervisor on every check
+    pub(super) fn sample_queue_depths(&self) {
+        self.peak_injector_depth
+            .fetch_max(self.injector.len(), Ordering::Relaxed);
+        for slot in &self.slots {
+            slot.counters
+                .peak_queue_depth
+                .fetch_max(slot.queued(), Ordering::Relaxed);
+        }
+    }
+}
+
+impl TaskScheduler {
+    /// Snapshot of the scheduler's counters, queue depths and latency percentiles
+    ///
+    /// Counters are read one at a time while workers keep running, so
+    /// totals may be off by the few tasks that finish during the call.
+    pub fn stats(&self) -> SchedulerStats {
+        let state = &self.state;
+        let workers = state
+            .slots
+            .iter()
+            .enumerate()
+            .filter_map(|(worker_id, slot)| {
+                let counters = &slot.counters;
+                let active = slot.is_occupied();
+                let executed = counters.executed.load(Ordering::Relaxed);
+                if !active && executed == 0 {
+                    return None;
+                }
+
+                Some(WorkerStats {
+                    worker_id,
+                    active,
+                    executed,
+                    panicked: counters.panicked.load(Ordering::Relaxed),
+                    stolen_from: counters.stolen_from.load(Ordering::Relaxed),
+                    stolen_by: counters.stolen_by.load(Ordering::Relaxed),
+                    idle_time: Duration::from_nanos(counters.idle_ns.load(Ordering::Relaxed)),
+                    queue_depth: slot.queued(),
+                    peak_queue_depth: counters.peak_queue_depth.load(Ordering::Relaxed),
+                })
+            })
+            .collect();
+
+        SchedulerStats {
+            submitted: state.task_counter.load(Ordering::Relaxed),
+            completed: state.completed_tasks.load(Ordering::SeqCst),
+            panicked: state.panicked_tasks.load(Ordering::SeqCst),
+            failed: state.failed_tasks.load(Ordering::SeqCst),
+            rejected: state.rejected_tasks.load(Ordering::SeqCst),
+            in_flight: state.outstanding_tasks.load(Ordering::SeqCst),
+            injector_depth: state.injector.len(),
+            delayed_depth: state.delayed.len(),
+            peak_injector_depth: state.peak_injector_depth.load(Ordering::Relaxed),
+            workers,
+            tenants: state.tenant_stats(),
+            queue_wait: state.queue_wait.summary(),
+            execution_time: state.execution_time.summary(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_buckets_are_contiguous() {
+        let mut previous_upper = None;
+        for bucket in 0..HISTOGRAM_BUCKETS {
+            let upper = Histogram::bucket_upper_bound(bucket);
+            assert_eq!(Histogram::bucket(upper), bucket);
+            if let Some(previous) = previous_upper {
+                assert_eq!(Histogram::bucket(previous + 1), bucket);
+            }
+            previous_upper = (upper < u64::MAX).then_some(upper);
+        }
+    }
+
+    #[test]
+    fn test_histogram_percentiles() {
+        let histogram = Histogram::new();
+        assert_eq!(histogram.summary(), LatencySummary::default());
+
+        for micros in 1..=100 {
+            histogram.record(Duration::from_micros(micros));
+        }
+
+        let summary = histogram.summary();
+        assert_eq!(summary.count, 100);
+        assert_eq!(summary.max, Duration::from_micros(100));
+        for (reported, exact) in [(summary.p50, 50), (summary.p95, 95), (summary.p99, 99)] {
+            let exact = Duration::from_micros(exact);
+            assert!(reported >= exact && reported <= exact + exact / 8, "{:?} vs {:?}", reported, exact);
+        }
+    }
+
+    #[test]
+    fn test_stats_to_json() {
+        let stats = SchedulerStats {
+            submitted: 3,
+            completed: 2,
+            panicked: 1,
+            workers: vec![WorkerStats {
+                worker_id: 0,
+                active: true,
+                executed: 3,
+                idle_time: Duration::from_millis(2),
+                ..WorkerStats::default()
+            }],
+            ..SchedulerStats::default()
+        };
+
+        let json = stats.to_json();
+        assert!(json.starts_with("{\"submitted\":3,\"completed\":2,\"panicked\":1,\"failed\":0,"));
+        assert!(json.contains("\"queue_wait\":{\"count\":0,\"p50_us\":0,"));
+        assert!(json.ends_with(
+            "\"workers\":[{\"worker_id\":0,\"active\":true,\"executed\":3,\"panicked\":0,\
+             \"stolen_from\":0,\"stolen_by\":0,\"idle_time_us\":2000,\"queue_depth\":0,\
+             \"peak_queue_depth\":0}]}"
+        ));
+    }
+}
@@ -0,0 +1,247 @@
+use std::sync::atomic::Ordering;
+
+use super::queue::WorkerQueue;
+use super::{Priority, ScheduledTask, SchedulerState, TaskScheduler};
+
+/// Chooses which worker an idle worker steals from, and how much it takes
+///
+/// Selected with `SchedulerConfig::steal_strategy`.
+pub trait StealStrategy: Send + Sync {
+    /// The worker to steal from, or `None` to find nothing this round
+    fn victim(&self, context: &StealContext<'_>) -> Option<usize>;
+
+    /// How many tasks to take from a victim with `queued` tasks; the thief
+    /// runs the first and keeps the rest in its own queue
+    fn amount(&self, _queued: usize) -> usize {
+        1
+    }
+}
+
+/// What a steal strategy sees of the pool
+pub struct StealContext<'a> {
+    thief: usize,
+    state: &'a SchedulerState,
+}
+
+impl StealContext<'_> {
+    /// The worker looking for work
+    pub fn thief(&self) -> usize {
+        self.thief
+    }
+
+    /// Every other worker slot, starting after the thief and wrapping around
+    pub fn others(&self) -> impl Iterator<Item = usize> + '_ {
+        let num_slots = self.state.slots.len();
+        (1..num_slots).map(move |i| (self.thief + i) % num_slots)
+    }
+
+    /// Priority of the most urgent task that could be stolen from `worker`
+    pub fn top_priority(&self, worker: usize) -> Option<Priority> {
+        let slot = &self.state.slots[worker];
+        slot.stealer.top_priority().max(slot.inbox.top_priority())
+    }
+
+    /// Tasks queued for `worker`
+    pub fn load(&self, worker: usize) -> usize {
+        self.state.slots[worker].queued()
+    }
+
+    /// Whether `worker` shares the thief's CPU set; always true when workers are not pinned
+    pub fn same_core_group(&self, worker: usize) -> bool {
+        self.state.same_core_group(self.thief, worker)
+    }
+
+    /// A random number, from the seeded generator in simulation mode
+    pub fn random(&self) -> u64 {
+        self.state.random()
+    }
+
+    /// The worker with the most urgent work, preferring the thief's core
+    /// group between equally urgent ones and the nearest after the thief
+    /// after that
+    pub fn most_urgent(&self) -> Option<usize> {
+        let mut best = None;
+        let mut best_worker = None;
+        for worker in self.others() {
+            let top = self.top_priority(worker);
+            let candidate = (top, self.same_core_group(worker));
+            if top.is_some() && Some(candidate) > best {
+                best = Some(candidate);
+                best_worker = Some(worker);
+                if candidate == (Some(Priority::MAX), true) {
+                    break;
+                }
+            }
+        }
+        best_worker
+    }
+}
+
+/// Take one task from the worker with the most urgent work (the default)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StealOne;
+
+impl StealStrategy for StealOne {
+    fn victim(&self, context: &StealContext<'_>) -> Option<usize> {
+        context.most_urgent()
+    }
+}
+
+/// Take half the queue of the worker with the most urgent work, so a
+/// backlog spreads out in fewer steals
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StealHalf;
+
+impl StealStrategy for StealHalf {
+    fn victim(&self, context: &StealContext<'_>) -> Option<usize> {
+        context.most_urgent()
+    }
+
+    fn amount(&self, queued: usize) -> usize {
+        queued.div_ceil(2)
+    }
+}
+
+/// Take one task from a random worker that has any, so thieves spread
+/// over victims instead of all converging on the same one
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RandomVictim;
+
+impl StealStrategy for RandomVictim {
+    fn victim(&self, context: &StealContext<'_>) -> Option<usize> {
+        let candidates: Vec<usize> = context.others().filter(|&worker| context.load(worker) > 0).collect();
+        if candidates.is_empty() {
+            return None;
+        }
+        Some(candidates[(context.random() % candidates.len() as u64) as usize])
+    }
+}
+
+impl TaskScheduler {
+    /// Steal from the worker the steal strategy picks, keeping all but the
+    /// first task taken in our own queue
+    pub(super) fn try_steal_work(worker_id: usize, queue: &WorkerQueue, state: &SchedulerState) -> Option<ScheduledTask> {
+        let context = StealContext { thief: worker_id, state };
+        let victim = state.steal_strategy.victim(&context).filter(|&victim| victim != worker_id && victim < state.slots.len())?;
+        let slot = &state.slots[victim];
+        // Beyond the task we run, keep no more than a bounded queue has room for
+        let room = match state.worker_queue_capacity {
+            0 => usize::MAX,
+            capacity => capacity.saturating_sub(queue.len()),
+        };
+        let amount = state.steal_strategy.amount(slot.queued()).clamp(1, room.saturating_add(1));
+
+        // Tasks placed in the victim's inbox are as fair game as its own queue
+        let mut stolen = if !slot.inbox.is_empty() && slot.inbox.top_priority() >= slot.stealer.top_priority() {
+            slot.inbox.pop_up_to(amount)
+        } else {
+            std::iter::from_fn(|| slot.stealer.steal()).take(amount).collect()
+        };
+        if stolen.is_empty() {
+            return None;
+        }
+
+        slot.counters.stolen_from.fetch_add(stolen.len(), Ordering::Relaxed);
+        state.slots[worker_id].counters.stolen_by.fetch_add(stolen.len(), Ordering::Relaxed);
+        for task in &stolen {
+            state.notify_hooks("on_steal", |hooks| hooks.on_steal(worker_id, victim, task.metadata.id));
+        }
+
+        let first = stolen.remove(0);
+        // Pushed newest first so our LIFO pops keep the victim's order
+        for scheduled_task in stolen.into_iter().rev() {
+            queue.push(scheduled_task);
+        }
+        Some(first)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task_scheduler::{CancellationToken, SchedulerConfig, test_config};
+    use std::sync::Arc;
+
+    /// A scheduler that is never started, so tasks stay where the test puts them
+    fn idle_scheduler(steal_strategy: Arc<dyn StealStrategy>) -> TaskScheduler {
+        bounded_idle_scheduler(steal_strategy, 0)
+    }
+
+    fn bounded_idle_scheduler(steal_strategy: Arc<dyn StealStrategy>, worker_queue_capacity: usize) -> TaskScheduler {
+        TaskScheduler::new(SchedulerConfig {
+            simulation_seed: Some(3),
+            steal_strategy,
+            worker_queue_capacity,
+            ..test_config(2)
+        })
+    }
+
+    /// Queue `count` tasks at `priority` in the inbox of `worker`
+    fn fill_inbox(scheduler: &TaskScheduler, worker: usize, count: u64, priority: Priority) {
+        for id in 0..count {
+            let task = TaskScheduler::job(|| {});
+            let scheduled_task = ScheduledTask::new(id, task, priority, CancellationToken::new(), scheduler.now());
+            scheduler.state.slots[worker].inbox.push(scheduled_task);
+        }
+    }
+
+    #[test]
+    fn test_steal_half_keeps_the_rest_locally() {
+        for (strategy, kept) in [(Arc::new(StealOne) as Arc<dyn StealStrategy>, 0), (Arc::new(StealHalf), 4)] {
+            let scheduler = idle_scheduler(strategy);
+            fill_inbox(&scheduler, 1, 10, Priority::NORMAL);
+            let (queue, _stealer) = WorkerQueue::new();
+
+            let first = TaskScheduler::try_steal_work(0, &queue, &scheduler.state).unwrap();
+            assert_eq!(first.metadata.id, 0);
+            assert_eq!(queue.len(), kept);
+            // The victim's order is kept
+            assert!(queue.pop().is_none_or(|next| next.metadata.id == 1));
+            let slots = &scheduler.state.slots;
+            assert_eq!(slots[0].counters.stolen_by.load(Ordering::Relaxed), kept + 1);
+            assert_eq!(slots[1].counters.stolen_from.load(Ordering::Relaxed), kept + 1);
+        }
+    }
+
+    #[test]
+    fn test_steal_half_respects_worker_queue_capacity() {
+        let scheduler = bounded_idle_scheduler(Arc::new(StealHalf), 2);
+        fill_inbox(&scheduler, 1, 10, Priority::NORMAL);
+        let (queue, _stealer) = WorkerQueue::new();
+
+        // Half of ten would be five, but only two fit beside the one it runs
+        let first = TaskScheduler::try_steal_work(0, &queue, &scheduler.state).unwrap();
+        assert_eq!(first.metadata.id, 0);
+        assert_eq!(queue.len(), 2);
+
+        // A full queue still steals the one task it runs
+        assert!(TaskScheduler::try_steal_work(0, &queue, &scheduler.state).is_some());
+        assert_eq!(queue.len(), 2);
+        assert_eq!(scheduler.state.slots[1].inbox.len(), 6);
+    }
+
+    #[test]
+    fn test_most_urgent_victim_is_preferred() {
+        let scheduler = idle_scheduler(Arc::new(StealOne));
+        let context = StealContext { thief: 0, state: &scheduler.state };
+        assert_eq!(StealOne.victim(&context), None);
+
+        fill_inbox(&scheduler, 1, 3, Priority::NORMAL);
+        fill_inbox(&scheduler, 3, 1, Priority::HIGH);
+        assert_eq!(StealOne.victim(&context), Some(3));
+        assert_eq!(StealHalf.amount(3), 2);
+    }
+
+    #[test]
+    fn test_random_victim_only_picks_workers_with_work() {
+        let scheduler = idle_scheduler(Arc::new(RandomVictim));
+        let context = StealContext { thief: 0, state: &scheduler.state };
+        assert_eq!(RandomVictim.victim(&context), None);
+
+        fill_inbox(&scheduler, 1, 1, Priority::NORMAL);
+        fill_inbox(&scheduler, 2, 1, Priority::LOW);
+        let victims: Vec<usize> = (0..20).filter_map(|_| RandomVictim.victim(&context)).collect();
+        assert!(victims.iter().all(|&victim| victim == 1 || victim == 2));
+        assert!(victims.contains(&1) && victims.contains(&2));
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use super::stats::Histogram;
+use super::{CancellationToken, LatencySummary, Priority, SchedulerError, SchedulerState, TaskMetadata, TaskScheduler, TaskTags};
+
+/// Counters one tenant accumulates over the scheduler's lifetime
+struct TenantCounters {
+    submitted: AtomicU64,
+    executed: AtomicUsize,
+    queue_wait: Histogram,
+    execution_time: Histogram,
+}
+
+/// The tenants configured in `SchedulerConfig::tenant_weights`, indexed by
+/// `TaskTags::tenant`
+pub(super) struct Tenants {
+    ids: HashMap<String, u16>,
+    names: Vec<String>,
+    weights: Vec<u32>,
+    counters: Vec<TenantCounters>,
+}
+
+impl Tenants {
+    pub(super) fn new(weights: &HashMap<String, u32>) -> Self {
+        // Sorted so that stats list tenants in a stable order
+        let mut names: Vec<String> = weights.keys().cloned().collect();
+        names.sort();
+
+        Self {
+            ids: names.iter().zip(0..).map(|(name, id)| (name.clone(), id)).collect(),
+            weights: names.iter().map(|name| weights[name].max(1)).collect(),
+            counters: names
+                .iter()
+                .map(|_| TenantCounters {
+                    submitted: AtomicU64::new(0),
+                    executed: AtomicUsize::new(0),
+                    queue_wait: Histogram::new(),
+                    execution_time: Histogram::new(),
+                })
+                .collect(),
+            names,
+        }
+    }
+
+    fn id(&self, name: &str) -> Option<u16> {
+        self.ids.get(name).copied()
+    }
+
+    /// Weight of each injector lane: tasks without a tenant share lane 0,
+    /// with weight 1, and each tenant has the lane after its index
+    pub(super) fn lane_weights(&self) -> Vec<u32> {
+        std::iter::once(1).chain(self.weights.iter().copied()).collect()
+    }
+
+    pub(super) fn submitted(&self, tenant: Option<u16>) {
+        if let Some(tenant) = tenant {
+            self.counters[tenant as usize].submitted.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl TaskMetadata {
+    /// The injector lane the task queues in, by its tenant
+    pub(super) fn lane(&self) -> usize {
+        self.tags.tenant.map_or(0, |tenant| tenant as usize + 1)
+    }
+}
+
+/// Counters and latencies for one tenant
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TenantStats {
+    pub tenant: String,
+    pub weight: u32,
+    /// Tasks accepted for the tenant over the scheduler's lifetime
+    pub submitted: u64,
+    /// Task runs, including retries and periodic runs
+    pub executed: usize,
+    /// Tasks waiting in the injector or an inbox; those already handed to a worker are not counted
+    pub queued: usize,
+    pub queue_wait: LatencySummary,
+    pub execution_time: LatencySummary,
+}
+
+impl SchedulerState {
+    /// Record a finished run against the task's tenant
+    pub(super) fn tenant_ran(&self, metadata: &TaskMetadata, queue_wait: Duration, execution_time: Duration) {
+        if let Some(tenant) = metadata.tags.tenant {
+            let counters = &self.tenants.counters[tenant as usize];
+            counters.executed.fetch_add(1, Ordering::Relaxed);
+            counters.queue_wait.record(queue_wait);
+            counters.execution_time.record(execution_time);
+        }
+    }
+
+    pub(super) fn tenant_stats(&self) -> Vec<TenantStats> {
+        let tenants = &self.tenants;
+        if tenants.names.is_empty() {
+            return Vec::new();
+        }
+
+        let mut queued = self.injector.lane_lens();
+        for slot in &self.slots {
+            for (lane, len) in slot.inbox.lane_lens().into_iter().enumerate() {
+                queued[lane] += len;
+            }
+        }
+        tenants
+            .names
+            .iter()
+            .enumerate()
+            .map(|(tenant, name)| {
+                let counters = &tenants.counters[tenant];
+                TenantStats {
+                    tenant: name.clone(),
+                    weight: tenants.weights[tenant],
+                    submitted: counters.submitted.load(Ordering::Relaxed),
+                    executed: counters.executed.load(Ordering::Relaxed),
+                    queued: queued[tenant + 1],
+                    queue_wait: counters.queue_wait.summary(),
+                    execution_time: counters.execution_time.summary(),
+                }
+            })
+            .collect()
+    }
+}
+
+impl TaskScheduler {
+    /// Submit a task on behalf of one of the tenants of `SchedulerConfig::tenant_weights`
+    ///
+    /// Queued tasks are shared out between tenants in proportion to their
+    /// weights, so a tenant with a long backlog cannot starve the others;
+    /// tasks without a tenant take turns as one more tenant of weight 1.
+    /// Fails with `SchedulerError::UnknownTenant` if `tenant` is not configured.
+    pub fn submit_for_tenant<F>(&self, tenant: &str, task: F) -> Result<u64, SchedulerError>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let tenant = self.state.tenants.id(tenant).ok_or(SchedulerError::UnknownTenant)?;
+        let tags = TaskTags {
+            tenant: Some(tenant),
+            ..TaskTags::default()
+        };
+        self.state
+            .enqueue_tagged(Self::job(task), Priority::NORMAL, tags, CancellationToken::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task_scheduler::{SchedulerConfig, test_config};
+    use std::sync::{Arc, Mutex};
+
+    fn simulated_with_tenants(num_workers: usize, weights: &[(&str, u32)]) -> TaskScheduler {
+        let mut scheduler = TaskScheduler::new(SchedulerConfig {
+            simulation_seed: Some(5),
+            tenant_weights: weights.iter().map(|(name, weight)| (name.to_string(), *weight)).collect(),
+            ..test_config(num_workers)
+        });
+        scheduler.start();
+        scheduler
+    }
+
+    /// Submit `count` tasks for `tenant` that log its name when they run
+    fn submit_logged(scheduler: &TaskScheduler, tenant: &'static str, count: usize, log: &Arc<Mutex<Vec<&'static str>>>) {
+        for _ in 0..count {
+            let log = Arc::clone(log);
+            scheduler.submit_for_tenant(tenant, move || log.lock().unwrap().push(tenant)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_heavy_tenant_cannot_starve_equal_weight_tenant() {
+        let scheduler = simulated_with_tenants(2, &[("bulk", 1), ("interactive", 1)]);
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        // Ten times the work, all of it queued first
+        submit_logged(&scheduler, "bulk", 200, &log);
+        submit_logged(&scheduler, "interactive", 20, &log);
+        assert!(scheduler.run_until_idle());
+
+        let log = log.lock().unwrap();
+        assert_eq!(log.len(), 220);
+        // Alternating runs would finish it by position 40; each worker may
+        // also be working through a batch of up to 16 taken from the injector.
+        // In submission order it would have waited for all 200 bulk tasks
+        let last_interactive = log.iter().rposition(|tenant| *tenant == "interactive").unwrap();
+        assert!(last_interactive < 40 + 2 * 16, "interactive work finished at position {}", last_interactive);
+    }
+
+    #[test]
+    fn test_tenants_share_in_proportion_to_weight() {
+        let scheduler = simulated_with_tenants(1, &[("a", 3), ("b", 1)]);
+        let log = Arc::new(Mutex::new(Vec::new()));
+        submit_logged(&scheduler, "a", 60, &log);
+        submit_logged(&scheduler, "b", 60, &log);
+        assert!(scheduler.run_until_idle());
+
+        let log = log.lock().unwrap();
+        let first_a = log[..40].iter().filter(|tenant| **tenant == "a").count();
+        assert_eq!(first_a, 30);
+    }
+
+    #[test]
+    fn test_tenant_stats_and_unknown_tenant() {
+        let scheduler = simulated_with_tenants(2, &[("a", 2), ("b", 1)]);
+        assert!(matches!(scheduler.submit_for_tenant("c", || {}), Err(SchedulerError::UnknownTenant)));
+
+        let log = Arc::new(Mutex::new(Vec::new()));
+        submit_logged(&scheduler, "a", 4, &log);
+        submit_logged(&scheduler, "b", 3, &log);
+        scheduler.submit(|| {}).unwrap();
+
+        let queued: Vec<(String, usize)> =
+            scheduler.stats().tenants.into_iter().map(|tenant| (tenant.tenant, tenant.queued)).collect();
+        assert_eq!(queued, vec![("a".to_string(), 4), ("b".to_string(), 3)]);
+
+        assert!(scheduler.run_until_idle());
+        let stats = scheduler.stats();
+        assert_eq!(stats.tenants[0].weight, 2);
+        assert_eq!((stats.tenants[0].submitted, stats.tenants[0].executed), (4, 4));
+        assert_eq!((stats.tenants[1].submitted, stats.tenants[1].executed), (3, 3));
+        assert_eq!(stats.tenants[1].queue_wait.count, 3);
+        assert!(stats.to_json().contains("\"tenants\":[{\"tenant\":\"a\",\"weight\":2,\"submitted\":4,"));
+    }
+}
@@ -0,0 +1,237 @@
+use std::cell::RefCell;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::simulation::Clock;
+
+/// A waker to call once `deadline` passes
+struct TimerEntry {
+    deadline: Instant,
+    waker: Waker,
+}
+
+impl PartialEq for TimerEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline == other.deadline
+    }
+}
+
+impl Eq for TimerEntry {}
+
+impl PartialOrd for TimerEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TimerEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.deadline.cmp(&other.deadline)
+    }
+}
+
+/// Wakers waiting on a deadline, earliest first
+#[derive(Default)]
+pub(super) struct TimerHeap {
+    entries: Mutex<BinaryHeap<Reverse<TimerEntry>>>,
+}
+
+impl TimerHeap {
+    fn lock(&self) -> MutexGuard<'_, BinaryHeap<Reverse<TimerEntry>>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The earliest deadline anything waits for
+    pub(super) fn next_deadline(&self) -> Option<Instant> {
+        self.lock().peek().map(|Reverse(first)| first.deadline)
+    }
+
+    /// Call the wakers whose deadline is at or before `now`
+    pub(super) fn wake_due(&self, now: Instant) {
+        let mut due = Vec::new();
+        let mut entries = self.lock();
+        while entries.peek().is_some_and(|Reverse(first)| first.deadline <= now) {
+            if let Some(Reverse(entry)) = entries.pop() {
+                due.push(entry.waker);
+            }
+        }
+        // Wakers may re-register, so call them without the lock
+        drop(entries);
+        due.into_iter().for_each(Waker::wake);
+    }
+}
+
+/// The timers of a simulated scheduler, which fire as its virtual clock is advanced
+#[derive(Clone)]
+pub(super) struct VirtualTimers {
+    pub(super) clock: Clock,
+    pub(super) heap: Arc<TimerHeap>,
+}
+
+thread_local! {
+    /// Set while a simulated worker takes a step, so `Sleep` waits on its virtual clock
+    static VIRTUAL_TIMERS: RefCell<Option<VirtualTimers>> = const { RefCell::new(None) };
+}
+
+/// Make `Sleep` futures first polled on this thread use `timers`, until
+/// the returned previous setting is put back
+pub(super) fn enter_virtual(timers: Option<VirtualTimers>) -> Option<VirtualTimers> {
+    VIRTUAL_TIMERS.with(|current| current.replace(timers))
+}
+
+/// Process-wide timer shared by every `Sleep` on the real clock, driven by one background thread
+struct Timer {
+    entries: Mutex<BinaryHeap<Reverse<TimerEntry>>>,
+    changed: Condvar,
+}
+
+impl Timer {
+    /// The shared timer, starting its thread on first use
+    fn get() -> &'static Timer {
+        static TIMER: OnceLock<Timer> = OnceLock::new();
+        TIMER.get_or_init(|| {
+            thread::spawn(|| Timer::get().run());
+            Timer {
+                entries: Mutex::new(BinaryHeap::new()),
+                changed: Condvar::new(),
+            }
+        })
+    }
+
+    fn register(&self, deadline: Instant, waker: Waker) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let earliest = entries.peek().is_none_or(|Reverse(first)| deadline < first.deadline);
+        entries.push(Reverse(TimerEntry { deadline, waker }));
+
+        // Only a new earliest deadline shortens the thread's wait
+        if earliest {
+            self.changed.notify_one();
+        }
+    }
+
+    fn run(&self) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            let now = Instant::now();
+            let mut due = Vec::new();
+            while entries.peek().is_some_and(|Reverse(first)| first.deadline <= now) {
+                if let Some(Reverse(entry)) = entries.pop() {
+                    due.push(entry.waker);
+                }
+            }
+
+            // Wakers may re-register, so call them without the lock
+            if !due.is_empty() {
+                drop(entries);
+                due.into_iter().for_each(Waker::wake);
+                entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+                continue;
+            }
+
+            entries = match entries.peek() {
+                Some(Reverse(first)) => {
+                    let wait = first.deadline.saturating_duration_since(now);
+                    self.changed
+                        .wait_timeout(entries, wait)
+                        .map(|(guard, _)| guard)
+                        .unwrap_or_else(|e| e.into_inner().0)
+                }
+                None => self.changed.wait(entries).unwrap_or_else(|e| e.into_inner()),
+            };
+        }
+    }
+}
+
+/// The clock a `Sleep` waits on, chosen when it is first polled
+enum SleepClock {
+    Unpolled,
+    Real,
+    Virtual(VirtualTimers),
+}
+
+/// Future returned by `sleep`
+pub struct Sleep {
+    duration: Duration,
+    /// `None` when the sleep is too long to end before `Instant` runs out
+    deadline: Option<Instant>,
+    clock: SleepClock,
+    /// Waker last handed to the timer, so repeated polls register only once
+    registered: Option<Waker>,
+}
+
+/// Wait for `duration` without blocking the thread, on any executor
+///
+/// A `Sleep` first polled by a scheduler in simulation mode waits on its
+/// virtual clock, so `run_until_idle` skips ahead to it instead of waiting.
+/// A duration too large to add to the current time never ends.
+pub fn sleep(duration: Duration) -> Sleep {
+    Sleep {
+        duration,
+        deadline: Instant::now().checked_add(duration),
+        clock: SleepClock::Unpolled,
+        registered: None,
+    }
+}
+
+impl Sleep {
+    /// When the sleep ends, on the clock it waits on; until the first poll
+    /// this is on the real clock; `None` if it never ends
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+}
+
+impl fmt::Debug for Sleep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sleep")
+            .field("deadline", &self.deadline)
+            .field("simulated", &matches!(self.clock, SleepClock::Virtual(_)))
+            .finish()
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if matches!(self.clock, SleepClock::Unpolled) {
+            self.clock = match VIRTUAL_TIMERS.with(|current| current.borrow().clone()) {
+                Some(timers) => {
+                    self.deadline = timers.clock.now().checked_add(self.duration);
+                    SleepClock::Virtual(timers)
+                }
+                None => SleepClock::Real,
+            };
+        }
+
+        let Some(deadline) = self.deadline else {
+            return Poll::Pending;
+        };
+        let now = match &self.clock {
+            SleepClock::Virtual(timers) => timers.clock.now(),
+            _ => Instant::now(),
+        };
+        if now >= deadline {
+            return Poll::Ready(());
+        }
+
+        if !self.registered.as_ref().is_some_and(|waker| waker.will_wake(cx.waker())) {
+            match &self.clock {
+                SleepClock::Virtual(timers) => timers.heap.lock().push(Reverse(TimerEntry {
+                    deadline,
+                    waker: cx.waker().clone(),
+                })),
+                _ => Timer::get().register(deadline, cx.waker().clone()),
+            }
+            self.registered = Some(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
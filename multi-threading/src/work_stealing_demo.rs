@@ -1,6 +1,6 @@
 use std::time::Duration;
 use std::thread;
-use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use crate::task_scheduler::{TaskScheduler, SchedulerConfig};
 
@@ -12,38 +12,38 @@ pub fn demonstrate_work_stealing() {
         num_workers: 4,
         timeout_seconds: 5,
         enable_work_stealing: true,
+        ..SchedulerConfig::default()
     };
 
     let mut scheduler = TaskScheduler::new(config);
     scheduler.start();
 
-    let counter = Arc::new(AtomicUsize::new(0));
+    let counter = AtomicUsize::new(0);
 
     println!("Submitting 50 tasks to demonstrate work stealing across 4 workers...");
 
-    // Submit many small tasks that will be distributed via work stealing
-    for i in 0..50 {
-        let counter_clone = Arc::clone(&counter);
-        
-        scheduler.submit(move || {
-            // Simulate some work
-            thread::sleep(Duration::from_millis(10));
-            
-            counter_clone.fetch_add(1, Ordering::SeqCst);
-            if i % 10 == 0 {
-                println!("  Task {} completed", i);
-            }
-        }).unwrap();
-    }
-
-    // Wait for all tasks to complete
-    while counter.load(Ordering::SeqCst) < 50 {
-        thread::sleep(Duration::from_millis(10));
-    }
-
-    println!("All 50 tasks completed! Work stealing ensured efficient distribution.");
+    // Submit many small tasks that will be distributed via work stealing;
+    // the scope returns once all of them have run, so they can borrow `counter`
+    scheduler.scope(|s| {
+        for i in 0..50 {
+            let counter = &counter;
+
+            s.spawn(move || {
+                // Simulate some work
+                thread::sleep(Duration::from_millis(10));
+
+                counter.fetch_add(1, Ordering::SeqCst);
+                if i % 10 == 0 {
+                    println!("  Task {} completed", i);
+                }
+            }).unwrap();
+        }
+    });
+
+    println!("All {} tasks completed! Work stealing ensured efficient distribution.", counter.load(Ordering::SeqCst));
     println!("Note: With work stealing enabled, idle workers steal tasks from busy workers,");
     println!("      ensuring optimal CPU utilization and minimal task starvation.");
+    println!("\nScheduler stats:\n{}", scheduler.stats());
 
     scheduler.shutdown();
     println!("=== Work Stealing Demonstration Completed ===\n");